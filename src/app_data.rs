@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use request::Request;
+use response::Response;
+use middleware::{Continue, Middleware, MiddlewareResult};
+
+// The key of the application data in the map of the request
+pub struct AppData<T> {
+    pub data: Arc<T>
+}
+
+// Hands the application data to every request, see `Nickel::with_data`
+pub struct AppDataMiddleware<T> {
+    data: Arc<T>
+}
+
+impl<T: Send + Sync + 'static> AppDataMiddleware<T> {
+    pub fn new(data: T) -> AppDataMiddleware<T> {
+        AppDataMiddleware { data: Arc::new(data) }
+    }
+}
+
+impl<T: Send + Sync + 'static> Middleware for AppDataMiddleware<T> {
+    fn invoke(&self, req: &mut Request, _res: &mut Response) -> MiddlewareResult {
+        req.map.insert(AppData { data: self.data.clone() });
+        Ok(Continue)
+    }
+}
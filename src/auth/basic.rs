@@ -0,0 +1,114 @@
+use std::str;
+use std::ascii::AsciiExt;
+use serialize::base64::FromBase64;
+use http::status::Unauthorized;
+
+use request::Request;
+use response::Response;
+use middleware::{Continue, Middleware, MiddlewareResult};
+use nickel_error::{NickelError, ErrorWithStatusCode};
+use header_utils;
+use super::set_authenticated_user;
+
+/// Verifies the credentials of a user.
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, user: &str, password: &str) -> bool;
+}
+
+impl Authenticator for fn(&str, &str) -> bool {
+    fn authenticate(&self, user: &str, password: &str) -> bool {
+        (*self)(user, password)
+    }
+}
+
+/// A middleware requiring HTTP Basic authentication for all requests which
+/// reach it. Requests without valid credentials fail with a
+/// `401 Unauthorized` error, which asks the browser for credentials. The name
+/// of the authenticated user is available through `Authenticated`.
+///
+/// The credentials are sent in plain text, so only use this over HTTPS.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, Request, Response, HttpRouter, BasicAuth, Authenticated};
+///
+/// fn check(user: &str, password: &str) -> bool {
+///     user == "admin" && password == "secret"
+/// }
+///
+/// fn handler(request: &Request, response: &mut Response) {
+///     response.send(format!("Hello {}", request.authenticated_user().unwrap()));
+/// }
+///
+/// let mut server = Nickel::new();
+/// server.utilize(BasicAuth::new("Admin area", check));
+/// server.get("/admin", handler);
+/// ```
+pub struct BasicAuth<A> {
+    realm: String,
+    authenticator: A
+}
+
+impl<A: Authenticator> BasicAuth<A> {
+    pub fn new(realm: &str, authenticator: A) -> BasicAuth<A> {
+        BasicAuth {
+            realm: realm.to_string(),
+            authenticator: authenticator
+        }
+    }
+}
+
+impl<A: Authenticator> Middleware for BasicAuth<A> {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        let credentials = header_utils::find(&req.origin.headers, "Authorization")
+                              .and_then(|header| parse_credentials(header.as_slice()));
+
+        match credentials {
+            Some((ref user, ref password)) if self.authenticator.authenticate(user.as_slice(),
+                                                                               password.as_slice()) => {
+                set_authenticated_user(req, user.clone());
+                Ok(Continue)
+            },
+            _ => {
+                let challenge = format!("Basic realm=\"{}\"", self.realm.replace("\"", "\\\""));
+                res.origin.headers.extensions.insert("WWW-Authenticate".to_string(), challenge);
+                Err(NickelError::new("Unauthorized", ErrorWithStatusCode(Unauthorized)))
+            }
+        }
+    }
+}
+
+// Parses the user and password of a Basic Authorization header
+fn parse_credentials(header: &str) -> Option<(String, String)> {
+    let header = header.trim();
+    if header.len() < 6 || !header.slice_to(6).eq_ignore_ascii_case("basic ") {
+        return None
+    }
+
+    let decoded = match header.slice_from(6).trim().from_base64() {
+        Ok(decoded) => decoded,
+        Err(_) => return None
+    };
+    let decoded = match str::from_utf8(decoded.as_slice()) {
+        Some(decoded) => decoded,
+        None => return None
+    };
+
+    decoded.find(':').map(|colon| {
+        (decoded.slice_to(colon).to_string(), decoded.slice_from(colon + 1).to_string())
+    })
+}
+
+#[test]
+fn parses_basic_credentials() {
+    // "Aladdin:open sesame" from RFC 2617
+    assert_eq!(parse_credentials("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="),
+               Some(("Aladdin".to_string(), "open sesame".to_string())));
+    // passwords may contain colons
+    assert_eq!(parse_credentials("basic dXNlcjpwYTpzcw=="),
+               Some(("user".to_string(), "pa:ss".to_string())));
+    assert_eq!(parse_credentials("Bearer QWxhZGRpbjpvcGVuIHNlc2FtZQ=="), None);
+    assert_eq!(parse_credentials("Basic not base64!"), None);
+    // no colon
+    assert_eq!(parse_credentials("Basic dXNlcg=="), None);
+}
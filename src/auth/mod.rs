@@ -0,0 +1,28 @@
+use request::Request;
+
+pub use self::basic::{BasicAuth, Authenticator};
+pub use self::token::{TokenAuth, TokenValidator};
+
+mod basic;
+mod token;
+
+// The principal authenticated by one of the auth middlewares
+struct AuthenticatedUser {
+    name: String
+}
+
+pub trait Authenticated {
+    /// Returns the user authenticated by one of the auth middlewares, like
+    /// `BasicAuth` or `TokenAuth`, if any.
+    fn authenticated_user(&self) -> Option<&str>;
+}
+
+impl<'a, 'b> Authenticated for Request<'a, 'b> {
+    fn authenticated_user(&self) -> Option<&str> {
+        self.map.get::<AuthenticatedUser>().map(|user| user.name.as_slice())
+    }
+}
+
+fn set_authenticated_user(req: &mut Request, name: String) {
+    req.map.insert(AuthenticatedUser { name: name });
+}
@@ -0,0 +1,142 @@
+use std::ascii::AsciiExt;
+use std::collections::HashMap;
+use http::status::{Unauthorized, Forbidden};
+
+use request::Request;
+use response::Response;
+use middleware::{Continue, Middleware, MiddlewareResult};
+use nickel_error::{NickelError, ErrorWithStatusCode};
+use query_string::QueryString;
+use header_utils;
+use super::set_authenticated_user;
+
+/// Resolves a token to the principal it belongs to.
+pub trait TokenValidator: Send + Sync {
+    fn validate(&self, token: &str) -> Option<String>;
+}
+
+impl TokenValidator for fn(&str) -> Option<String> {
+    fn validate(&self, token: &str) -> Option<String> {
+        (*self)(token)
+    }
+}
+
+/// A static set of tokens, mapping each of them to its principal.
+impl TokenValidator for HashMap<String, String> {
+    fn validate(&self, token: &str) -> Option<String> {
+        self.get(token).map(|principal| principal.clone())
+    }
+}
+
+enum TokenSource {
+    Bearer,
+    Header(String),
+    Query(String)
+}
+
+/// A middleware requiring a token, like an API key, for all requests which
+/// reach it. By default the token is taken from an `Authorization: Bearer`
+/// header. Requests without a token fail with a `401 Unauthorized` error,
+/// requests with an invalid token with a `403 Forbidden` error. The principal
+/// the token belongs to is available through `Authenticated`.
+///
+/// # Example
+/// ```{rust}
+/// use std::collections::HashMap;
+/// use nickel::{Nickel, TokenAuth};
+///
+/// let mut keys = HashMap::new();
+/// keys.insert("0b79bab50daca910b000d4f1a2b675d604257e42".to_string(), "reporting".to_string());
+///
+/// let mut server = Nickel::new();
+/// server.utilize(TokenAuth::new(keys).from_header("X-Api-Key"));
+/// ```
+pub struct TokenAuth<V> {
+    source: TokenSource,
+    validator: V
+}
+
+impl<V: TokenValidator> TokenAuth<V> {
+    pub fn new(validator: V) -> TokenAuth<V> {
+        TokenAuth {
+            source: TokenSource::Bearer,
+            validator: validator
+        }
+    }
+
+    /// Takes the token from the header with the given name instead.
+    pub fn from_header(mut self, name: &str) -> TokenAuth<V> {
+        self.source = TokenSource::Header(name.to_string());
+        self
+    }
+
+    /// Takes the token from the query string parameter with the given name
+    /// instead. Keep in mind that URLs tend to end up in logs.
+    pub fn from_query(mut self, name: &str) -> TokenAuth<V> {
+        self.source = TokenSource::Query(name.to_string());
+        self
+    }
+
+    fn token(&self, req: &Request) -> Option<String> {
+        let token = match self.source {
+            TokenSource::Bearer => header_utils::find(&req.origin.headers, "Authorization")
+                                       .and_then(|header| parse_bearer(header.as_slice())),
+            TokenSource::Header(ref name) => header_utils::find(&req.origin.headers, name.as_slice()),
+            TokenSource::Query(ref name) => req.query_param(name.as_slice())
+        };
+        token.map(|token| token.as_slice().trim().to_string())
+             .and_then(|token| if token.is_empty() { None } else { Some(token) })
+    }
+}
+
+impl<V: TokenValidator> Middleware for TokenAuth<V> {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        let token = match self.token(req) {
+            Some(token) => token,
+            None => {
+                match self.source {
+                    TokenSource::Bearer => {
+                        res.origin.headers.extensions.insert("WWW-Authenticate".to_string(),
+                                                             "Bearer".to_string());
+                    },
+                    _ => {}
+                }
+                return Err(NickelError::new("Unauthorized", ErrorWithStatusCode(Unauthorized)))
+            }
+        };
+
+        match self.validator.validate(token.as_slice()) {
+            Some(principal) => {
+                set_authenticated_user(req, principal);
+                Ok(Continue)
+            },
+            None => Err(NickelError::new("Forbidden", ErrorWithStatusCode(Forbidden)))
+        }
+    }
+}
+
+fn parse_bearer(header: &str) -> Option<String> {
+    let header = header.trim();
+    if header.len() > 7 && header.slice_to(7).eq_ignore_ascii_case("bearer ") {
+        Some(header.slice_from(7).to_string())
+    } else {
+        None
+    }
+}
+
+#[test]
+fn parses_bearer_tokens() {
+    assert_eq!(parse_bearer("Bearer mF_9.B5f-4.1JqM"), Some("mF_9.B5f-4.1JqM".to_string()));
+    assert_eq!(parse_bearer("bearer abc"), Some("abc".to_string()));
+    assert_eq!(parse_bearer("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="), None);
+    assert_eq!(parse_bearer("Bearer"), None);
+}
+
+#[test]
+fn validates_tokens_from_a_map() {
+    let mut tokens = HashMap::new();
+    tokens.insert("secret".to_string(), "client".to_string());
+
+    assert_eq!(tokens.validate("secret"), Some("client".to_string()));
+    assert_eq!(tokens.validate("guess"), None);
+}
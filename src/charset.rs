@@ -0,0 +1,46 @@
+use std::ascii::AsciiExt;
+use std::str;
+
+/// Why a body couldn't be decoded as text.
+#[deriving(Clone, PartialEq, Show)]
+pub enum DecodeError {
+    /// The charset isn't one nickel knows.
+    UnsupportedCharset(String),
+    /// The body isn't valid in the charset.
+    InvalidEncoding(String)
+}
+
+// Decodes text in one of the charsets spoken on the web. Only UTF-8, ASCII
+// and Latin-1 are supported, anything else gets rejected rather than
+// garbled.
+pub fn decode(bytes: &[u8], charset: &str) -> Result<String, DecodeError> {
+    let charset = charset.trim().to_ascii_lower();
+    match charset.as_slice() {
+        "utf-8" | "utf8" => match str::from_utf8(bytes) {
+            // a byte order mark isn't part of the text
+            Some(text) => Ok(text.trim_left_chars('\uFEFF').to_string()),
+            None => Err(DecodeError::InvalidEncoding(charset))
+        },
+        "us-ascii" | "ascii" => {
+            if bytes.iter().all(|&b| b < 0x80) {
+                Ok(bytes.iter().map(|&b| b as char).collect())
+            } else {
+                Err(DecodeError::InvalidEncoding(charset))
+            }
+        },
+        // every byte is the code point of the same value
+        "iso-8859-1" | "latin1" | "latin-1" | "l1" => Ok(bytes.iter().map(|&b| b as char).collect()),
+        _ => Err(DecodeError::UnsupportedCharset(charset))
+    }
+}
+
+#[test]
+fn decodes_charsets() {
+    assert_eq!(decode("grüß".as_bytes(), "UTF-8"), Ok("grüß".to_string()));
+    assert_eq!(decode(b"\xEF\xBB\xBFhi", "utf-8"), Ok("hi".to_string()));
+    assert_eq!(decode(b"gr\xFC\xDF", "utf-8"), Err(DecodeError::InvalidEncoding("utf-8".to_string())));
+    assert_eq!(decode(b"gr\xFC\xDF", "ISO-8859-1"), Ok("grüß".to_string()));
+    assert_eq!(decode(b"hi", "us-ascii"), Ok("hi".to_string()));
+    assert_eq!(decode(b"h\xE9", "us-ascii"), Err(DecodeError::InvalidEncoding("us-ascii".to_string())));
+    assert_eq!(decode(b"hi", "koi8-r"), Err(DecodeError::UnsupportedCharset("koi8-r".to_string())));
+}
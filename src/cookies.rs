@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use url::percent_encoding::percent_decode;
+use time;
+use time::Tm;
+
+pub use self::SameSite::{Strict, Lax, Unrestricted};
+
+/// The value of the SameSite attribute of a cookie.
+#[deriving(Clone, PartialEq, Show)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent as `SameSite=None`
+    Unrestricted
+}
+
+/// The attributes of a cookie set with `Response::set_cookie`.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::cookies::{CookieOptions, Lax};
+///
+/// let mut options = CookieOptions::new();
+/// options.path = Some("/".to_string());
+/// options.max_age = Some(3600);
+/// options.http_only = true;
+/// options.same_site = Some(Lax);
+/// ```
+#[deriving(Clone)]
+pub struct CookieOptions {
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub expires: Option<Tm>,
+    /// The lifetime of the cookie in seconds
+    pub max_age: Option<i64>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>
+}
+
+impl CookieOptions {
+    /// Creates options without any attributes, i.e. a session cookie
+    /// for the current path.
+    pub fn new() -> CookieOptions {
+        CookieOptions {
+            path: None,
+            domain: None,
+            expires: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None
+        }
+    }
+}
+
+/// Serializes a cookie into the value of a `Set-Cookie` header. Characters
+/// which aren't allowed in cookie names or values are percent-encoded.
+pub fn serialize(name: &str, value: &str, options: &CookieOptions) -> String {
+    let mut cookie = format!("{}={}", encode(name), encode(value));
+
+    match options.path {
+        Some(ref path) => cookie.push_str(format!("; Path={}", path).as_slice()),
+        None => {}
+    }
+    match options.domain {
+        Some(ref domain) => cookie.push_str(format!("; Domain={}", domain).as_slice()),
+        None => {}
+    }
+    match options.expires {
+        Some(ref expires) => {
+            let expires = time::strftime("%a, %d %b %Y %H:%M:%S GMT", &expires.to_utc());
+            cookie.push_str(format!("; Expires={}", expires).as_slice());
+        },
+        None => {}
+    }
+    match options.max_age {
+        Some(max_age) => cookie.push_str(format!("; Max-Age={}", max_age).as_slice()),
+        None => {}
+    }
+    if options.secure {
+        cookie.push_str("; Secure");
+    }
+    if options.http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    match options.same_site {
+        Some(Strict) => cookie.push_str("; SameSite=Strict"),
+        Some(Lax) => cookie.push_str("; SameSite=Lax"),
+        Some(Unrestricted) => cookie.push_str("; SameSite=None"),
+        None => {}
+    }
+
+    cookie
+}
+
+// Percent-encodes everything that isn't a cookie-octet as of RFC 6265
+// (also covering '%' itself to keep the encoding unambiguous).
+fn encode(s: &str) -> String {
+    let mut encoded = String::new();
+    for &byte in s.as_bytes().iter() {
+        match byte {
+            0x21 | 0x23 ... 0x24 | 0x26 ... 0x2B | 0x2D ... 0x3A |
+            0x3C ... 0x5B | 0x5D ... 0x7E => encoded.push(byte as char),
+            _ => encoded.push_str(format!("%{:02X}", byte).as_slice())
+        }
+    }
+    encoded
+}
+
+// Values which don't decode to UTF-8 are kept as they are
+fn decode(s: &str) -> String {
+    String::from_utf8(percent_decode(s.as_bytes())).unwrap_or(s.to_string())
+}
+
+/// Parses the value of a `Cookie` header into a map of cookie names and values.
+/// Surrounding quotes of the values are removed and names and values are
+/// percent-decoded, like `serialize` encodes them. If the same name occurs
+/// more than once, the first occurrence wins as it's the most specific one.
+pub fn parse(header: &str) -> HashMap<String, String> {
+    let mut cookies = HashMap::new();
+
+    for pair in header.split(';') {
+        let mut parts = pair.splitn(1, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let value = match parts.next() {
+            Some(value) => value.trim(),
+            // Not a name=value pair
+            None => continue
+        };
+
+        let name = decode(name);
+        if name.is_empty() || cookies.contains_key(&name) {
+            continue
+        }
+
+        let value = if value.len() > 1 && value.starts_with("\"") && value.ends_with("\"") {
+            value.slice(1, value.len() - 1)
+        } else {
+            value
+        };
+
+        cookies.insert(name, decode(value));
+    }
+
+    cookies
+}
+
+#[test]
+fn parses_cookie_header() {
+    let cookies = parse("session=abc123; theme=\"dark\";lang=en");
+
+    assert_eq!(cookies.len(), 3);
+    assert_eq!(cookies["session".to_string()], "abc123".to_string());
+    assert_eq!(cookies["theme".to_string()], "dark".to_string());
+    assert_eq!(cookies["lang".to_string()], "en".to_string());
+}
+
+#[test]
+fn ignores_malformed_cookie_pairs() {
+    let cookies = parse("foo; =bar; token=a=b; token=second; ");
+
+    assert_eq!(cookies.len(), 1);
+    assert_eq!(cookies["token".to_string()], "a=b".to_string());
+}
+
+#[test]
+fn serializes_cookie_with_attributes() {
+    let mut options = CookieOptions::new();
+    options.path = Some("/".to_string());
+    options.domain = Some("example.com".to_string());
+    options.max_age = Some(3600);
+    options.secure = true;
+    options.http_only = true;
+    options.same_site = Some(Strict);
+
+    assert_eq!(serialize("session", "abc123", &options).as_slice(),
+               "session=abc123; Path=/; Domain=example.com; Max-Age=3600; \
+                Secure; HttpOnly; SameSite=Strict");
+}
+
+#[test]
+fn serializes_expires_as_http_date() {
+    let mut options = CookieOptions::new();
+    options.expires = Some(time::at_utc(time::Timespec::new(0, 0)));
+
+    assert_eq!(serialize("a", "b", &options).as_slice(),
+               "a=b; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+}
+
+#[test]
+fn encodes_invalid_cookie_characters() {
+    let options = CookieOptions::new();
+
+    assert_eq!(serialize("name", "hello world;\r\n", &options).as_slice(),
+               "name=hello%20world%3B%0D%0A");
+}
+
+#[test]
+fn parses_serialized_cookies() {
+    let cookie = serialize("a b", "x y;100%", &CookieOptions::new());
+    let cookies = parse(cookie.as_slice());
+
+    assert_eq!(cookies["a b".to_string()], "x y;100%".to_string());
+}
@@ -0,0 +1,266 @@
+use std::ascii::AsciiExt;
+use std::collections::TreeMap;
+use http::method::{Method, Get, Head, Post, Put, Patch, Delete, Options};
+use http::status::NoContent;
+use regex::{Regex, quote};
+
+use request::Request;
+use response::Response;
+use middleware::{Continue, Halt, Middleware, MiddlewareResult};
+use header_utils;
+
+/// A middleware adding the headers for Cross-Origin Resource Sharing, so
+/// browsers let pages of other origins call the application. Preflight
+/// requests, i.e. `OPTIONS` requests carrying an
+/// `Access-Control-Request-Method` header, are answered right away.
+///
+/// Requests from origins which aren't allowed are passed on without any CORS
+/// headers, which makes the browser block the response.
+///
+/// # Example
+/// ```{rust}
+/// # extern crate http;
+/// # extern crate nickel;
+/// # fn main() {
+/// use nickel::{Nickel, Cors};
+/// use http::method::{Get, Post};
+///
+/// let cors = Cors::new()
+///     .allow_origin("https://example.com")
+///     .allow_origin("https://*.example.com")
+///     .allow_methods(vec![Get, Post])
+///     .allow_headers(vec!["Content-Type", "X-Api-Key"])
+///     .allow_credentials(true)
+///     .max_age(3600);
+///
+/// let mut server = Nickel::new();
+/// server.utilize(cors);
+/// # }
+/// ```
+pub struct Cors {
+    any_origin: bool,
+    origins: Vec<String>,
+    patterns: Vec<Regex>,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    exposed_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<u64>
+}
+
+impl Cors {
+    /// Creates a middleware allowing all origins to use the methods GET, HEAD,
+    /// POST, PUT, PATCH and DELETE, without credentials. Allowing specific
+    /// origins restricts the access to them.
+    pub fn new() -> Cors {
+        Cors {
+            any_origin: true,
+            origins: Vec::new(),
+            patterns: Vec::new(),
+            methods: vec![Get, Head, Post, Put, Patch, Delete],
+            headers: Vec::new(),
+            exposed_headers: Vec::new(),
+            credentials: false,
+            max_age: None
+        }
+    }
+
+    /// Allows an origin, e.g. `https://example.com`. A `*` in the origin
+    /// matches any host name labels, e.g. `https://*.example.com` allows all
+    /// subdomains, and `*` on its own allows all origins again.
+    pub fn allow_origin(mut self, origin: &str) -> Cors {
+        if origin == "*" {
+            self.any_origin = true;
+            return self
+        }
+
+        self.any_origin = false;
+        if origin.contains_char('*') {
+            let parts: Vec<String> = origin.split('*').map(|part| quote(part)).collect();
+            let pattern = format!("^(?i){}$", parts.connect("[a-zA-Z0-9.-]+"));
+            self.patterns.push(Regex::new(pattern.as_slice()).unwrap());
+        } else {
+            self.origins.push(origin.to_ascii_lower());
+        }
+        self
+    }
+
+    /// Allows all origins matching the given regular expression.
+    pub fn allow_origin_regex(mut self, pattern: Regex) -> Cors {
+        self.any_origin = false;
+        self.patterns.push(pattern);
+        self
+    }
+
+    /// Sets the methods which can be used by other origins.
+    pub fn allow_methods(mut self, methods: Vec<Method>) -> Cors {
+        self.methods = methods;
+        self
+    }
+
+    /// Sets the headers other origins can send. By default all headers
+    /// requested in a preflight request are allowed.
+    pub fn allow_headers(mut self, headers: Vec<&str>) -> Cors {
+        self.headers = headers.iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    /// Sets the response headers which can be read by other origins, besides
+    /// the simple ones like Content-Type.
+    pub fn expose_headers(mut self, headers: Vec<&str>) -> Cors {
+        self.exposed_headers = headers.iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    /// Allows requests with credentials like cookies from the allowed
+    /// origins, which are sent explicitly then, as browsers reject a
+    /// wildcard. This only works with origins allowed by `allow_origin` or
+    /// `allow_origin_regex`; with all origins allowed, requests never get to
+    /// send credentials, since any page could read the responses then.
+    pub fn allow_credentials(mut self, allowed: bool) -> Cors {
+        self.credentials = allowed;
+        self
+    }
+
+    /// Sets the seconds browsers are allowed to cache the result of a
+    /// preflight request.
+    pub fn max_age(mut self, seconds: u64) -> Cors {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    fn is_allowed(&self, origin: &str) -> bool {
+        self.any_origin ||
+            self.origins.iter().any(|allowed| allowed.as_slice().eq_ignore_ascii_case(origin)) ||
+            self.patterns.iter().any(|pattern| pattern.is_match(origin))
+    }
+
+    // Reflecting any origin along with credentials would let every page
+    // make requests with the cookies of the user
+    fn credentials_allowed(&self) -> bool {
+        self.credentials && !self.any_origin
+    }
+
+    fn is_allowed_method(&self, method: &str) -> bool {
+        self.methods.iter().any(|allowed| allowed.to_string().as_slice() == method)
+    }
+}
+
+impl Middleware for Cors {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        let headers = &req.origin.headers;
+        // the response depends on the origin unless all are allowed, so
+        // shared caches must not hand it to other origins
+        if !self.any_origin {
+            add_vary(&mut res.origin.headers.extensions, "Origin");
+        }
+        let origin = match header_utils::find(headers, "Origin") {
+            Some(origin) => origin,
+            None => return Ok(Continue)
+        };
+        if !self.is_allowed(origin.as_slice()) {
+            return Ok(Continue)
+        }
+
+        let preflight_method = match req.origin.method {
+            Options => header_utils::find(headers, "Access-Control-Request-Method"),
+            _ => None
+        };
+
+        {
+            let extensions = &mut res.origin.headers.extensions;
+            if self.any_origin {
+                extensions.insert("Access-Control-Allow-Origin".to_string(), "*".to_string());
+            } else {
+                extensions.insert("Access-Control-Allow-Origin".to_string(), origin);
+            }
+            if self.credentials_allowed() {
+                extensions.insert("Access-Control-Allow-Credentials".to_string(), "true".to_string());
+            }
+
+            let method = match preflight_method {
+                Some(method) => method,
+                None => {
+                    if !self.exposed_headers.is_empty() {
+                        extensions.insert("Access-Control-Expose-Headers".to_string(),
+                                          self.exposed_headers.connect(", "));
+                    }
+                    return Ok(Continue)
+                }
+            };
+
+            // the browser fails the preflight if the method isn't listed
+            if self.is_allowed_method(method.trim()) {
+                let methods: Vec<String> = self.methods.iter().map(|method| method.to_string()).collect();
+                extensions.insert("Access-Control-Allow-Methods".to_string(), methods.connect(", "));
+
+                let allowed_headers = if self.headers.is_empty() {
+                    header_utils::find(headers, "Access-Control-Request-Headers")
+                } else {
+                    Some(self.headers.connect(", "))
+                };
+                match allowed_headers {
+                    Some(allowed_headers) => {
+                        extensions.insert("Access-Control-Allow-Headers".to_string(), allowed_headers);
+                    },
+                    None => {}
+                }
+
+                match self.max_age {
+                    Some(max_age) => {
+                        extensions.insert("Access-Control-Max-Age".to_string(), max_age.to_string());
+                    },
+                    None => {}
+                }
+            }
+        }
+
+        res.origin.status = NoContent;
+        res.origin.headers.content_length = Some(0);
+        res.start();
+        Ok(Halt)
+    }
+}
+
+// Adds a value to the Vary header, keeping the ones set already
+fn add_vary(extensions: &mut TreeMap<String, String>, value: &str) {
+    let name = "Vary".to_string();
+    let vary = match extensions.remove(&name) {
+        Some(vary) => format!("{}, {}", vary, value),
+        None => value.to_string()
+    };
+    extensions.insert(name, vary);
+}
+
+#[test]
+fn matches_allowed_origins() {
+    let cors = Cors::new().allow_origin("https://Example.com")
+                          .allow_origin("https://*.example.com");
+
+    assert!(cors.is_allowed("https://example.com"));
+    assert!(cors.is_allowed("https://api.example.com"));
+    assert!(cors.is_allowed("https://a.b.example.com"));
+    assert!(!cors.is_allowed("http://example.com"));
+    assert!(!cors.is_allowed("https://example.com.evil.org"));
+    assert!(!cors.is_allowed("https://evilexample.com"));
+
+    assert!(Cors::new().is_allowed("https://anything.org"));
+    assert!(Cors::new().allow_origin("https://example.com").allow_origin("*")
+                       .is_allowed("https://anything.org"));
+}
+
+#[test]
+fn matches_allowed_methods() {
+    let cors = Cors::new().allow_methods(vec![Get, Post]);
+    assert!(cors.is_allowed_method("POST"));
+    assert!(!cors.is_allowed_method("DELETE"));
+}
+
+#[test]
+fn allows_credentials_only_for_explicit_origins() {
+    assert!(!Cors::new().allow_credentials(true).credentials_allowed());
+    assert!(!Cors::new().allow_origin("*").allow_credentials(true).credentials_allowed());
+    assert!(Cors::new().allow_origin("https://example.com").allow_credentials(true)
+                       .credentials_allowed());
+    assert!(!Cors::new().allow_origin("https://example.com").credentials_allowed());
+}
@@ -0,0 +1,52 @@
+use time;
+use http::status::GatewayTimeout;
+
+use request::Request;
+use nickel_error::{NickelError, ErrorWithStatusCode};
+
+// The time by which the request should be answered, set by the server
+struct Deadline {
+    ns: u64
+}
+
+pub trait RequestDeadline {
+    /// Returns the milliseconds left until the request timeout set with
+    /// `Nickel::request_timeout` expires, if there is one. Long running
+    /// handlers can use it to give up in time.
+    fn time_left_ms(&self) -> Option<u64>;
+
+    /// Whether the request timeout expired.
+    fn deadline_exceeded(&self) -> bool;
+}
+
+impl<'a, 'b> RequestDeadline for Request<'a, 'b> {
+    fn time_left_ms(&self) -> Option<u64> {
+        self.map.get::<Deadline>().map(|deadline| {
+            let now = time::precise_time_ns();
+            if now >= deadline.ns { 0 } else { (deadline.ns - now) / 1_000_000 }
+        })
+    }
+
+    // compares the timestamps, the milliseconds left are rounded down to 0
+    // up to a millisecond before the deadline
+    fn deadline_exceeded(&self) -> bool {
+        match self.map.get::<Deadline>() {
+            Some(deadline) => time::precise_time_ns() >= deadline.ns,
+            None => false
+        }
+    }
+}
+
+pub fn set_timeout(req: &mut Request, timeout_ms: u64) {
+    req.map.insert(Deadline { ns: time::precise_time_ns() + timeout_ms * 1_000_000 });
+}
+
+// Fails once the deadline passed, so a timed out request doesn't run any
+// further middleware.
+pub fn check(req: &Request) -> Result<(), NickelError> {
+    if req.deadline_exceeded() {
+        Err(NickelError::new("Request timed out", ErrorWithStatusCode(GatewayTimeout)))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::{HashMap, TreeMap};
+use serialize::json::{Json, Object, ToJson};
+use http::status::Status;
+
+use request::Request;
+use response::{Response, escape_html};
+use middleware::{Halt, ErrorHandler, MiddlewareResult};
+use nickel_error::NickelError;
+
+enum Page {
+    Template(String),
+    Handler(Box<ErrorHandler + Send + Sync>)
+}
+
+/// An error handler rendering all errors consistently with pages registered
+/// per status code or per class of status codes, e.g. for all 5xx errors.
+///
+/// Pages can be templates, which get rendered with `status`, `reason` and
+/// `message` fields, or error handlers. Errors without a registered page get
+/// a plain page. Clients preferring JSON over HTML in their Accept header
+/// get a JSON object with the same fields instead, unless there's a handler
+/// registered for the error.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, ErrorPages};
+///
+/// let pages = ErrorPages::new()
+///     .template(404, "examples/assets/not_found.tpl")
+///     .class_template(5, "examples/assets/server_error.tpl");
+///
+/// let mut server = Nickel::new();
+/// server.handle_error(pages);
+/// ```
+pub struct ErrorPages {
+    codes: HashMap<u16, Page>,
+    classes: HashMap<u16, Page>
+}
+
+impl ErrorPages {
+    pub fn new() -> ErrorPages {
+        ErrorPages {
+            codes: HashMap::new(),
+            classes: HashMap::new()
+        }
+    }
+
+    /// Renders errors with the given status code with a template.
+    pub fn template(mut self, code: u16, path: &str) -> ErrorPages {
+        self.codes.insert(code, Page::Template(path.to_string()));
+        self
+    }
+
+    /// Renders errors of the given class, e.g. `4` for all 4xx errors, with
+    /// a template. Pages for specific status codes take precedence.
+    pub fn class_template(mut self, class: u16, path: &str) -> ErrorPages {
+        self.classes.insert(class, Page::Template(path.to_string()));
+        self
+    }
+
+    /// Handles errors with the given status code with an error handler.
+    pub fn handler<H: ErrorHandler>(mut self, code: u16, handler: H) -> ErrorPages {
+        self.codes.insert(code, Page::Handler(box handler as Box<ErrorHandler + Send + Sync>));
+        self
+    }
+
+    /// Handles errors of the given class, e.g. `4` for all 4xx errors, with
+    /// an error handler. Pages for specific status codes take precedence.
+    pub fn class_handler<H: ErrorHandler>(mut self, class: u16, handler: H) -> ErrorPages {
+        self.classes.insert(class, Page::Handler(box handler as Box<ErrorHandler + Send + Sync>));
+        self
+    }
+
+    fn find(&self, code: u16) -> Option<&Page> {
+        self.codes.get(&code).or_else(|| self.classes.get(&(code / 100)))
+    }
+}
+
+impl ErrorHandler for ErrorPages {
+    fn invoke(&self, err: &NickelError, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        let status = err.status();
+        res.origin.status = status.clone();
+
+        let page = self.find(status.code());
+        match page {
+            Some(&Page::Handler(ref handler)) => return handler.invoke(err, req, res),
+            _ => {}
+        }
+
+        let data = page_data(&status, err);
+        if req.preferred_of(&["text/html", "application/json"]) == Some("application/json") {
+            res.json(&data);
+            return Ok(Halt)
+        }
+
+        match page {
+            Some(&Page::Template(ref path)) => res.render_template(path.as_slice(), &data),
+            _ => {
+                let reason = escape_html(status.reason().as_slice());
+                res.send(format!("<!DOCTYPE html>\n<html><head><title>{0} {1}</title></head>\n\
+                                  <body><h1>{0} {1}</h1></body></html>\n",
+                                 status.code(), reason))
+            }
+        }
+        Ok(Halt)
+    }
+}
+
+fn page_data(status: &Status, err: &NickelError) -> Json {
+    let mut data = TreeMap::new();
+    data.insert("status".to_string(), status.code().to_json());
+    data.insert("reason".to_string(), status.reason().as_slice().to_json());
+    data.insert("message".to_string(), err.message.as_slice().to_json());
+    Object(data)
+}
@@ -0,0 +1,57 @@
+use std::io::IoResult;
+use response::Response;
+
+/// A stream of server-sent events, created with `Response::start_events`.
+/// Every event is flushed to the client as soon as it's sent.
+pub struct EventStream<'c, 'a: 'c, 'b: 'a> {
+    response: &'c mut Response<'a, 'b>
+}
+
+impl<'c, 'a, 'b> EventStream<'c, 'a, 'b> {
+    #[doc(hidden)]
+    pub fn new(response: &'c mut Response<'a, 'b>) -> EventStream<'c, 'a, 'b> {
+        EventStream { response: response }
+    }
+
+    /// Sends an event with the given name. Clients receive it in listeners
+    /// registered for that name with `addEventListener`.
+    pub fn send_event(&mut self, name: &str, data: &str) -> IoResult<()> {
+        self.response.write_chunk(format_event(Some(name), data).as_bytes())
+    }
+
+    /// Sends an unnamed event, which clients receive in `onmessage`.
+    pub fn send_data(&mut self, data: &str) -> IoResult<()> {
+        self.response.write_chunk(format_event(None, data).as_bytes())
+    }
+
+    /// Sends a comment which is ignored by clients. Sending one periodically
+    /// keeps proxies from closing an idle connection.
+    pub fn keep_alive(&mut self) -> IoResult<()> {
+        self.response.write_chunk(b": keep-alive\n\n")
+    }
+}
+
+// Every line of the data gets its own `data` field, since a newline would
+// end the field otherwise.
+fn format_event(name: Option<&str>, data: &str) -> String {
+    let mut event = String::new();
+    match name {
+        Some(name) => event.push_str(format!("event: {}\n", name).as_slice()),
+        None => {}
+    }
+    for line in data.lines_any() {
+        event.push_str(format!("data: {}\n", line).as_slice());
+    }
+    if data.is_empty() {
+        event.push_str("data: \n");
+    }
+    event.push('\n');
+    event
+}
+
+#[test]
+fn formats_events() {
+    assert_eq!(format_event(Some("update"), "42").as_slice(), "event: update\ndata: 42\n\n");
+    assert_eq!(format_event(None, "first\nsecond").as_slice(), "data: first\ndata: second\n\n");
+    assert_eq!(format_event(None, "").as_slice(), "data: \n\n");
+}
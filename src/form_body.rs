@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use request::Request;
+use urlencoded;
+
+/// Provides access to `application/x-www-form-urlencoded` request bodies as
+/// they are sent by HTML forms.
+pub trait FormBody {
+    /// Returns the fields of the form body parsed into a multimap. The field
+    /// names and values are percent-decoded and `+` is decoded to a space.
+    ///
+    /// The map is empty if the request doesn't declare a urlencoded body.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Request, Response, FormBody};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     let form = request.form();
+    ///     response.send(format!("Your tags are: {}", form.get("tag")));
+    /// }
+    /// ```
+    fn form(&self) -> HashMap<String, Vec<String>>;
+
+    /// Returns the first value of the given field of the form body.
+    fn form_value(&self, key: &str) -> Option<String>;
+}
+
+fn has_form_body(req: &Request) -> bool {
+    req.has_content_type("application/x-www-form-urlencoded")
+}
+
+impl<'a, 'b> FormBody for Request<'a, 'b> {
+    fn form(&self) -> HashMap<String, Vec<String>> {
+        if !has_form_body(self) {
+            return HashMap::new()
+        }
+
+        let body = String::from_utf8_lossy(self.body_bytes());
+        urlencoded::parse(body.as_slice())
+    }
+
+    fn form_value(&self, key: &str) -> Option<String> {
+        self.form().get(key).and_then(|values| values.as_slice().head().cloned())
+    }
+}
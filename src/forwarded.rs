@@ -0,0 +1,215 @@
+pub use self::ProxyHeaders::{XForwarded, Rfc7239};
+
+use std::ascii::AsciiExt;
+use std::io::net::ip::IpAddr;
+use http::headers::request::HeaderCollection;
+
+use request::Request;
+use header_utils;
+
+/// The headers the proxies in front of the server use to pass on the
+/// original request, see `Nickel::proxy_headers`. Only one of them is
+/// read, since proxies pass the other one on as the client sent it.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ProxyHeaders {
+    /// `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`,
+    /// which most proxies set. That's the default.
+    XForwarded,
+    /// The `Forwarded` header of RFC 7239.
+    Rfc7239
+}
+
+/// What the proxies in front of the server tell about the original request,
+/// from the `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`
+/// headers or the `Forwarded` header of RFC 7239, see
+/// `Nickel::proxy_headers`.
+///
+/// Clients can send these headers themselves, so they can only be trusted
+/// as far as they were set by trusted proxies, see `Nickel::trust_proxy`.
+/// The proxies have to append to `X-Forwarded-For` or `Forwarded` and to
+/// overwrite `X-Forwarded-Proto` and `X-Forwarded-Host`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Forwarded {
+    /// The nodes the request was forwarded for, the client first and then
+    /// the proxies in between, e.g. `192.0.2.60`, `[2001:db8::1]:4711` or
+    /// `unknown`.
+    pub forwarded_for: Vec<String>,
+    /// The interface of the first proxy the request came in at.
+    pub by: Option<String>,
+    /// The protocol the client used, e.g. `https`.
+    pub proto: Option<String>,
+    /// The Host header the client sent.
+    pub host: Option<String>
+}
+
+impl Forwarded {
+    /// Collects the forwarding information of the request from the headers
+    /// chosen with `Nickel::proxy_headers`.
+    pub fn from_request(req: &Request) -> Forwarded {
+        let proxy_headers = req.map.get::<ProxyHeaders>().map_or(XForwarded, |kind| kind.clone());
+        from_headers(&req.origin.headers, proxy_headers)
+    }
+}
+
+// Reads only the given kind of headers, as proxies pass the other kind on
+// untouched
+fn from_headers(headers: &HeaderCollection, proxy_headers: ProxyHeaders) -> Forwarded {
+    match proxy_headers {
+        Rfc7239 => parse(header_utils::find(headers, "Forwarded").unwrap_or(String::new()).as_slice()),
+        XForwarded => {
+            let first = |name: &str| {
+                header_utils::find(headers, name).and_then(|value| {
+                    value.as_slice().split(',').next().map(|value| value.trim().to_string())
+                })
+            };
+
+            Forwarded {
+                forwarded_for: header_utils::find(headers, "X-Forwarded-For").map_or(Vec::new(), |value| {
+                    value.as_slice().split(',').map(|node| node.trim().to_string())
+                                    .filter(|node| !node.is_empty()).collect()
+                }),
+                by: None,
+                proto: first("X-Forwarded-Proto").map(|proto| proto.to_ascii_lower()),
+                host: first("X-Forwarded-Host")
+            }
+        }
+    }
+}
+
+/// Parses the value of a `Forwarded` header. Every proxy adds an element,
+/// so `for` is taken from all of them, and the rest from the first one,
+/// which describes the request of the client.
+pub fn parse(header: &str) -> Forwarded {
+    let mut forwarded = Forwarded {
+        forwarded_for: Vec::new(),
+        by: None,
+        proto: None,
+        host: None
+    };
+
+    for (index, element) in split_unquoted(header, ',').iter().enumerate() {
+        for pair in split_unquoted(element.as_slice(), ';').iter() {
+            let mut parts = pair.as_slice().splitn(1, '=');
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name.trim().to_ascii_lower(), unquote(value.trim())),
+                _ => continue
+            };
+
+            match name.as_slice() {
+                "for" => forwarded.forwarded_for.push(value),
+                "by" if index == 0 => forwarded.by = Some(value),
+                "proto" if index == 0 => forwarded.proto = Some(value.to_ascii_lower()),
+                "host" if index == 0 => forwarded.host = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    forwarded
+}
+
+/// The IP address of a node of the `for` or `by` parameter, leaving out the
+/// port. Obfuscated nodes and `unknown` have none.
+pub fn node_ip(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if node.starts_with("[") {
+        return node.find(']').and_then(|end| from_str(node.slice(1, end)))
+    }
+
+    // a single colon separates the port of an IPv4 address
+    match node.find(':') {
+        Some(colon) if node.rfind(':') == Some(colon) => from_str(node.slice_to(colon)),
+        _ => from_str(node)
+    }
+}
+
+// Splits at the separator, except within quoted strings
+fn split_unquoted(value: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for c in value.chars() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(part);
+            part = String::new();
+            continue
+        }
+        part.push(c);
+    }
+    parts.push(part);
+
+    parts.into_iter().filter(|part| !part.as_slice().trim().is_empty()).collect()
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() < 2 || !value.starts_with("\"") || !value.ends_with("\"") {
+        return value.to_string()
+    }
+
+    let mut unquoted = String::new();
+    let mut escaped = false;
+    for c in value.slice(1, value.len() - 1).chars() {
+        if c == '\\' && !escaped {
+            escaped = true;
+        } else {
+            unquoted.push(c);
+            escaped = false;
+        }
+    }
+    unquoted
+}
+
+#[test]
+fn parses_forwarded_headers() {
+    let forwarded = parse("for=192.0.2.60;proto=HTTPS;by=203.0.113.43;host=\"example.com\", \
+                           for=\"[2001:db8:cafe::17]:4711\", For=unknown;proto=http");
+    assert_eq!(forwarded, Forwarded {
+        forwarded_for: vec!["192.0.2.60".to_string(), "[2001:db8:cafe::17]:4711".to_string(),
+                            "unknown".to_string()],
+        by: Some("203.0.113.43".to_string()),
+        proto: Some("https".to_string()),
+        host: Some("example.com".to_string())
+    });
+
+    let forwarded = parse("for=\"_hidden, \\\"quoted\\\"\";host=a, garbage");
+    assert_eq!(forwarded.forwarded_for, vec!["_hidden, \"quoted\"".to_string()]);
+    assert_eq!(forwarded.host, Some("a".to_string()));
+    assert_eq!(parse("").forwarded_for, vec![]);
+}
+
+#[test]
+fn takes_addresses_from_nodes() {
+    use std::io::net::ip::{Ipv4Addr, Ipv6Addr};
+
+    assert_eq!(node_ip("192.0.2.60"), Some(Ipv4Addr(192, 0, 2, 60)));
+    assert_eq!(node_ip("192.0.2.60:8080"), Some(Ipv4Addr(192, 0, 2, 60)));
+    assert_eq!(node_ip("[2001:db8::17]:4711"), Some(Ipv6Addr(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x17)));
+    assert_eq!(node_ip("2001:db8::17"), Some(Ipv6Addr(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x17)));
+    assert_eq!(node_ip("unknown"), None);
+    assert_eq!(node_ip("_hidden"), None);
+}
+
+#[test]
+fn reads_only_the_chosen_proxy_headers() {
+    let mut headers = HeaderCollection::new();
+    headers.extensions.insert("Forwarded".to_string(), "for=10.0.0.1;proto=https".to_string());
+    headers.extensions.insert("X-Forwarded-For".to_string(), "10.0.0.1, 192.0.2.60".to_string());
+
+    let forwarded = from_headers(&headers, XForwarded);
+    assert_eq!(forwarded.forwarded_for, vec!["10.0.0.1".to_string(), "192.0.2.60".to_string()]);
+    assert_eq!(forwarded.proto, None);
+
+    let forwarded = from_headers(&headers, Rfc7239);
+    assert_eq!(forwarded.forwarded_for, vec!["10.0.0.1".to_string()]);
+    assert_eq!(forwarded.proto, Some("https".to_string()));
+
+    assert_eq!(from_headers(&HeaderCollection::new(), Rfc7239).forwarded_for, vec![]);
+}
@@ -0,0 +1,57 @@
+use std::ascii::AsciiExt;
+use http::headers::HeaderEnum;
+use http::headers::request::HeaderCollection;
+use http::headers::response::HeaderCollection as ResponseHeaderCollection;
+
+// Looks up the value of the header with the given name. The lookup is case
+// insensitive and also covers the headers without a typed representation,
+// which rust-http keeps as extension headers.
+pub fn find(headers: &HeaderCollection, name: &str) -> Option<String> {
+    headers.iter()
+           .find(|header| header.header_name().as_slice().eq_ignore_ascii_case(name))
+           .map(|header| header.header_value())
+}
+
+// Removes the port from the value of a `Host` header.
+pub fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        // the colons of an IPv6 address are inside the brackets
+        Some(colon) if !host.slice_from(colon).contains_char(']') => host.slice_to(colon),
+        _ => host
+    }
+}
+
+// Returns the port of the value of a `Host` header, if it has one.
+pub fn port(host: &str) -> Option<u16> {
+    let name = strip_port(host);
+    if name.len() < host.len() {
+        from_str(host.slice_from(name.len() + 1))
+    } else {
+        None
+    }
+}
+
+// Adds a `Set-Cookie` header to the response headers.
+//
+// FIXME: rust-http keeps extension headers in a map, so there can only be
+// a single `Set-Cookie` entry. To send several cookies the additional
+// headers are appended to that entry's value, separated by line breaks.
+// The cookie itself never contains line breaks as `cookies::serialize`
+// encodes them.
+pub fn append_set_cookie(headers: &mut ResponseHeaderCollection, cookie: String) {
+    let name = "Set-Cookie".to_string();
+    let value = match headers.extensions.remove(&name) {
+        Some(existing) => format!("{}\r\nSet-Cookie: {}", existing, cookie),
+        None => cookie
+    };
+    headers.extensions.insert(name, value);
+}
+
+#[test]
+fn finds_ports_of_hosts() {
+    assert_eq!(port("example.com:8080"), Some(8080));
+    assert_eq!(port("example.com"), None);
+    assert_eq!(port("[::1]:443"), Some(443));
+    assert_eq!(port("[::1]"), None);
+    assert_eq!(port("example.com:http"), None);
+}
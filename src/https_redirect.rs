@@ -0,0 +1,134 @@
+use http::server::request::{AbsolutePath, AbsoluteUri};
+use http::status::{BadRequest, MovedPermanently};
+
+use request::Request;
+use response::Response;
+use middleware::{Continue, Halt, Middleware, MiddlewareResult};
+use nickel_error::{NickelError, ErrorWithStatusCode};
+use header_utils;
+
+/// A middleware sending clients to HTTPS. Requests over plain HTTP get a
+/// `301 Moved Permanently` redirect to the same url with `https`, responses
+/// to secure requests get a `Strict-Transport-Security` header, which makes
+/// browsers use HTTPS on their own for the next year.
+///
+/// Nickel itself doesn't speak TLS, so it has to run behind a proxy doing
+/// that. Requests count as secure if the proxy says so in the
+/// `X-Forwarded-Proto` or `Forwarded` header, see `Request::is_secure`. The
+/// proxy needs to be trusted with `Nickel::trust_proxy` for that.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, HttpsRedirect};
+///
+/// let mut server = Nickel::new();
+/// server.utilize(HttpsRedirect::new().max_age(86400).include_subdomains(true));
+/// ```
+#[deriving(Clone)]
+pub struct HttpsRedirect {
+    max_age: u64,
+    include_subdomains: bool,
+    preload: bool,
+    port: Option<u16>
+}
+
+impl HttpsRedirect {
+    pub fn new() -> HttpsRedirect {
+        HttpsRedirect {
+            max_age: 31536000,
+            include_subdomains: false,
+            preload: false,
+            port: None
+        }
+    }
+
+    /// Sets the seconds browsers should stick to HTTPS, `0` makes them
+    /// forget it.
+    pub fn max_age(mut self, seconds: u64) -> HttpsRedirect {
+        self.max_age = seconds;
+        self
+    }
+
+    /// Makes browsers use HTTPS for all subdomains, too.
+    pub fn include_subdomains(mut self, include: bool) -> HttpsRedirect {
+        self.include_subdomains = include;
+        self
+    }
+
+    /// Asks for the inclusion in the preload lists of browsers.
+    pub fn preload(mut self, preload: bool) -> HttpsRedirect {
+        self.preload = preload;
+        self
+    }
+
+    /// Sets the port HTTPS is served on, if it isn't 443.
+    pub fn port(mut self, port: u16) -> HttpsRedirect {
+        self.port = Some(port);
+        self
+    }
+
+    fn header(&self) -> String {
+        let mut header = format!("max-age={}", self.max_age);
+        if self.include_subdomains {
+            header.push_str("; includeSubDomains");
+        }
+        if self.preload {
+            header.push_str("; preload");
+        }
+        header
+    }
+
+    fn location(&self, host: &str, path: &str) -> String {
+        let host = header_utils::strip_port(host);
+        match self.port {
+            Some(port) if port != 443 => format!("https://{}:{}{}", host, port, path),
+            _ => format!("https://{}{}", host, path)
+        }
+    }
+}
+
+impl Middleware for HttpsRedirect {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        if req.is_secure() {
+            res.origin.headers.extensions.insert("Strict-Transport-Security".to_string(),
+                                                 self.header());
+            return Ok(Continue)
+        }
+
+        let host = match header_utils::find(&req.origin.headers, "Host") {
+            Some(host) => host,
+            None => return Err(NickelError::new("Missing Host header", ErrorWithStatusCode(BadRequest)))
+        };
+        let path = match req.origin.request_uri {
+            AbsolutePath(ref path) => path.clone(),
+            AbsoluteUri(ref url) => {
+                let path = url.serialize_path().unwrap_or("/".to_string());
+                match url.query {
+                    Some(ref query) => format!("{}?{}", path, query),
+                    None => path
+                }
+            },
+            _ => "/".to_string()
+        };
+
+        res.redirect_with(MovedPermanently, self.location(host.as_slice(), path.as_slice()).as_slice());
+        Ok(Halt)
+    }
+}
+
+#[test]
+fn builds_the_hsts_header() {
+    assert_eq!(HttpsRedirect::new().header().as_slice(), "max-age=31536000");
+    assert_eq!(HttpsRedirect::new().max_age(60).include_subdomains(true).preload(true).header().as_slice(),
+               "max-age=60; includeSubDomains; preload");
+}
+
+#[test]
+fn builds_https_locations() {
+    let redirect = HttpsRedirect::new();
+    assert_eq!(redirect.location("example.com:8080", "/a?b=c").as_slice(), "https://example.com/a?b=c");
+    assert_eq!(redirect.location("[::1]", "/").as_slice(), "https://[::1]/");
+    assert_eq!(redirect.location("[::1]:80", "/").as_slice(), "https://[::1]/");
+    assert_eq!(HttpsRedirect::new().port(8443).location("example.com", "/").as_slice(),
+               "https://example.com:8443/");
+}
@@ -0,0 +1,280 @@
+use std::from_str::FromStr;
+use std::io::net::ip::{IpAddr, Ipv4Addr, Ipv6Addr};
+use http::status::Forbidden;
+
+use request::Request;
+use response::Response;
+use middleware::{Continue, Middleware, MiddlewareResult};
+use nickel_error::{NickelError, ErrorWithStatusCode};
+use forwarded;
+
+/// A block of IP addresses in CIDR notation, e.g. `10.0.0.0/8` or
+/// `fd00::/8`. A plain address is a block of its own. IPv4 addresses mapped
+/// to IPv6, like `::ffff:10.0.0.1`, are treated as IPv4 addresses.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Cidr {
+    octets: Vec<u8>,
+    prefix: uint
+}
+
+impl Cidr {
+    /// Whether the address lies within this block.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let octets = to_octets(ip);
+        if octets.len() != self.octets.len() {
+            return false
+        }
+
+        let full = self.prefix / 8;
+        if octets[..full] != self.octets[..full] {
+            return false
+        }
+
+        let rest = self.prefix % 8;
+        rest == 0 || {
+            let mask = 0xFFu8 << (8 - rest);
+            octets[full] & mask == self.octets[full] & mask
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    fn from_str(s: &str) -> Option<Cidr> {
+        let mut parts = s.trim().splitn(1, '/');
+        let ip = match parts.next().and_then(|ip| from_str::<IpAddr>(ip)) {
+            Some(ip) => ip,
+            None => return None
+        };
+
+        let octets = to_octets(&ip);
+        let max = octets.len() * 8;
+        let prefix = match parts.next() {
+            Some(prefix) => match from_str::<uint>(prefix) {
+                Some(prefix) if prefix <= max => prefix,
+                _ => return None
+            },
+            None => max
+        };
+
+        Some(Cidr { octets: octets, prefix: prefix })
+    }
+}
+
+fn to_octets(ip: &IpAddr) -> Vec<u8> {
+    match *ip {
+        Ipv4Addr(a, b, c, d) => vec![a, b, c, d],
+        Ipv6Addr(0, 0, 0, 0, 0, 0xFFFF, g, h) => {
+            vec![(g >> 8) as u8, g as u8, (h >> 8) as u8, h as u8]
+        },
+        Ipv6Addr(a, b, c, d, e, f, g, h) => {
+            [a, b, c, d, e, f, g, h].iter()
+                                    .flat_map(|&part| vec![(part >> 8) as u8, part as u8].into_iter())
+                                    .collect()
+        }
+    }
+}
+
+// The address of the client behind the trusted proxies, set by the server
+pub struct ClientIp {
+    pub ip: IpAddr
+}
+
+// Marks requests from trusted proxies, whose forwarding headers count
+pub struct TrustedPeer;
+
+pub fn set_client_ip(req: &mut Request, proxies: &[Cidr]) {
+    let peer = match req.origin.remote_addr {
+        Some(addr) => addr.ip,
+        None => return
+    };
+    if proxies.iter().any(|cidr| cidr.contains(&peer)) {
+        req.map.insert(TrustedPeer);
+    }
+    let ip = resolve_client_ip(proxies, peer, req.forwarded().forwarded_for.as_slice());
+    req.map.insert(ClientIp { ip: ip });
+}
+
+// The address of the client, looking through trusted proxies
+fn resolve_client_ip(proxies: &[Cidr], peer: IpAddr, forwarded_for: &[String]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| proxies.iter().any(|cidr| cidr.contains(ip));
+    if !is_trusted(&peer) {
+        return peer
+    }
+
+    // every proxy appends the address it got the request from
+    let mut client = peer;
+    for node in forwarded_for.iter().rev() {
+        match forwarded::node_ip(node.as_slice()) {
+            Some(ip) => {
+                client = ip;
+                if !is_trusted(&client) {
+                    break
+                }
+            },
+            None => break
+        }
+    }
+    client
+}
+
+/// A middleware allowing or denying clients by their IP address. Denied
+/// clients, and clients outside of the allowed blocks if there are any, get a
+/// `403 Forbidden` error.
+///
+/// The filter looks at the address of the connected peer. Behind proxies
+/// that's the address of the proxy, so blocks of trusted proxies can be
+/// registered: for requests from them the client address is taken from the
+/// `Forwarded` or `X-Forwarded-For` header instead, skipping the trusted
+/// proxies. The header is ignored for everybody else, as clients can send
+/// anything in it. Without proxies of its own the filter uses the client
+/// address given by `Request::remote_ip`, which takes the proxies set with
+/// `Nickel::trust_proxy` into account.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, Router, HttpRouter, IpFilter};
+///
+/// let filter = IpFilter::new().allow("10.0.0.0/8")
+///                             .allow("::1")
+///                             .deny("10.13.0.0/16")
+///                             .trust_proxy("172.16.0.1");
+///
+/// let mut admin = Nickel::router();
+/// // ... admin routes
+///
+/// let mut server = Nickel::new();
+/// server.utilize(filter);
+/// server.utilize(admin);
+/// ```
+pub struct IpFilter {
+    allowed: Vec<Cidr>,
+    denied: Vec<Cidr>,
+    proxies: Vec<Cidr>
+}
+
+impl IpFilter {
+    /// Creates a filter which lets all clients through.
+    pub fn new() -> IpFilter {
+        IpFilter {
+            allowed: Vec::new(),
+            denied: Vec::new(),
+            proxies: Vec::new()
+        }
+    }
+
+    /// Allows a block of addresses, which denies all others.
+    ///
+    /// Panics if the block isn't valid CIDR notation.
+    pub fn allow(mut self, cidr: &str) -> IpFilter {
+        self.allowed.push(parse_cidr(cidr));
+        self
+    }
+
+    /// Denies a block of addresses, even if it's part of an allowed block.
+    ///
+    /// Panics if the block isn't valid CIDR notation.
+    pub fn deny(mut self, cidr: &str) -> IpFilter {
+        self.denied.push(parse_cidr(cidr));
+        self
+    }
+
+    /// Trusts the `X-Forwarded-For` header of requests from the block.
+    ///
+    /// Panics if the block isn't valid CIDR notation.
+    pub fn trust_proxy(mut self, cidr: &str) -> IpFilter {
+        self.proxies.push(parse_cidr(cidr));
+        self
+    }
+
+    fn client_ip(&self, peer: IpAddr, forwarded_for: &[String]) -> IpAddr {
+        resolve_client_ip(self.proxies.as_slice(), peer, forwarded_for)
+    }
+
+    fn is_allowed(&self, ip: &IpAddr) -> bool {
+        if self.denied.iter().any(|cidr| cidr.contains(ip)) {
+            return false
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+pub fn parse_cidr(cidr: &str) -> Cidr {
+    match from_str(cidr) {
+        Some(cidr) => cidr,
+        None => panic!("invalid CIDR block: {}", cidr)
+    }
+}
+
+impl Middleware for IpFilter {
+    fn invoke(&self, req: &mut Request, _res: &mut Response) -> MiddlewareResult {
+        let client_ip = if self.proxies.is_empty() {
+            req.remote_ip()
+        } else {
+            req.origin.remote_addr.map(|addr| {
+                self.client_ip(addr.ip, req.forwarded().forwarded_for.as_slice())
+            })
+        };
+
+        let allowed = match client_ip {
+            Some(ip) => self.is_allowed(&ip),
+            // without an address only a filter without allowed blocks passes
+            None => self.allowed.is_empty()
+        };
+
+        if allowed {
+            Ok(Continue)
+        } else {
+            Err(NickelError::new("Forbidden", ErrorWithStatusCode(Forbidden)))
+        }
+    }
+}
+
+#[test]
+fn parses_cidr_blocks() {
+    assert_eq!(from_str::<Cidr>("10.0.0.0/8"), Some(Cidr { octets: vec![10, 0, 0, 0], prefix: 8 }));
+    assert_eq!(from_str::<Cidr>("127.0.0.1"), Some(Cidr { octets: vec![127, 0, 0, 1], prefix: 32 }));
+    assert_eq!(from_str::<Cidr>("::1").map(|cidr| cidr.prefix), Some(128));
+    assert_eq!(from_str::<Cidr>("10.0.0.0/33"), None);
+    assert_eq!(from_str::<Cidr>("localhost"), None);
+}
+
+#[test]
+fn matches_addresses_in_blocks() {
+    let block = from_str::<Cidr>("192.168.16.0/20").unwrap();
+    assert!(block.contains(&Ipv4Addr(192, 168, 16, 1)));
+    assert!(block.contains(&Ipv4Addr(192, 168, 31, 255)));
+    assert!(!block.contains(&Ipv4Addr(192, 168, 32, 0)));
+    assert!(block.contains(&Ipv6Addr(0, 0, 0, 0, 0, 0xFFFF, 0xC0A8, 0x1001)));
+
+    let block = from_str::<Cidr>("fd00::/8").unwrap();
+    assert!(block.contains(&Ipv6Addr(0xFD12, 0, 0, 0, 0, 0, 0, 1)));
+    assert!(!block.contains(&Ipv6Addr(0xFE80, 0, 0, 0, 0, 0, 0, 1)));
+    assert!(!block.contains(&Ipv4Addr(253, 0, 0, 1)));
+
+    assert!(from_str::<Cidr>("0.0.0.0/0").unwrap().contains(&Ipv4Addr(8, 8, 8, 8)));
+}
+
+#[test]
+fn applies_allow_and_deny_lists() {
+    let filter = IpFilter::new().allow("10.0.0.0/8").deny("10.13.0.0/16");
+    assert!(filter.is_allowed(&Ipv4Addr(10, 1, 2, 3)));
+    assert!(!filter.is_allowed(&Ipv4Addr(10, 13, 2, 3)));
+    assert!(!filter.is_allowed(&Ipv4Addr(192, 168, 0, 1)));
+
+    let filter = IpFilter::new().deny("192.168.0.0/16");
+    assert!(filter.is_allowed(&Ipv4Addr(8, 8, 8, 8)));
+    assert!(!filter.is_allowed(&Ipv4Addr(192, 168, 0, 1)));
+}
+
+#[test]
+fn takes_client_addresses_from_trusted_proxies() {
+    let filter = IpFilter::new().trust_proxy("10.0.0.0/8");
+    let forwarded_for = vec!["203.0.113.7".to_string(), "10.0.0.2:8080".to_string()];
+    let forwarded_for = forwarded_for.as_slice();
+
+    assert_eq!(filter.client_ip(Ipv4Addr(10, 0, 0, 1), forwarded_for), Ipv4Addr(203, 0, 113, 7));
+    assert_eq!(filter.client_ip(Ipv4Addr(198, 51, 100, 1), forwarded_for), Ipv4Addr(198, 51, 100, 1));
+    assert_eq!(filter.client_ip(Ipv4Addr(10, 0, 0, 1), &[]), Ipv4Addr(10, 0, 0, 1));
+    assert_eq!(filter.client_ip(Ipv4Addr(10, 0, 0, 1), &["1.2.3.4".to_string(), "bogus".to_string()]),
+               Ipv4Addr(10, 0, 0, 1));
+}
@@ -14,39 +14,54 @@ pub struct JsonBodyParser;
 
 impl Middleware for JsonBodyParser {
     fn invoke(&self, req: &mut Request, _res: &mut Response) -> MiddlewareResult {
-        if !req.origin.body.is_empty() {
-            match json::from_str(str::from_utf8(req.origin.body.as_slice()).unwrap()) {
-                Ok(parsed) => {
-                    req.map.insert(parsed);
-                    return Ok(Continue);
-                },
-                Err(_) => {
-                    return Err(NickelError::new("Error Parsing JSON", ErrorWithStatusCode(BadRequest)));
-                }
-            }
+        // Other bodies (e.g. form submissions) are none of our business
+        if !req.body_bytes().is_empty() && has_json_body(req) {
+            let parsed = try!(parse_body(req));
+            req.map.insert(parsed);
         }
         Ok(Continue)
     }
 }
 
+fn has_json_body(req: &Request) -> bool {
+    req.has_content_type("application/json") || req.has_content_type("application/*+json")
+}
+
+fn parse_body(req: &Request) -> Result<Json, NickelError> {
+    let body = match str::from_utf8(req.body_bytes()) {
+        Some(body) => body,
+        None => return Err(NickelError::new("JSON body is not valid UTF-8",
+                                            ErrorWithStatusCode(BadRequest)))
+    };
+
+    json::from_str(body).map_err(|err| {
+        NickelError::new(format!("Error parsing JSON: {}", err),
+                         ErrorWithStatusCode(BadRequest))
+    })
+}
+
 pub trait JsonBody {
-    fn json_as<T: Decodable<Decoder,DecoderError>>(& self) -> Option<T>;
+    /// Decodes the JSON body of the request into the given type.
+    ///
+    /// Fails with a `BadRequest` error if the request doesn't declare
+    /// a JSON Content-Type or if the body can't be parsed or decoded.
+    fn json_as<T: Decodable<Decoder,DecoderError>>(& self) -> Result<T, NickelError>;
 }
 
 impl<'a, 'b> JsonBody for request::Request<'a, 'b> {
-    fn json_as<T: Decodable<Decoder, DecoderError>>(& self) -> Option<T> {
-
-        // FIXME:
-        // I think it would be smarter to not return Option<T> but rather
-        // DecodeResult<T> to not swallow valuable debugging information.
-        // I couldn't figure out how to properly do that
-
-        self.map.get::<Json>()
-                .and_then(| parsed | {
-                    match ::serialize::json::decode::<T>(parsed.to_string().as_slice()) {
-                        Ok(e) => Some(e),
-                        _ => None
-                    }
-                })
+    fn json_as<T: Decodable<Decoder, DecoderError>>(& self) -> Result<T, NickelError> {
+        // Reuse the result of the JsonBodyParser middleware if it ran
+        let parsed = match self.map.get::<Json>() {
+            Some(parsed) => parsed.clone(),
+            None if has_json_body(self) => try!(parse_body(self)),
+            None => return Err(NickelError::new("Expected a body with Content-Type: application/json",
+                                                ErrorWithStatusCode(BadRequest)))
+        };
+
+        let mut decoder = Decoder::new(parsed);
+        Decodable::decode(&mut decoder).map_err(|err: DecoderError| {
+            NickelError::new(format!("Error decoding JSON: {}", err),
+                             ErrorWithStatusCode(BadRequest))
+        })
     }
 }
@@ -2,18 +2,22 @@
 #![comment = "A expressjs inspired web framework for Rust"]
 #![license = "MIT"]
 #![crate_type = "rlib"]
-#![feature(macro_rules, phase, slicing_syntax)]
+#![feature(macro_rules, phase, slicing_syntax, unboxed_closures)]
 
 //!Nickel is supposed to be a simple and lightweight foundation for web applications written in Rust. Its API is inspired by the popular express framework for JavaScript.
 //!
 //!Some of the features are:
 //!
 //!* Easy handlers: A handler is just a function that takes a `Request` and `ResponseWriter`
+//!* Closures and boxed trait objects as handlers
 //!* Variables in routes. Just write `my/route/:someid`
 //!* Easy parameter access: `request.params.get(&"someid")`
 //!* simple wildcard routes: `/some/*/route`
 //!* double wildcard routes: `/a/**/route`
+//!* named wildcards: `/static/*file` or `/files/**path`
 //!* middleware
+//!* sessions with pluggable stores
+//!* access logs in common, combined or custom formats
 
 extern crate time;
 extern crate http;
@@ -23,6 +27,7 @@ extern crate anymap;
 extern crate url;
 extern crate mustache;
 extern crate groupable;
+extern crate "rust-crypto" as crypto;
 #[phase(plugin)]
 extern crate regex_macros;
 #[phase(plugin, link)]
@@ -30,29 +35,76 @@ extern crate log;
 
 
 pub use nickel::Nickel;
+pub use server::Listening;
 pub use request::Request;
-pub use response::Response;
+pub use response::{Response, ResponseHook, ResponseStream, ETag};
+pub use event_stream::EventStream;
 pub use middleware::{Action, Continue, Halt, Middleware, ErrorHandler, MiddlewareResult};
 pub use static_files_handler::StaticFilesHandler;
 pub use favicon_handler::FaviconHandler;
 pub use default_error_handler::DefaultErrorHandler;
+pub use error_pages::ErrorPages;
+pub use logger::{Logger, LogFormat};
+pub use request_id::{RequestIdMiddleware, RequestId};
+pub use response_time::{ResponseTime, RequestTiming};
+pub use locale::{LocaleMiddleware, RequestLocale};
+pub use cors::Cors;
+pub use ip_filter::{IpFilter, Cidr};
+pub use forwarded::{Forwarded, ProxyHeaders, XForwarded, Rfc7239};
+pub use security_headers::SecurityHeaders;
+pub use https_redirect::HttpsRedirect;
+pub use method_override::MethodOverride;
+pub use normalize_path::NormalizePath;
+pub use deadline::RequestDeadline;
+pub use auth::{Authenticated, BasicAuth, Authenticator, TokenAuth, TokenValidator};
 pub use json_body_parser::{JsonBodyParser, JsonBody};
-pub use query_string::{QueryStringParser, QueryString};
-pub use router::{Router, Route, RouteResult, RequestHandler, HttpRouter};
+pub use query_string::{QueryStringParser, QueryString, QueryStore};
+pub use form_body::FormBody;
+pub use session::{Session, SessionData, SessionStore, SessionMiddleware, SessionAccess};
+pub use session::{CookieSessionStore, MemorySessionStore};
+pub use router::{Router, Route, RouteResult, RouteGuard, RouteInfo, ParamValue, ParamError, FromParam, TrailingSlash, ShadowedRoutes, DynamicRouter, RequestHandler, HttpRouter, FnHandler};
 pub use nickel_error::{ NickelError, NickelErrorKind, ErrorWithStatusCode, UserDefinedError, Other };
 pub use mimes::get_media_type;
+pub use negotiation::QualityItem;
+pub use charset::DecodeError;
+pub use http::status;
+pub use template_engine::{TemplateEngine, CompiledTemplate, MustacheEngine};
 
 pub mod router;
 mod server;
 mod nickel;
 mod request;
 mod response;
+mod event_stream;
 mod middleware;
 mod favicon_handler;
 mod static_files_handler;
 mod json_body_parser;
 pub mod mimes;
 mod query_string;
+mod form_body;
 mod urlencoded;
 mod nickel_error;
 mod default_error_handler;
+mod error_pages;
+mod logger;
+mod request_id;
+mod response_time;
+mod locale;
+mod cors;
+mod ip_filter;
+mod forwarded;
+mod security_headers;
+mod https_redirect;
+mod method_override;
+mod normalize_path;
+mod deadline;
+mod auth;
+mod header_utils;
+mod negotiation;
+mod charset;
+mod template_engine;
+mod app_data;
+pub mod cookies;
+pub mod websocket;
+mod session;
@@ -0,0 +1,82 @@
+use request::Request;
+use response::Response;
+use middleware::{Continue, Middleware, MiddlewareResult};
+use negotiation;
+
+struct LocaleData {
+    locale: String
+}
+
+/// A middleware picking the locale of the response from the ones the
+/// application supports, by the preferences of the client in the
+/// `Accept-Language` header. Clients without the header or without any
+/// supported language get the first locale.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, Request, Response, HttpRouter, LocaleMiddleware, RequestLocale};
+///
+/// fn handler(request: &Request, response: &mut Response) {
+///     match request.locale() {
+///         Some("de") => response.send("Hallo"),
+///         _ => response.send("Hello")
+///     }
+/// }
+///
+/// let mut server = Nickel::new();
+/// server.utilize(LocaleMiddleware::new(vec!["en-US", "en-GB", "de"]));
+/// server.get("/", handler);
+/// ```
+#[deriving(Clone)]
+pub struct LocaleMiddleware {
+    supported: Vec<String>
+}
+
+impl LocaleMiddleware {
+    /// Creates the middleware for the supported locales, the first one is
+    /// the default. Panics without any locale.
+    pub fn new(supported: Vec<&str>) -> LocaleMiddleware {
+        assert!(!supported.is_empty(), "No supported locales");
+        LocaleMiddleware {
+            supported: supported.iter().map(|locale| locale.to_string()).collect()
+        }
+    }
+
+    fn pick(&self, req: &Request) -> String {
+        let accepted = req.accepted_languages();
+        let offers: Vec<&str> = self.supported.iter().map(|locale| locale.as_slice()).collect();
+        let locale = if accepted.is_empty() {
+            None
+        } else {
+            negotiation::preferred(Some(accepted), offers.as_slice(),
+                                   |accepted, tag| negotiation::language_quality(accepted, tag))
+        };
+        locale.unwrap_or(offers[0]).to_string()
+    }
+}
+
+impl Middleware for LocaleMiddleware {
+    fn invoke(&self, req: &mut Request, _res: &mut Response) -> MiddlewareResult {
+        let locale = self.pick(req);
+        req.map.insert(LocaleData { locale: locale });
+        Ok(Continue)
+    }
+}
+
+pub trait RequestLocale {
+    /// Returns the locale picked for the request, if the `LocaleMiddleware`
+    /// is in use.
+    fn locale(&self) -> Option<&str>;
+}
+
+impl<'a, 'b> RequestLocale for Request<'a, 'b> {
+    fn locale(&self) -> Option<&str> {
+        self.map.get::<LocaleData>().map(|data| data.locale.as_slice())
+    }
+}
+
+#[test]
+#[should_fail]
+fn needs_supported_locales() {
+    LocaleMiddleware::new(vec![]);
+}
@@ -0,0 +1,239 @@
+use std::collections::TreeMap;
+use std::io::stdio;
+use std::sync::{Arc, Mutex};
+use serialize::json::{Json, Object, ToJson};
+use time;
+
+use request::Request;
+use response::{Response, ResponseHook};
+use middleware::{Continue, Middleware, MiddlewareResult};
+use header_utils;
+use request_id::REQUEST_ID_HEADER;
+use response_time::RESPONSE_TIME_HEADER;
+
+/// The format of the lines written by the `Logger`.
+#[deriving(Clone)]
+pub enum LogFormat {
+    /// The Common Log Format of the Apache httpd and most other servers,
+    /// e.g. `127.0.0.1 - - [10/Oct/2014:13:55:36 +0000] "GET / HTTP/1.1" 200 2326`.
+    Common,
+    /// The Common Log Format followed by the quoted Referer and User-Agent
+    /// headers.
+    Combined,
+    /// A custom format with placeholders which get replaced by the values of
+    /// the request: `{remote_ip}`, `{time}`, `{method}`, `{path}`, `{protocol}`,
+    /// `{status}`, `{bytes}`, `{latency}` (in milliseconds), `{referer}`,
+    /// `{user_agent}`, `{request_id}`, see `RequestIdMiddleware`, and
+    /// `{response_time}`, see `ResponseTime`.
+    Custom(String),
+    /// One JSON object per request with the fields `timestamp`, `remote_ip`,
+    /// `method`, `path`, `protocol`, `status`, `bytes`, `duration_ms`,
+    /// `referer`, `user_agent` and `request_id`, which is taken from the
+    /// `X-Request-Id` header of the response or the request, see
+    /// `RequestIdMiddleware`.
+    Json
+}
+
+/// A middleware writing a line to the log for every request, once the
+/// response is complete. Register it first, so it sees all requests.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, Logger, LogFormat};
+/// use std::io::File;
+///
+/// let mut server = Nickel::new();
+/// server.utilize(Logger::stdout(LogFormat::Combined));
+///
+/// let log = File::create(&Path::new("/tmp/access.log")).unwrap();
+/// server.utilize(Logger::new(log, LogFormat::Custom("{method} {path} {status} {latency}ms".to_string())));
+/// ```
+pub struct Logger {
+    format: LogFormat,
+    writer: Arc<Mutex<Box<Writer + Send>>>
+}
+
+impl Logger {
+    /// Creates a logger writing to the given writer.
+    pub fn new<W: Writer + Send>(writer: W, format: LogFormat) -> Logger {
+        Logger {
+            format: format,
+            writer: Arc::new(Mutex::new(box writer as Box<Writer + Send>))
+        }
+    }
+
+    /// Creates a logger writing to the standard output.
+    pub fn stdout(format: LogFormat) -> Logger {
+        Logger::new(stdio::stdout_raw(), format)
+    }
+}
+
+impl Middleware for Logger {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        res.on_send(LogEntry {
+            format: self.format.clone(),
+            writer: self.writer.clone(),
+            start: time::precise_time_ns(),
+            time: time::now(),
+            remote_ip: req.remote_ip().map(|ip| ip.to_string()),
+            method: req.origin.method.to_string(),
+            path: req.origin.request_uri.to_string(),
+            protocol: format!("HTTP/{}.{}", req.origin.version.val0(), req.origin.version.val1()),
+            referer: header_utils::find(&req.origin.headers, "Referer"),
+            user_agent: header_utils::find(&req.origin.headers, "User-Agent"),
+            request_id: header_utils::find(&req.origin.headers, REQUEST_ID_HEADER),
+            response_time: None
+        });
+
+        Ok(Continue)
+    }
+}
+
+// The data of the request which needs to be kept until the response is done
+struct LogEntry {
+    format: LogFormat,
+    writer: Arc<Mutex<Box<Writer + Send>>>,
+    start: u64,
+    time: time::Tm,
+    remote_ip: Option<String>,
+    method: String,
+    path: String,
+    protocol: String,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    response_time: Option<String>
+}
+
+impl LogEntry {
+    fn line(&self, status: u16, bytes: u64, latency_ns: u64) -> String {
+        match self.format {
+            LogFormat::Json => return self.json_line(status, bytes, latency_ns),
+            _ => {}
+        }
+
+        let or_dash = |value: &Option<String>| {
+            value.as_ref().map_or("-".to_string(), |value| value.clone())
+        };
+        let bytes = if bytes == 0 { "-".to_string() } else { bytes.to_string() };
+        let time = time::strftime("%d/%b/%Y:%H:%M:%S %z", &self.time);
+
+        let common = format!("{} - - [{}] \"{} {} {}\" {} {}",
+                             or_dash(&self.remote_ip), time, self.method, self.path,
+                             self.protocol, status, bytes);
+
+        match self.format {
+            LogFormat::Common => common,
+            LogFormat::Combined => format!("{} \"{}\" \"{}\"", common,
+                                           or_dash(&self.referer), or_dash(&self.user_agent)),
+            LogFormat::Custom(ref format) => {
+                let latency = format!("{:.3}", latency_ns as f64 / 1_000_000.0);
+                let fields = [
+                    ("{remote_ip}", or_dash(&self.remote_ip)),
+                    ("{time}", time.clone()),
+                    ("{method}", self.method.clone()),
+                    ("{path}", self.path.clone()),
+                    ("{protocol}", self.protocol.clone()),
+                    ("{status}", status.to_string()),
+                    ("{bytes}", bytes.clone()),
+                    ("{latency}", latency),
+                    ("{referer}", or_dash(&self.referer)),
+                    ("{user_agent}", or_dash(&self.user_agent)),
+                    ("{request_id}", or_dash(&self.request_id)),
+                    ("{response_time}", or_dash(&self.response_time))
+                ];
+                fields.iter().fold(format.clone(), |line, &(ref name, ref value)| {
+                    line.replace(*name, value.as_slice())
+                })
+            },
+            LogFormat::Json => unreachable!()
+        }
+    }
+
+    fn json_line(&self, status: u16, bytes: u64, latency_ns: u64) -> String {
+        let timestamp = time::strftime("%Y-%m-%dT%H:%M:%SZ", &self.time.to_utc());
+
+        let mut object = TreeMap::new();
+        object.insert("timestamp".to_string(), timestamp.to_json());
+        object.insert("remote_ip".to_string(), self.remote_ip.to_json());
+        object.insert("method".to_string(), self.method.to_json());
+        object.insert("path".to_string(), self.path.to_json());
+        object.insert("protocol".to_string(), self.protocol.to_json());
+        object.insert("status".to_string(), status.to_json());
+        object.insert("bytes".to_string(), bytes.to_json());
+        object.insert("duration_ms".to_string(), (latency_ns as f64 / 1_000_000.0).to_json());
+        object.insert("referer".to_string(), self.referer.to_json());
+        object.insert("user_agent".to_string(), self.user_agent.to_json());
+        object.insert("request_id".to_string(), self.request_id.to_json());
+
+        Object(object).to_string()
+    }
+}
+
+impl ResponseHook for LogEntry {
+    fn after_send(&mut self, res: &Response) {
+        match res.origin.headers.extensions.get(REQUEST_ID_HEADER) {
+            Some(id) => self.request_id = Some(id.clone()),
+            None => {}
+        }
+        self.response_time = res.origin.headers.extensions.get(RESPONSE_TIME_HEADER)
+                                            .map(|time| time.clone());
+
+        let latency = time::precise_time_ns() - self.start;
+        let line = self.line(res.current_status().code(), res.bytes_written(), latency);
+
+        let mut writer = self.writer.lock();
+        let _ = writer.write_line(line.as_slice());
+        let _ = writer.flush();
+    }
+}
+
+#[cfg(test)]
+fn test_entry(format: LogFormat) -> LogEntry {
+    use std::io::util::NullWriter;
+
+    LogEntry {
+        format: format,
+        writer: Arc::new(Mutex::new(box NullWriter as Box<Writer + Send>)),
+        start: 0,
+        time: time::at_utc(time::Timespec::new(1415000000, 0)),
+        remote_ip: Some("127.0.0.1".to_string()),
+        method: "GET".to_string(),
+        path: "/index.html".to_string(),
+        protocol: "HTTP/1.1".to_string(),
+        referer: None,
+        user_agent: Some("curl/7.38.0".to_string()),
+        request_id: Some("abc".to_string()),
+        response_time: None
+    }
+}
+
+#[test]
+fn formats_common_and_combined_lines() {
+    assert_eq!(test_entry(LogFormat::Common).line(200, 2326, 0).as_slice(),
+               "127.0.0.1 - - [03/Nov/2014:07:33:20 +0000] \"GET /index.html HTTP/1.1\" 200 2326");
+    assert_eq!(test_entry(LogFormat::Combined).line(304, 0, 0).as_slice(),
+               "127.0.0.1 - - [03/Nov/2014:07:33:20 +0000] \"GET /index.html HTTP/1.1\" 304 - \
+                \"-\" \"curl/7.38.0\"");
+}
+
+#[test]
+fn formats_custom_lines() {
+    let entry = test_entry(LogFormat::Custom("{method} {path} {status} {bytes} {latency}ms {request_id}".to_string()));
+    assert_eq!(entry.line(404, 9, 1_500_000).as_slice(), "GET /index.html 404 9 1.500ms abc");
+}
+
+#[test]
+fn formats_json_lines() {
+    use serialize::json;
+
+    let line = test_entry(LogFormat::Json).line(200, 12, 2_000_000);
+    let object = json::from_str(line.as_slice()).unwrap();
+    let object = object.as_object().unwrap();
+
+    assert_eq!(object["timestamp".to_string()], "2014-11-03T07:33:20Z".to_json());
+    assert_eq!(object["status".to_string()], 200u16.to_json());
+    assert_eq!(object["duration_ms".to_string()], 2.0f64.to_json());
+    assert_eq!(object["referer".to_string()], Json::Null);
+    assert_eq!(object["request_id".to_string()], "abc".to_json());
+}
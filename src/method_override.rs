@@ -0,0 +1,75 @@
+use std::ascii::AsciiExt;
+use http::method::{Method, Post, Put, Patch, Delete};
+
+use request::Request;
+use response::Response;
+use middleware::{Continue, Middleware, MiddlewareResult};
+use form_body::FormBody;
+use header_utils;
+
+/// The header clients can send the method to use instead.
+pub const METHOD_OVERRIDE_HEADER: &'static str = "X-HTTP-Method-Override";
+
+/// A middleware letting POST requests be handled as PUT, PATCH or DELETE
+/// requests, as HTML forms can only send GET and POST requests. The method
+/// is taken from the `X-HTTP-Method-Override` header or the `_method` field
+/// of a urlencoded form body. It needs to be registered before the router,
+/// which routes by `Request::method`.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, Request, Response, HttpRouter, MethodOverride};
+///
+/// fn delete_handler(request: &Request, response: &mut Response) {
+///     response.send(format!("Deleted post {}", request.param("id")));
+/// }
+///
+/// // <form method="post" action="/posts/3">
+/// //   <input type="hidden" name="_method" value="DELETE">
+/// // </form>
+/// let mut server = Nickel::new();
+/// server.utilize(MethodOverride);
+/// server.delete("/posts/:id", delete_handler);
+/// ```
+#[deriving(Clone)]
+pub struct MethodOverride;
+
+impl Middleware for MethodOverride {
+    fn invoke(&self, req: &mut Request, _res: &mut Response) -> MiddlewareResult {
+        if req.origin.method != Post {
+            return Ok(Continue)
+        }
+
+        let method = header_utils::find(&req.origin.headers, METHOD_OVERRIDE_HEADER)
+                         .or_else(|| req.form_value("_method"))
+                         .and_then(|method| parse_method(method.as_slice()));
+
+        match method {
+            Some(method) => {
+                debug!("overriding POST with {}", method);
+                req.set_method(method);
+            },
+            None => {}
+        }
+        Ok(Continue)
+    }
+}
+
+// Only methods a form can't send are accepted, so a POST can't turn into a
+// GET which is supposed to be safe.
+fn parse_method(method: &str) -> Option<Method> {
+    match method.trim().to_ascii_upper().as_slice() {
+        "PUT" => Some(Put),
+        "PATCH" => Some(Patch),
+        "DELETE" => Some(Delete),
+        _ => None
+    }
+}
+
+#[test]
+fn accepts_only_unsafe_overrides() {
+    assert_eq!(parse_method("DELETE"), Some(Delete));
+    assert_eq!(parse_method(" patch "), Some(Patch));
+    assert_eq!(parse_method("GET"), None);
+    assert_eq!(parse_method("CONNECT"), None);
+}
@@ -1,6 +1,11 @@
 use request::Request;
 use response::Response;
-use nickel_error::NickelError;
+use nickel_error::{NickelError, ErrorWithStatusCode};
+use http::status::RequestEntityTooLarge;
+use deadline;
+use ip_filter;
+use ip_filter::Cidr;
+use forwarded::{ProxyHeaders, XForwarded};
 
 pub use self::Action::{Continue, Halt};
 
@@ -40,7 +45,11 @@ impl ErrorHandler for fn(&NickelError, &Request, &mut Response) -> MiddlewareRes
 
 pub struct MiddlewareStack {
     handlers: Vec<Box<Middleware + Send + Sync>>,
-    error_handlers: Vec<Box<ErrorHandler + Send + Sync>>
+    error_handlers: Vec<Box<ErrorHandler + Send + Sync>>,
+    max_body_size: Option<uint>,
+    request_timeout: Option<u64>,
+    trusted_proxies: Vec<Cidr>,
+    proxy_headers: ProxyHeaders
 }
 
 impl MiddlewareStack {
@@ -52,35 +61,135 @@ impl MiddlewareStack {
         self.error_handlers.push(box handler);
     }
 
+    pub fn set_max_body_size(&mut self, max_body_size: Option<uint>) {
+        self.max_body_size = max_body_size;
+    }
+
+    pub fn set_request_timeout(&mut self, timeout_ms: Option<u64>) {
+        self.request_timeout = timeout_ms;
+    }
+
+    pub fn add_trusted_proxy(&mut self, cidr: Cidr) {
+        self.trusted_proxies.push(cidr);
+    }
+
+    pub fn set_proxy_headers(&mut self, proxy_headers: ProxyHeaders) {
+        self.proxy_headers = proxy_headers;
+    }
+
     pub fn invoke<'a, 'b>(&'a self, req: &mut Request<'b, 'a>, res: &mut Response) {
+        req.map.insert(self.proxy_headers.clone());
+        if !self.trusted_proxies.is_empty() {
+            ip_filter::set_client_ip(req, self.trusted_proxies.as_slice());
+        }
+
+        match self.request_timeout {
+            Some(timeout_ms) => deadline::set_timeout(req, timeout_ms),
+            None => {}
+        }
+
+        match self.check_body_size(req) {
+            Err(err) => {
+                warn!("{} {} {} {}", req.origin.method, req.origin.remote_addr, req.origin.request_uri, err);
+                return self.handle_error(err, req, res)
+            },
+            Ok(()) => {}
+        }
+
         for handler in self.handlers.iter() {
-            match handler.invoke(req, res) {
+            // a timed out request isn't worth any more work, unless the
+            // response is on its way already
+            let result = match deadline::check(req) {
+                Err(err) if !res.is_started() => Err(err),
+                _ => handler.invoke(req, res)
+            };
+
+            match result {
                 Ok(Halt) => 
                 {
                     debug!("{} {} {} {}", req.origin.method, req.origin.remote_addr, req.origin.request_uri, res.origin.status);
                     return
                 }
                 Ok(Continue) => {},
-                Err(mut err) => {
-                    warn!("{} {} {} {}", req.origin.method, req.origin.remote_addr, req.origin.request_uri, err.kind);
-                    for error_handler in self.error_handlers.iter().rev() {
-                        match error_handler.invoke(&err, req, res) {
-                            Ok(Continue) => {},
-                            Ok(Halt) => return,
-                            // change the error so that other ErrorHandler
-                            // down the stack receive the new error.
-                            Err(new_err) => err = new_err,
-                        }
-                    }
+                Err(err) => {
+                    warn!("{} {} {} {}", req.origin.method, req.origin.remote_addr, req.origin.request_uri, err);
+                    return self.handle_error(err, req, res)
                 }
             }
         }
     }
 
+    // Answers requests with bodies above the limit with a 413 before any
+    // middleware or body parser gets to see them. A declared length above
+    // the limit counts, too.
+    //
+    // FIXME: rust-http reads the whole body before handing out the request,
+    // so the limit can't stop it from being read into memory.
+    fn check_body_size(&self, req: &Request) -> Result<(), NickelError> {
+        check_body_size(req.origin.headers.content_length, req.origin.body.len(),
+                        self.max_body_size)
+    }
+
+    // Passes the error through the error handlers, starting with the one
+    // registered last. The remaining middleware doesn't run.
+    fn handle_error(&self, err: NickelError, req: &mut Request, res: &mut Response) {
+        let mut err = err;
+        for error_handler in self.error_handlers.iter().rev() {
+            match error_handler.invoke(&err, req, res) {
+                Ok(Continue) => {},
+                Ok(Halt) => return,
+                // change the error so that other ErrorHandler
+                // down the stack receive the new error.
+                Err(new_err) => err = new_err,
+            }
+        }
+    }
+
     pub fn new () -> MiddlewareStack {
         MiddlewareStack{
             handlers: Vec::new(),
-            error_handlers: Vec::new()
+            error_handlers: Vec::new(),
+            max_body_size: None,
+            request_timeout: None,
+            trusted_proxies: Vec::new(),
+            proxy_headers: XForwarded
         }
     }
 }
+
+// Fails requests whose declared or actual body size exceeds the limit, if
+// there is one.
+fn check_body_size(declared: Option<uint>, actual: uint, limit: Option<uint>)
+                   -> Result<(), NickelError> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return Ok(())
+    };
+
+    if declared.unwrap_or(0) > limit || actual > limit {
+        Err(NickelError::new(format!("Request body exceeds the limit of {} bytes", limit),
+                             ErrorWithStatusCode(RequestEntityTooLarge)))
+    } else {
+        Ok(())
+    }
+}
+
+#[test]
+fn limits_body_sizes() {
+    let too_large = |result: Result<(), NickelError>| {
+        match result {
+            Err(NickelError { kind: ErrorWithStatusCode(RequestEntityTooLarge), .. }) => true,
+            _ => false
+        }
+    };
+
+    assert!(check_body_size(Some(10), 10, Some(10)).is_ok());
+    assert!(check_body_size(None, 0, Some(10)).is_ok());
+    // the declared length is rejected before the body is looked at
+    assert!(too_large(check_body_size(Some(11), 0, Some(10))));
+    // as is a body longer than declared, or without a declared length
+    assert!(too_large(check_body_size(Some(5), 11, Some(10))));
+    assert!(too_large(check_body_size(None, 11, Some(10))));
+    // without a limit anything goes
+    assert!(check_body_size(Some(100_000), 100_000, None).is_ok());
+}
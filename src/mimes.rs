@@ -1,5 +1,9 @@
 use http::headers::content_type;
+use std::ascii::AsciiExt;
+use std::collections::HashMap;
+use std::mem;
 use std::str::FromStr;
+use std::sync::{RWLock, Once, ONCE_INIT};
 
 macro_rules! mimes(
     ($($t:expr { $($name:ident, $as_s:pat, $subt:expr,)+ })+) => (
@@ -44,6 +48,63 @@ macro_rules! mimes(
     )
 )
 
+type Registry = RWLock<HashMap<String, (String, String)>>;
+
+static mut REGISTRY: *const Registry = 0 as *const Registry;
+static INIT: Once = ONCE_INIT;
+
+fn registry() -> &'static Registry {
+    unsafe {
+        INIT.doit(|| {
+            let registry: Box<Registry> = box RWLock::new(HashMap::new());
+            REGISTRY = mem::transmute(registry);
+        });
+        &*REGISTRY
+    }
+}
+
+/// Registers the media type to use for files with the given extension.
+/// Registered extensions take precedence over the built-in table, so this
+/// can also be used to override the type of a known extension.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::mimes;
+///
+/// mimes::register_extension("webmanifest", "application", "manifest+json");
+/// let media_type = mimes::from_extension("webmanifest").unwrap();
+/// assert_eq!(media_type.subtype.as_slice(), "manifest+json");
+/// ```
+pub fn register_extension(extension: &str, type_: &str, subtype: &str) {
+    let extension = extension.trim_left_chars('.').to_ascii_lower();
+    registry().write().insert(extension, (type_.to_string(), subtype.to_string()));
+}
+
+/// Looks up the media type for a file extension, e.g. `"css"`, ignoring case.
+/// Extensions registered with `register_extension` are checked before the
+/// built-in table.
+pub fn from_extension(extension: &str) -> Option<content_type::MediaType> {
+    let extension = extension.trim_left_chars('.').to_ascii_lower();
+
+    match registry().read().get(&extension) {
+        Some(&(ref type_, ref subtype)) => {
+            return Some(content_type::MediaType {
+                type_: type_.clone(),
+                subtype: subtype.clone(),
+                parameters: vec![]
+            })
+        },
+        None => {}
+    }
+
+    from_str::<MediaType>(extension.as_slice()).map(get_media_type)
+}
+
+/// Looks up the media type for the extension of the given path.
+pub fn from_path(path: &Path) -> Option<content_type::MediaType> {
+    path.extension_str().and_then(from_extension)
+}
+
 mimes!(
 
     "application" {
@@ -559,6 +620,7 @@ mimes!(
         Ttf, "ttf", "x-font-ttf",
         Pfa, "pfa", "x-font-type1",
         Woff, "woff", "font-woff",
+        Woff2, "woff2", "font-woff2",
         Arc, "arc", "x-freearc",
         Spl, "spl", "x-futuresplash",
         Gca, "gca", "x-gca-compressed",
@@ -857,3 +919,23 @@ mimes!(
 
     }
 )
+
+#[test]
+fn looks_up_extensions() {
+    let media_type = from_extension("CSS").unwrap();
+    assert_eq!(media_type.type_.as_slice(), "text");
+    assert_eq!(media_type.subtype.as_slice(), "css");
+
+    let media_type = from_path(&Path::new("fonts/icons.woff")).unwrap();
+    assert_eq!(media_type.subtype.as_slice(), "font-woff");
+
+    assert!(from_extension("nickel-unknown").is_none());
+}
+
+#[test]
+fn prefers_registered_extensions() {
+    register_extension(".nickel-tpl", "text", "x-nickel");
+    let media_type = from_extension("nickel-tpl").unwrap();
+    assert_eq!(media_type.type_.as_slice(), "text");
+    assert_eq!(media_type.subtype.as_slice(), "x-nickel");
+}
@@ -0,0 +1,197 @@
+use std::ascii::AsciiExt;
+
+/// An entry of a header like `Accept`, e.g. `text/html` in
+/// `text/html;q=0.8`, with its quality between `0` and `1`. Entries without
+/// a `q` parameter have the quality `1`, and `0` means not acceptable.
+#[deriving(Clone, PartialEq, Show)]
+pub struct QualityItem {
+    pub value: String,
+    pub quality: f32
+}
+
+/// Parses a comma separated header with quality values, like `Accept`, into
+/// its entries ordered by decreasing quality. Entries of the same quality
+/// keep the order of the header. Parameters other than `q` are left out.
+pub fn parse_quality_list(header: &str) -> Vec<QualityItem> {
+    let mut items: Vec<QualityItem> = header.split(',').filter_map(|entry| {
+        let mut params = entry.split(';').map(|part| part.trim());
+        let value = params.next().unwrap_or("");
+        if value.is_empty() {
+            return None
+        }
+
+        let quality = params.filter_map(|param| {
+            if param.starts_with("q=") || param.starts_with("Q=") {
+                from_str::<f32>(param.slice_from(2))
+            } else {
+                None
+            }
+        }).next().unwrap_or(1.0);
+
+        Some(QualityItem {
+            value: value.to_string(),
+            quality: quality.max(0.0).min(1.0)
+        })
+    }).collect();
+
+    // the sort is stable, so equally ranked entries stay in order
+    items.sort_by(|a, b| b.quality.partial_cmp(&a.quality).unwrap());
+    items
+}
+
+/// The quality the parsed `Accept` header gives the media type, e.g.
+/// `application/json`, taking the most specific matching media range.
+pub fn media_type_quality(accepted: &[QualityItem], media_type: &str) -> f32 {
+    let mut parts = media_type.splitn(1, '/');
+    let (type_, subtype) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let mut best = (0u, 0f32);
+
+    for item in accepted.iter() {
+        let mut parts = item.value.as_slice().splitn(1, '/');
+        let specificity = match (parts.next(), parts.next()) {
+            (Some("*"), Some("*")) => 1,
+            (Some(t), Some("*")) if t.eq_ignore_ascii_case(type_) => 2,
+            (Some(t), Some(s)) if t.eq_ignore_ascii_case(type_) &&
+                                  s.eq_ignore_ascii_case(subtype) => 3,
+            _ => 0
+        };
+
+        if specificity > best.val0() {
+            best = (specificity, item.quality);
+        }
+    }
+
+    best.val1()
+}
+
+/// The quality the parsed `Accept-Language` header gives the language tag,
+/// e.g. `en-US`, taking the longest matching language range. A range
+/// matches a tag if it's the same or a prefix of it ending before a `-`,
+/// so `en` matches `en-US`, too.
+pub fn language_quality(accepted: &[QualityItem], tag: &str) -> f32 {
+    let mut best = (0u, 0f32);
+
+    for item in accepted.iter() {
+        let range = item.value.as_slice();
+        let specificity = if range == "*" {
+            1
+        } else if tag.len() >= range.len() &&
+                  tag.slice_to(range.len()).eq_ignore_ascii_case(range) &&
+                  (tag.len() == range.len() || tag.char_at(range.len()) == '-') {
+            range.len() + 1
+        } else {
+            0
+        };
+
+        if specificity > best.val0() {
+            best = (specificity, item.quality);
+        }
+    }
+
+    best.val1()
+}
+
+/// The quality the parsed `Accept-Encoding` header gives the content
+/// coding, e.g. `gzip`. The `identity` coding, i.e. no compression, is
+/// acceptable unless the header excludes it, by name or with `*`.
+pub fn encoding_quality(accepted: &[QualityItem], coding: &str) -> f32 {
+    let find = |name: &str| {
+        accepted.iter().find(|item| item.value.as_slice().eq_ignore_ascii_case(name))
+                       .map(|item| item.quality)
+    };
+
+    match find(coding).or_else(|| find("*")) {
+        Some(quality) => quality,
+        None if coding.eq_ignore_ascii_case("identity") => 1.0,
+        None => 0.0
+    }
+}
+
+/// Picks the offer with the highest quality, preferring earlier offers if
+/// they're ranked the same. A missing header accepts anything, so the first
+/// offer gets picked then.
+pub fn preferred<'a>(accepted: Option<Vec<QualityItem>>, offers: &[&'a str],
+                     quality: |&[QualityItem], &str| -> f32) -> Option<&'a str> {
+    let accepted = match accepted {
+        Some(accepted) => accepted,
+        None => return offers.head().map(|offer| *offer)
+    };
+
+    let mut best = None;
+    let mut best_quality = 0f32;
+    for offer in offers.iter() {
+        let q = quality(accepted.as_slice(), *offer);
+        if q > best_quality {
+            best = Some(*offer);
+            best_quality = q;
+        }
+    }
+    best
+}
+
+#[test]
+fn parses_quality_lists() {
+    let items = parse_quality_list("text/html;level=1, application/json;q=0.9, */*;q=0.1, text/plain");
+    let values: Vec<&str> = items.iter().map(|item| item.value.as_slice()).collect();
+    assert_eq!(values, vec!["text/html", "text/plain", "application/json", "*/*"]);
+    assert_eq!(items[2].quality, 0.9);
+
+    assert_eq!(parse_quality_list("gzip;q=2, , br;q=x"), vec![
+        QualityItem { value: "gzip".to_string(), quality: 1.0 },
+        QualityItem { value: "br".to_string(), quality: 1.0 }
+    ]);
+    assert_eq!(parse_quality_list(""), vec![]);
+}
+
+#[test]
+fn ranks_media_types_by_quality() {
+    let quality = |accept: &str, media_type: &str| {
+        media_type_quality(parse_quality_list(accept).as_slice(), media_type)
+    };
+    assert_eq!(quality("text/html,application/json;q=0.9", "application/json"), 0.9);
+    assert_eq!(quality("text/html,application/json;q=0.9", "text/html"), 1.0);
+    assert_eq!(quality("text/*;q=0.5, */*;q=0.1", "text/html"), 0.5);
+    assert_eq!(quality("text/*;q=0.5, */*;q=0.1", "application/json"), 0.1);
+    assert_eq!(quality("Text/HTML", "text/html"), 1.0);
+    assert_eq!(quality("image/png", "text/html"), 0.0);
+}
+
+#[test]
+fn ranks_languages_by_quality() {
+    let quality = |accept: &str, tag: &str| {
+        language_quality(parse_quality_list(accept).as_slice(), tag)
+    };
+    assert_eq!(quality("de-DE, en;q=0.8, *;q=0.1", "en-US"), 0.8);
+    assert_eq!(quality("de-DE, en;q=0.8, *;q=0.1", "DE-de"), 1.0);
+    assert_eq!(quality("de-DE, en;q=0.8, *;q=0.1", "de"), 0.1);
+    assert_eq!(quality("en, en-GB;q=0", "en-GB"), 0.0);
+    assert_eq!(quality("en", "eng"), 0.0);
+}
+
+#[test]
+fn ranks_encodings_by_quality() {
+    let quality = |accept: &str, coding: &str| {
+        encoding_quality(parse_quality_list(accept).as_slice(), coding)
+    };
+    assert_eq!(quality("gzip, deflate;q=0.5", "GZIP"), 1.0);
+    assert_eq!(quality("gzip, deflate;q=0.5", "deflate"), 0.5);
+    assert_eq!(quality("gzip, deflate;q=0.5", "br"), 0.0);
+    assert_eq!(quality("gzip, deflate;q=0.5", "identity"), 1.0);
+    assert_eq!(quality("gzip, *;q=0", "identity"), 0.0);
+    assert_eq!(quality("gzip, *;q=0.2", "br"), 0.2);
+    assert_eq!(quality("", "identity"), 1.0);
+}
+
+#[test]
+fn picks_preferred_offers() {
+    let offers = ["text/html", "application/json"];
+    let pick = |accept: Option<&str>| {
+        preferred(accept.map(|accept| parse_quality_list(accept)), offers.as_slice(),
+                  |accepted, offer| media_type_quality(accepted, offer))
+    };
+    assert_eq!(pick(Some("application/json, text/html;q=0.5")), Some("application/json"));
+    assert_eq!(pick(Some("*/*")), Some("text/html"));
+    assert_eq!(pick(Some("application/*, text/html;q=0")), Some("application/json"));
+    assert_eq!(pick(Some("image/png")), None);
+    assert_eq!(pick(None), Some("text/html"));
+}
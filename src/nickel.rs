@@ -1,9 +1,13 @@
-use std::io::net::ip::{Port, IpAddr};
+use std::io::net::ip::{SocketAddr, Port, IpAddr, Ipv6Addr};
+use std::os;
 
 use router::{Router, RequestHandler, HttpRouter};
+use router::router::MethodNotAllowedHandler;
 use middleware::{MiddlewareStack, Middleware, ErrorHandler, MiddlewareResult};
 use nickel_error::{ NickelError, ErrorWithStatusCode };
-use server::Server;
+use server;
+use server::{Server, ServerOptions, Listening};
+use template_engine::{Templates, TemplateEngine};
 
 use http::method::Method;
 use http::status::NotFound;
@@ -14,19 +18,27 @@ use response::Response;
 use json_body_parser::JsonBodyParser;
 use query_string::QueryStringParser;
 use default_error_handler::DefaultErrorHandler;
+use app_data::AppDataMiddleware;
+use ip_filter;
+use forwarded::ProxyHeaders;
+
+// The default limit for request bodies, 10 MiB
+const DEFAULT_MAX_BODY_SIZE: uint = 10 * 1024 * 1024;
 
 /// Nickel is the application object. It's the surface that
 /// holds all public APIs.
 pub struct Nickel{
     middleware_stack: MiddlewareStack,
+    default_router: Router,
+    templates: Templates,
+    not_found_handler: Option<Box<RequestHandler + Send + Sync>>,
+    options: ServerOptions
 }
 
 impl HttpRouter for Nickel {
     fn add_route<H: RequestHandler>(&mut self, method: Method, uri: &str, handler: H) {
-        let mut router = Router::new();
         // FIXME: Inference failure in nightly 22/10/2014
-        router.add_route::<H>(method, uri, handler);
-        self.utilize(router);
+        self.default_router.add_route::<H>(method, uri, handler);
     }
 }
 
@@ -39,8 +51,252 @@ impl Nickel {
         // free to cancel it out from their custom error handler if
         // they don't like the default behaviour.
         middleware_stack.add_error_handler(DefaultErrorHandler);
+        middleware_stack.set_max_body_size(Some(DEFAULT_MAX_BODY_SIZE));
+
+        let mut templates = Templates::new();
+        let env = os::getenv("NICKEL_ENV");
+        templates.set_reload(env.as_ref().map_or(false, |env| env.as_slice() == "development"));
+
+        Nickel {
+            middleware_stack: middleware_stack,
+            default_router: Router::new(),
+            templates: templates,
+            not_found_handler: None,
+            options: ServerOptions::new()
+        }
+    }
+
+    /// Creates an instance of Nickel sharing a value like the configuration
+    /// or a connection pool with all requests, which `Request::data` gives.
+    /// The value is shared between the tasks handling requests, so it needs
+    /// to be thread-safe, and changing it takes e.g. a `Mutex`.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    ///
+    /// struct Config {
+    ///     greeting: String
+    /// }
+    ///
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     let config = request.data::<Config>().unwrap();
+    ///     response.send(config.greeting.as_slice());
+    /// }
+    ///
+    /// let mut server = Nickel::with_data(Config { greeting: "Hello".to_string() });
+    /// server.get("/", handler);
+    /// ```
+    pub fn with_data<T: Send + Sync + 'static>(data: T) -> Nickel {
+        let mut server = Nickel::new();
+        // registered first, so all middleware sees it
+        server.utilize(AppDataMiddleware::new(data));
+        server
+    }
+
+    /// Enables or disables development mode. In development mode templates
+    /// are recompiled whenever their file changed, otherwise they are
+    /// compiled only once and served from the cache.
+    ///
+    /// Development mode is enabled by default if the `NICKEL_ENV`
+    /// environment variable is set to `development`.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::Nickel;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.development_mode(true);
+    /// ```
+    pub fn development_mode(&mut self, enabled: bool) {
+        self.templates.set_reload(enabled);
+    }
+
+    /// Sets the maximum size of request bodies in bytes, which is 10 MiB by
+    /// default. Requests with larger bodies, or with a larger Content-Length,
+    /// fail with a `413 Request Entity Too Large` error before any middleware
+    /// runs, so the body parsers never see them. `None` removes the limit.
+    ///
+    /// The limit doesn't protect against clients sending huge bodies, as
+    /// rust-http reads the whole body into memory before it's checked. That
+    /// still needs a proxy in front of nickel limiting it.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::Nickel;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.max_body_size(Some(64 * 1024));
+    /// ```
+    pub fn max_body_size(&mut self, bytes: Option<uint>) {
+        self.middleware_stack.set_max_body_size(bytes);
+    }
+
+    /// Sets the milliseconds a request may take. Once they're up, the
+    /// remaining middleware is skipped and the request fails with a
+    /// `504 Gateway Timeout` error, unless the response was started already.
+    /// Handlers can't be interrupted, so long running ones should check
+    /// `RequestDeadline::deadline_exceeded` themselves. There's no timeout by
+    /// default.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::Nickel;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.request_timeout(Some(30_000));
+    /// ```
+    pub fn request_timeout(&mut self, timeout_ms: Option<u64>) {
+        self.middleware_stack.set_request_timeout(timeout_ms);
+    }
 
-        Nickel { middleware_stack: middleware_stack }
+    /// Trusts the proxies with addresses in the CIDR block, e.g.
+    /// `10.0.0.0/8`, to tell the address of the client in the
+    /// X-Forwarded-For or Forwarded header, see `proxy_headers`, which
+    /// `Request::remote_ip` gives then. The addresses in the header are taken
+    /// from the end, skipping those of trusted proxies, since others could be
+    /// made up by the client.
+    ///
+    /// Panics if the block isn't valid CIDR notation.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::Nickel;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.trust_proxy("10.0.0.0/8");
+    /// server.trust_proxy("::1");
+    /// ```
+    pub fn trust_proxy(&mut self, cidr: &str) {
+        self.middleware_stack.add_trusted_proxy(ip_filter::parse_cidr(cidr));
+    }
+
+    /// Sets the headers the proxies tell about the original request with,
+    /// `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` by
+    /// default. Only those are read, so a header of the other kind sent by
+    /// the client and passed on by the proxy is ignored.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Nickel, Rfc7239};
+    ///
+    /// let mut server = Nickel::new();
+    /// server.trust_proxy("10.0.0.0/8");
+    /// server.proxy_headers(Rfc7239);
+    /// ```
+    pub fn proxy_headers(&mut self, proxy_headers: ProxyHeaders) {
+        self.middleware_stack.set_proxy_headers(proxy_headers);
+    }
+
+    /// Sets the number of tasks accepting connections, one by default. More
+    /// acceptors help with lots of short lived connections.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::Nickel;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.acceptors(4);
+    /// ```
+    pub fn acceptors(&mut self, count: uint) {
+        self.options.acceptors = count;
+    }
+
+    /// Sets the number of tasks handling connections. By default every
+    /// connection gets a task of its own. With a fixed number of workers
+    /// connections queue up until a worker is free, which bounds the
+    /// resources used under load. Keep in mind that keep-alive connections
+    /// occupy their worker until they're closed.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::Nickel;
+    /// use std::os;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.workers(Some(os::num_cpus() * 8));
+    /// ```
+    pub fn workers(&mut self, count: Option<uint>) {
+        self.options.workers = count;
+    }
+
+    /// Sets the milliseconds a client has to send its first request after
+    /// connecting, 30 seconds by default. Clients which take longer get a
+    /// `408 Request Timeout`. `None` waits forever.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::Nickel;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.read_timeout(Some(10_000));
+    /// ```
+    pub fn read_timeout(&mut self, timeout_ms: Option<u64>) {
+        self.options.read_timeout = timeout_ms;
+    }
+
+    /// Enables or disables persistent connections, which are enabled by
+    /// default. Responses on connections which get closed carry a
+    /// `Connection: close` header.
+    pub fn keep_alive(&mut self, enabled: bool) {
+        self.options.keep_alive = enabled;
+    }
+
+    /// Sets the milliseconds a persistent connection is kept open while
+    /// waiting for the next request, 5 seconds by default. `None` keeps it
+    /// open until the client closes it.
+    pub fn keep_alive_timeout(&mut self, timeout_ms: Option<u64>) {
+        self.options.keep_alive_timeout = timeout_ms;
+    }
+
+    /// Sets the number of requests after which a persistent connection gets
+    /// closed. There's no limit by default.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::Nickel;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.keep_alive_timeout(Some(15_000));
+    /// server.max_keep_alive_requests(Some(100));
+    /// ```
+    pub fn max_keep_alive_requests(&mut self, max: Option<uint>) {
+        self.options.max_keep_alive_requests = max;
+    }
+
+    /// Sets the number of headers a request may have, 100 by default.
+    /// Requests with more headers, or with headers above the limits set
+    /// with `max_header_size` and `max_header_section_size`, are rejected
+    /// with a `431 Request Header Fields Too Large` before any middleware
+    /// runs. `None` removes the limit.
+    ///
+    /// The limits don't protect against clients sending huge headers, as
+    /// rust-http reads all of them into memory before they're checked. That
+    /// still needs a proxy in front of nickel limiting them.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::Nickel;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.max_headers(Some(50));
+    /// server.max_header_size(Some(4 * 1024));
+    /// server.max_header_section_size(Some(16 * 1024));
+    /// ```
+    pub fn max_headers(&mut self, count: Option<uint>) {
+        self.options.max_headers = count;
+    }
+
+    /// Sets the bytes a single header may take, name and value together,
+    /// 8 KiB by default. `None` removes the limit.
+    pub fn max_header_size(&mut self, bytes: Option<uint>) {
+        self.options.max_header_size = bytes;
+    }
+
+    /// Sets the bytes all headers of a request may take together, 64 KiB by
+    /// default. `None` removes the limit.
+    pub fn max_header_section_size(&mut self, bytes: Option<uint>) {
+        self.options.max_header_section_size = bytes;
     }
 
     /// Registers a middleware handler which will be invoked among other middleware
@@ -53,6 +309,10 @@ impl Nickel {
     /// down the stack should continue or if the middleware invocation should
     /// be stopped after the current handler.
     ///
+    /// Routes registered directly on the server (e.g. with `server.get(...)`)
+    /// are collected in a default router which always runs as the last
+    /// middleware in the chain, after everything registered with `utilize`.
+    ///
     /// # Example
     ///
     /// ```{rust}
@@ -75,6 +335,12 @@ impl Nickel {
     /// A error handler is nearly identical to a regular middleware handler with the only
     /// difference that it takes an additional error parameter or type `NickelError.
     ///
+    /// As soon as a middleware or route handler returns an error, the remaining
+    /// middleware is skipped and the error is passed to the error handlers instead,
+    /// starting with the one registered last. An error handler can either handle
+    /// the error and halt, leave it to the next error handler by continuing, or
+    /// return a different error which is passed on instead.
+    ///
     /// # Example
     ///
     /// ```{rust}
@@ -107,6 +373,36 @@ impl Nickel {
         self.middleware_stack.add_error_handler(handler);
     }
 
+    /// Registers the handler for requests which weren't handled by any route
+    /// or middleware. The response status is set to `404 Not Found` before
+    /// the handler runs. By default the error handlers are invoked with a
+    /// `NotFound` error, which results in a plain "Not Found" page.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response};
+    ///
+    /// fn not_found(_request: &Request, response: &mut Response) {
+    ///     response.send("<h1>Nothing to see here</h1>");
+    /// }
+    ///
+    /// let mut server = Nickel::new();
+    /// server.handle_not_found(not_found);
+    /// ```
+    pub fn handle_not_found<H: RequestHandler>(&mut self, handler: H) {
+        self.not_found_handler = Some(box handler as Box<RequestHandler + Send + Sync>);
+    }
+
+    /// Registers a template engine for all templates with the given file
+    /// extension. Templates rendered with `Response::render_template` are
+    /// compiled with the engine registered for their extension or the
+    /// built-in mustache engine if there is none.
+    ///
+    /// Take a look at `TemplateEngine` for an example.
+    pub fn register_template_engine<E: TemplateEngine>(&mut self, extension: &str, engine: E) {
+        self.templates.register(extension, engine);
+    }
+
     /// Create a new middleware to serve as a router.
     ///
     ///
@@ -130,6 +426,9 @@ impl Nickel {
 
     /// Create a new middleware to parse JSON bodies.
     ///
+    /// Only bodies with a JSON Content-Type get parsed. A malformed body makes
+    /// the middleware fail with a `BadRequest` error. Without the middleware,
+    /// `json_as` parses the body on demand.
     ///
     /// # Example
     /// ```{rust}
@@ -156,8 +455,6 @@ impl Nickel {
     /// };
     ///
     /// let mut server = Nickel::new();
-    /// // It is currently a requirement that the json_body_parser middleware
-    /// // is added before any routes that require it.
     /// server.utilize(Nickel::json_body_parser());
     /// server.utilize(router);
     /// # }
@@ -168,6 +465,9 @@ impl Nickel {
 
     /// Create a new middleware to parse the query string.
     ///
+    /// The middleware parses the query string once and keeps the result for the
+    /// rest of the request. Without it, the query string accessors parse the
+    /// query string on demand.
     ///
     /// # Example
     /// ```{rust}
@@ -180,13 +480,12 @@ impl Nickel {
     /// let router = router! {
     ///     get "/a/get/request" => |request, response| {
     ///         let foo = request.query("foo", "this is the default value, if foo is not present!");
-    ///         response.send(foo[0].as_slice());
+    ///         let page = request.query_or("page", "1");
+    ///         response.send(format!("{} on page {}", foo[0], page));
     ///     }
     /// };
     ///
     /// let mut server = Nickel::new();
-    /// // It is currently a requirement that the query_string middleware
-    /// // is added before any routes that require it.
     /// server.utilize(Nickel::query_string());
     /// server.utilize(router);
     /// # }
@@ -195,26 +494,95 @@ impl Nickel {
         QueryStringParser
     }
 
-    /// Bind and listen for connections on the given host and port
+    /// Bind and listen for connections on the given host and port. Port 0
+    /// picks a free port, which `Listening::socket_name` tells.
+    ///
+    /// The returned `Listening` can shut the server down gracefully. Dropping
+    /// it blocks until that happens, so unless it's kept around the server
+    /// serves forever.
     ///
     /// # Example
     /// ```{rust,ignore}
     /// let mut server = Nickel::new();
     /// server.listen(Ipv4Addr(127, 0, 0, 1), 6767);
     /// ```
-    pub fn listen(mut self, ip: IpAddr, port: Port) {
+    pub fn listen(self, ip: IpAddr, port: Port) -> Listening {
+        self.listen_all(&[SocketAddr { ip: ip, port: port }])
+    }
+
+    /// Bind and listen for connections on the given address, which is an
+    /// IPv4 or IPv6 address with a port, e.g. `0.0.0.0:6767` or `[::]:6767`,
+    /// or a host name with a port, e.g. `localhost:6767`. The server listens
+    /// on all addresses a host name resolves to.
+    ///
+    /// On most systems, Linux included, listening on `[::]` accepts IPv4
+    /// connections, too. Their remote address is an IPv4 address mapped to
+    /// IPv6 then, e.g. `::ffff:10.0.0.1`. Listening on both `[::]` and
+    /// `0.0.0.0` with the same port fails there.
+    ///
+    /// Panics if the address can't be parsed or resolved.
+    ///
+    /// # Example
+    /// ```{rust,ignore}
+    /// let mut server = Nickel::new();
+    /// server.listen_on("[::]:6767");
+    /// ```
+    pub fn listen_on(self, address: &str) -> Listening {
+        match server::resolve_address(address) {
+            Ok(addresses) => self.listen_all(addresses.as_slice()),
+            Err(err) => panic!("Can't listen on {}: {}", address, err)
+        }
+    }
+
+    /// Bind and listen for connections on all of the given addresses, which
+    /// share the middleware and routes. The server is shut down for all of
+    /// them at once.
+    ///
+    /// # Example
+    /// ```{rust,ignore}
+    /// let mut server = Nickel::new();
+    /// server.listen_all(&[SocketAddr { ip: Ipv4Addr(127, 0, 0, 1), port: 6767 },
+    ///                     SocketAddr { ip: Ipv6Addr(0, 0, 0, 0, 0, 0, 0, 1), port: 6767 }]);
+    /// ```
+    pub fn listen_all(self, addresses: &[SocketAddr]) -> Listening {
         fn not_found_handler(_: &Request, _: &mut Response) -> MiddlewareResult {
             Err(NickelError::new("File Not Found", ErrorWithStatusCode(NotFound)))
         }
 
-        self.middleware_stack.add_middleware(not_found_handler);
+        let Nickel {
+            mut middleware_stack, default_router, templates,
+            not_found_handler: custom, options
+        } = self;
+        middleware_stack.add_middleware(default_router);
+        middleware_stack.add_middleware(MethodNotAllowedHandler);
+        match custom {
+            Some(handler) => middleware_stack.add_middleware(NotFoundHandler { handler: handler }),
+            None => middleware_stack.add_middleware(not_found_handler)
+        }
 
-        match port {
-            80u16 =>  println!("Listening on http://{}", ip),
-            _ =>  println!("Listening on http://{}:{}", ip, port),
+        let listening = Server::new(middleware_stack, templates, options).serve(addresses);
+
+        for address in listening.socket_names().iter() {
+            // the address brackets IPv6 addresses
+            match (address.port, address.ip) {
+                (80u16, Ipv6Addr(..)) =>  println!("Listening on http://[{}]", address.ip),
+                (80u16, _) =>  println!("Listening on http://{}", address.ip),
+                _ =>  println!("Listening on http://{}", address),
+            }
         }
         println!("Ctrl-C to shutdown server");
 
-        Server::new(self.middleware_stack, ip, port).serve();
+        listening
+    }
+}
+
+struct NotFoundHandler {
+    handler: Box<RequestHandler + Send + Sync>
+}
+
+impl Middleware for NotFoundHandler {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        res.origin.status = NotFound;
+        self.handler.handle(req, res)
     }
 }
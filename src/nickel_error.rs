@@ -1,15 +1,23 @@
+use std::error::{Error, FromError};
+use std::io::{IoError, FileNotFound, PermissionDenied};
+use std::fmt;
 use std::str::SendStr;
+use http::status;
 use http::status::Status;
 
 pub use self::NickelErrorKind::{ErrorWithStatusCode, UserDefinedError, Other};
 
 /// NickelError is the basic error type for HTTP errors as well as user defined errors.
 /// One can pattern match against the `kind` property to handle the different cases.
-
-#[deriving(Show)]
+///
+/// Errors returned by middleware or route handlers are passed to the error
+/// handlers registered with `Nickel::handle_error`, which is the place to log,
+/// report and render them.
 pub struct NickelError {
     pub kind: NickelErrorKind,
-    pub message: SendStr
+    pub message: SendStr,
+    /// The underlying error which caused this one, if any.
+    pub source: Option<Box<Error + Send>>
 }
 
 impl NickelError {
@@ -22,7 +30,61 @@ impl NickelError {
     pub fn new<T: IntoMaybeOwned<'static>>(message: T, kind: NickelErrorKind) -> NickelError {
         NickelError {
             message: message.into_maybe_owned(),
-            kind: kind
+            kind: kind,
+            source: None
+        }
+    }
+
+    /// Creates a new `NickelError` caused by another error
+    ///
+    /// # Example
+    /// ```{rust,ignore}
+    /// match File::open(&path) {
+    ///     Ok(file) => ...,
+    ///     Err(err) => Err(NickelError::with_source("Couldn't open the upload",
+    ///                                              ErrorWithStatusCode(InternalServerError),
+    ///                                              err))
+    /// }
+    /// ```
+    pub fn with_source<T, E>(message: T, kind: NickelErrorKind, source: E) -> NickelError
+            where T: IntoMaybeOwned<'static>, E: Error + Send {
+        NickelError {
+            message: message.into_maybe_owned(),
+            kind: kind,
+            source: Some(box source as Box<Error + Send>)
+        }
+    }
+
+    /// The status the error should be answered with, which is
+    /// `500 Internal Server Error` for errors without a status code.
+    pub fn status(&self) -> Status {
+        match self.kind {
+            ErrorWithStatusCode(ref status) => status.clone(),
+            _ => status::InternalServerError
+        }
+    }
+}
+
+/// Converts IO errors so they can be propagated from handlers with `try!`.
+/// Missing files result in a `404 Not Found`, missing permissions in a
+/// `403 Forbidden` and all other errors in a `500 Internal Server Error`.
+impl FromError<IoError> for NickelError {
+    fn from_error(err: IoError) -> NickelError {
+        let status = match err.kind {
+            FileNotFound => status::NotFound,
+            PermissionDenied => status::Forbidden,
+            _ => status::InternalServerError
+        };
+        NickelError::with_source(err.desc, ErrorWithStatusCode(status), err)
+    }
+}
+
+impl fmt::Show for NickelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} ({})", self.message, self.kind));
+        match self.source {
+            Some(ref source) => write!(f, ", caused by: {}", source.description()),
+            None => Ok(())
         }
     }
 }
@@ -34,3 +96,18 @@ pub enum NickelErrorKind {
     UserDefinedError(int, String),
     Other
 }
+
+#[test]
+fn converts_io_errors() {
+    use std::io::EndOfFile;
+
+    let not_found = IoError { kind: FileNotFound, desc: "no such file", detail: None };
+    let err: NickelError = FromError::from_error(not_found);
+    assert_eq!(err.status(), status::NotFound);
+    assert_eq!(err.message.as_slice(), "no such file");
+    assert!(err.source.is_some());
+
+    let eof = IoError { kind: EndOfFile, desc: "end of file", detail: None };
+    let err: NickelError = FromError::from_error(eof);
+    assert_eq!(err.status(), status::InternalServerError);
+}
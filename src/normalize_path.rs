@@ -0,0 +1,126 @@
+use std::ascii::AsciiExt;
+use http::method::{Get, Head};
+use http::status::MovedPermanently;
+
+use request::Request;
+use response::Response;
+use middleware::{Continue, Halt, Middleware, MiddlewareResult};
+
+/// A middleware bringing paths into their canonical form before they get
+/// routed: duplicate slashes are collapsed and `.` and `..` segments are
+/// resolved, so `//users/./1/../2` becomes `/users/2`. The query string is
+/// left alone.
+///
+/// By default the request is handled with the normalized path. With
+/// `redirect` clients get a `301 Moved Permanently` to it instead, which
+/// keeps a single url per page for search engines. Only `GET` and `HEAD`
+/// requests get redirected, as other methods would lose their body.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, NormalizePath};
+///
+/// let mut server = Nickel::new();
+/// server.utilize(NormalizePath::new().lowercase(true).redirect(true));
+/// ```
+#[deriving(Clone)]
+pub struct NormalizePath {
+    lowercase: bool,
+    redirect: bool
+}
+
+impl NormalizePath {
+    pub fn new() -> NormalizePath {
+        NormalizePath {
+            lowercase: false,
+            redirect: false
+        }
+    }
+
+    /// Turns the path into lower case, too.
+    pub fn lowercase(mut self, lowercase: bool) -> NormalizePath {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Redirects to the normalized path instead of handling the request with it.
+    pub fn redirect(mut self, redirect: bool) -> NormalizePath {
+        self.redirect = redirect;
+        self
+    }
+
+    fn normalize(&self, url: &str) -> String {
+        let (path, query) = match url.find('?') {
+            Some(pos) => (url.slice_to(pos), url.slice_from(pos)),
+            None => (url, "")
+        };
+
+        let mut segments: Vec<&str> = Vec::new();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {},
+                // there's nothing above the root
+                ".." => { segments.pop(); },
+                segment => segments.push(segment)
+            }
+        }
+
+        let mut normalized = format!("/{}", segments.connect("/"));
+        // a trailing slash is kept, as routes may tell them apart
+        let last = path.split('/').last().unwrap_or("");
+        if !segments.is_empty() && (last == "" || last == "." || last == "..") {
+            normalized.push('/');
+        }
+        if self.lowercase {
+            normalized = normalized.to_ascii_lower();
+        }
+        normalized.push_str(query);
+        normalized
+    }
+}
+
+impl Middleware for NormalizePath {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        let normalized = match req.path() {
+            Some(path) => {
+                let normalized = self.normalize(path);
+                if normalized.as_slice() == path {
+                    return Ok(Continue)
+                }
+                normalized
+            },
+            None => return Ok(Continue)
+        };
+
+        match *req.method() {
+            Get | Head if self.redirect => {
+                res.redirect_with(MovedPermanently, normalized.as_slice());
+                Ok(Halt)
+            },
+            _ => {
+                req.set_path(normalized);
+                Ok(Continue)
+            }
+        }
+    }
+}
+
+#[test]
+fn normalizes_paths() {
+    let normalize = NormalizePath::new();
+    assert_eq!(normalize.normalize("/users/1").as_slice(), "/users/1");
+    assert_eq!(normalize.normalize("//users///1").as_slice(), "/users/1");
+    assert_eq!(normalize.normalize("/users/./1/../2").as_slice(), "/users/2");
+    assert_eq!(normalize.normalize("/../../etc/passwd").as_slice(), "/etc/passwd");
+    assert_eq!(normalize.normalize("/users//").as_slice(), "/users/");
+    assert_eq!(normalize.normalize("/users/1/..").as_slice(), "/users/");
+    assert_eq!(normalize.normalize("/..").as_slice(), "/");
+    assert_eq!(normalize.normalize("//a//b?next=//c/../d").as_slice(), "/a/b?next=//c/../d");
+    assert_eq!(normalize.normalize("").as_slice(), "/");
+}
+
+#[test]
+fn lowercases_paths() {
+    let normalize = NormalizePath::new().lowercase(true);
+    assert_eq!(normalize.normalize("/Users/John?Name=Doe").as_slice(), "/users/john?Name=Doe");
+}
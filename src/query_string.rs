@@ -7,7 +7,7 @@ use urlencoded;
 use http::server::request::{RequestUri, Star, AbsoluteUri, AbsolutePath, Authority};
 use url::UrlParser;
 
-type QueryStore = HashMap<String, Vec<String>>;
+pub type QueryStore = HashMap<String, Vec<String>>;
 
 #[deriving(Clone)]
 pub struct QueryStringParser;
@@ -48,18 +48,52 @@ impl Middleware for QueryStringParser {
 }
 
 pub trait QueryString {
+    /// Returns all values for the given key of the query string or a vector
+    /// holding just the `default` value if the key is absent.
     fn query(&self, key: &str, default: &str) -> Vec<String>;
+
+    /// Returns the first value for the given key of the query string.
+    fn query_param(&self, key: &str) -> Option<String>;
+
+    /// Returns the first value for the given key of the query string or the
+    /// `default` value if the key is absent.
+    fn query_or(&self, key: &str, default: &str) -> String;
+
+    /// Returns the whole query string parsed into a multimap.
+    fn query_map(&self) -> QueryStore;
+}
+
+// Uses the store of the `QueryStringParser` middleware if it ran for this
+// request and parses the query string on demand otherwise.
+fn with_query_store<T>(req: &request::Request, f: |&QueryStore| -> T) -> T {
+    match req.map.get::<QueryStore>() {
+        Some(store) => f(store),
+        None => f(&QueryStringParser::parse(&req.origin.request_uri))
+    }
 }
 
 impl<'a, 'b> QueryString for request::Request<'a, 'b> {
     fn query(&self, key: &str, default: &str) -> Vec<String> {
-        self.map.get::<QueryStore>().and_then(| store | {
+        with_query_store(self, |store| {
             match store.get(key).cloned() {
-                Some(result) => Some(result),
-                _ => Some(vec![default.to_string().clone()])
+                Some(result) => result,
+                None => vec![default.to_string()]
             }
-        }).expect("QueryStore not available. Ensure the middleware \
-                  is added before the route that depends on it.")
+        })
+    }
+
+    fn query_param(&self, key: &str) -> Option<String> {
+        with_query_store(self, |store| {
+            store.get(key).and_then(|values| values.as_slice().head().cloned())
+        })
+    }
+
+    fn query_or(&self, key: &str, default: &str) -> String {
+        self.query_param(key).unwrap_or_else(|| default.to_string())
+    }
+
+    fn query_map(&self) -> QueryStore {
+        with_query_store(self, |store| store.clone())
     }
 }
 
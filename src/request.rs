@@ -1,6 +1,22 @@
+use std::ascii::AsciiExt;
 use http;
-use router::RouteResult;
+use http::method::Method;
+use http::server::request::AbsolutePath;
+use http::headers::HeaderEnum;
+use http::headers::content_type::MediaType;
+use router::{RouteResult, ParamValue, ParamError, FromParam};
 use anymap::AnyMap;
+use std::collections::HashMap;
+use cookies;
+use header_utils;
+use negotiation;
+use negotiation::QualityItem;
+use app_data::AppData;
+use ip_filter::{ClientIp, TrustedPeer};
+use forwarded::Forwarded;
+use charset;
+use charset::DecodeError;
+use std::io::net::ip::IpAddr;
 
 ///A container for all the request data
 pub struct Request<'a, 'b: 'a> {
@@ -9,7 +25,37 @@ pub struct Request<'a, 'b: 'a> {
     ///a `HashMap<String, String>` holding all params with names and values
     pub route_result: Option<RouteResult<'b>>,
 
-    pub map: AnyMap
+    ///data attached to the request by middleware, with one value per type,
+    ///e.g. the authenticated user or the picked locale. Using private types
+    ///for the values, with a trait on `Request` to get at them, keeps
+    ///middleware from seeing each other's data.
+    ///
+    ///# Example
+    ///```{rust}
+    ///use nickel::{Request, Response, Middleware, MiddlewareResult, Continue};
+    ///
+    ///struct Tenant(String);
+    ///
+    ///struct TenantMiddleware;
+    ///
+    ///impl Middleware for TenantMiddleware {
+    ///    fn invoke(&self, req: &mut Request, _res: &mut Response) -> MiddlewareResult {
+    ///        req.map.insert(Tenant("acme".to_string()));
+    ///        Ok(Continue)
+    ///    }
+    ///}
+    ///
+    ///fn handler(request: &Request, response: &mut Response) {
+    ///    match request.map.get::<Tenant>() {
+    ///        Some(&Tenant(ref name)) => response.send(format!("Hello {}", name)),
+    ///        None => response.send("Hello")
+    ///    }
+    ///}
+    ///```
+    pub map: AnyMap,
+
+    method: Method,
+    path: Option<String>
 }
 
 impl<'a, 'b> Request<'a, 'b> {
@@ -17,11 +63,323 @@ impl<'a, 'b> Request<'a, 'b> {
         Request {
             origin: req,
             route_result: None,
-            map: AnyMap::new()
+            map: AnyMap::new(),
+            method: req.method.clone(),
+            path: None
+        }
+    }
+
+    /// Returns the method the request should be handled as. That's the
+    /// method of the request unless it was overridden, e.g. by the
+    /// `MethodOverride` middleware. Routing uses this method.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Overrides the method the request gets handled as.
+    pub fn set_method(&mut self, method: Method) {
+        self.method = method;
+    }
+
+    /// Returns the path the request should be handled as, with the query
+    /// string. That's the path of the request unless it was rewritten, e.g.
+    /// by the `NormalizePath` middleware. Routing uses this path. Requests
+    /// which don't ask for a path, like `OPTIONS *`, have none.
+    pub fn path(&self) -> Option<&str> {
+        match self.path {
+            Some(ref path) => Some(path.as_slice()),
+            None => match self.origin.request_uri {
+                AbsolutePath(ref path) => Some(path.as_slice()),
+                _ => None
+            }
         }
     }
 
+    /// Rewrites the path the request gets handled as.
+    pub fn set_path(&mut self, path: String) {
+        self.path = Some(path);
+    }
+
     pub fn param(&self, key: &str) -> &str {
         self.route_result.as_ref().unwrap().param(key)
     }
+
+    /// Returns the value of a route variable converted to the given type,
+    /// see `RouteResult::param_as`.
+    pub fn param_as<T: FromParam>(&self, key: &str) -> Result<T, ParamError> {
+        match self.route_result {
+            Some(ref route_result) => route_result.param_as(key),
+            None => Err(ParamError::Missing(key.to_string()))
+        }
+    }
+
+    /// Returns the converted value of a route variable declared with a type,
+    /// e.g. `:id:uint`.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Request, Response, ParamValue};
+    /// // registered for "/users/:id:uint"
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     match request.param_value("id") {
+    ///         Some(&ParamValue::Uint(id)) => response.send(format!("User {}", id)),
+    ///         _ => unreachable!()
+    ///     }
+    /// }
+    /// ```
+    pub fn param_value(&self, key: &str) -> Option<&ParamValue> {
+        self.route_result.as_ref().unwrap().value(key)
+    }
+
+    /// Returns the address of the client. That's the address of the peer,
+    /// unless it's one of the proxies trusted with `Nickel::trust_proxy`,
+    /// which pass on the address of the client in the Forwarded or
+    /// X-Forwarded-For header.
+    pub fn remote_ip(&self) -> Option<IpAddr> {
+        match self.map.get::<ClientIp>() {
+            Some(client) => Some(client.ip),
+            None => self.origin.remote_addr.map(|addr| addr.ip)
+        }
+    }
+
+    /// Returns what the proxies in front of the server tell about the
+    /// original request, see `Forwarded`. That's whatever the headers say,
+    /// no matter whether the peer is a trusted proxy.
+    pub fn forwarded(&self) -> Forwarded {
+        Forwarded::from_request(self)
+    }
+
+    /// Returns the scheme the client used, `http` or `https`. Nickel doesn't
+    /// speak TLS itself, so that's `http` unless a proxy trusted with
+    /// `Nickel::trust_proxy` tells otherwise in the X-Forwarded-Proto or
+    /// Forwarded header.
+    pub fn scheme(&self) -> String {
+        self.trusted_forwarded().and_then(|forwarded| forwarded.proto)
+                                .unwrap_or("http".to_string())
+    }
+
+    /// Whether the client used HTTPS, see `scheme`.
+    pub fn is_secure(&self) -> bool {
+        self.scheme().as_slice() == "https"
+    }
+
+    /// Whether the request was sent by script, going by the
+    /// `X-Requested-With: XMLHttpRequest` header most JavaScript libraries
+    /// send.
+    pub fn is_xhr(&self) -> bool {
+        header_utils::find(&self.origin.headers, "X-Requested-With").map_or(false, |value| {
+            value.as_slice().trim().eq_ignore_ascii_case("XMLHttpRequest")
+        })
+    }
+
+    /// Returns the host name the client asked for, without the port. That's
+    /// the one a trusted proxy forwarded or the one of the Host header.
+    pub fn host(&self) -> Option<String> {
+        self.host_header().map(|host| header_utils::strip_port(host.as_slice()).to_string())
+    }
+
+    /// Returns the port the client connected to, which is the default port
+    /// of the scheme unless the host names another one.
+    pub fn port(&self) -> u16 {
+        match self.host_header().and_then(|host| header_utils::port(host.as_slice())) {
+            Some(port) => port,
+            None if self.is_secure() => 443,
+            None => 80
+        }
+    }
+
+    fn host_header(&self) -> Option<String> {
+        self.trusted_forwarded().and_then(|forwarded| forwarded.host)
+                                .or_else(|| header_utils::find(&self.origin.headers, "Host"))
+    }
+
+    // The headers of other peers than trusted proxies could be made up
+    fn trusted_forwarded(&self) -> Option<Forwarded> {
+        match self.map.get::<TrustedPeer>() {
+            Some(_) => Some(self.forwarded()),
+            None => None
+        }
+    }
+
+    /// Returns the value shared with all requests by `Nickel::with_data`, or
+    /// `None` if there's no value of the type.
+    pub fn data<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map.get::<AppData<T>>().map(|app_data| &*app_data.data)
+    }
+
+    /// Returns the value of the header with the name, which is looked up
+    /// case insensitively.
+    pub fn header(&self, name: &str) -> Option<String> {
+        header_utils::find(&self.origin.headers, name)
+    }
+
+    /// Returns the values of all headers with the name, for headers which
+    /// are lists of comma separated values, like `Accept` or `Cache-Control`.
+    pub fn headers_all(&self, name: &str) -> Vec<String> {
+        self.origin.headers.iter()
+            .filter(|header| header.header_name().as_slice().eq_ignore_ascii_case(name))
+            .flat_map(|header| {
+                let values: Vec<String> = header.header_value().as_slice().split(',')
+                                                .map(|value| value.trim().to_string())
+                                                .filter(|value| !value.is_empty())
+                                                .collect();
+                values.into_iter()
+            })
+            .collect()
+    }
+
+    /// Returns the length of the body the client declared.
+    pub fn content_length(&self) -> Option<uint> {
+        self.origin.headers.content_length
+    }
+
+    /// Returns the media type of the body, with its parameters.
+    pub fn content_type(&self) -> Option<&MediaType> {
+        self.origin.headers.content_type.as_ref()
+    }
+
+    /// Whether the body has the media type, e.g. `application/json`,
+    /// regardless of its parameters. The subtype `*` matches all subtypes,
+    /// and a subtype like `*+json` all with that suffix.
+    pub fn has_content_type(&self, media_type: &str) -> bool {
+        let mut parts = media_type.splitn(1, '/');
+        let (type_, subtype) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+        self.content_type().map_or(false, |content_type| {
+            content_type.type_.as_slice().eq_ignore_ascii_case(type_) && {
+                let actual = content_type.subtype.as_slice().to_ascii_lower();
+                let subtype = subtype.to_ascii_lower();
+                subtype.as_slice() == "*" || actual == subtype ||
+                    (subtype.as_slice().starts_with("*+") &&
+                     actual.as_slice().ends_with(subtype.as_slice().slice_from(1)))
+            }
+        })
+    }
+
+    /// Returns the value of a parameter of the media type of the body, e.g.
+    /// `charset` or the `boundary` of multipart bodies.
+    pub fn content_type_param(&self, name: &str) -> Option<&str> {
+        self.content_type().and_then(|content_type| {
+            content_type.parameters.iter()
+                        .find(|&&(ref param, _)| param.as_slice().eq_ignore_ascii_case(name))
+                        .map(|&(_, ref value)| value.as_slice())
+        })
+    }
+
+    pub fn user_agent(&self) -> Option<String> {
+        self.header("User-Agent")
+    }
+
+    pub fn referer(&self) -> Option<String> {
+        self.header("Referer")
+    }
+
+    /// Returns the raw body of the request. The server reads the whole body
+    /// before the middleware runs, so it can be looked at any number of
+    /// times, e.g. to verify a signature before parsing it as JSON.
+    pub fn body_bytes(&self) -> &[u8] {
+        self.origin.body.as_slice()
+    }
+
+    /// Returns the body decoded as text in the charset of its media type,
+    /// UTF-8 by default. Bodies which aren't valid in the charset are an
+    /// error instead of getting garbled.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Request, Response};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     match request.text() {
+    ///         Ok(text) => response.send(format!("You sent {} characters", text.char_len())),
+    ///         Err(err) => response.send(format!("Can't read the body: {}", err))
+    ///     }
+    /// }
+    /// ```
+    pub fn text(&self) -> Result<String, DecodeError> {
+        charset::decode(self.body_bytes(), self.content_type_param("charset").unwrap_or("utf-8"))
+    }
+
+    /// Returns all cookies sent with the request, parsed from the Cookie header.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Request, Response};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     let cookies = request.cookies();
+    ///     response.send(format!("You sent {} cookies", cookies.len()));
+    /// }
+    /// ```
+    pub fn cookies(&self) -> HashMap<String, String> {
+        match header_utils::find(&self.origin.headers, "Cookie") {
+            Some(header) => cookies::parse(header.as_slice()),
+            None => HashMap::new()
+        }
+    }
+
+    /// Returns the value of the cookie with the given name.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().remove(name)
+    }
+
+    /// Returns the media ranges of the Accept header, most preferred first.
+    /// Without the header the list is empty, though clients accept any
+    /// media type then.
+    pub fn accepted_types(&self) -> Vec<QualityItem> {
+        self.quality_list("Accept").unwrap_or(Vec::new())
+    }
+
+    /// Whether the client accepts responses of the media type, e.g.
+    /// `application/json`.
+    pub fn accepts(&self, media_type: &str) -> bool {
+        match self.quality_list("Accept") {
+            Some(accepted) => negotiation::media_type_quality(accepted.as_slice(), media_type) > 0.0,
+            None => true
+        }
+    }
+
+    /// Picks the media type the client prefers among the ones the handler
+    /// can respond with. Ties go to the earlier one. Returns `None` if the
+    /// client accepts none of them.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Request, Response};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     match request.preferred_of(&["text/html", "application/json"]) {
+    ///         Some("application/json") => response.send("{\"message\": \"hello\"}"),
+    ///         _ => response.send("<h1>hello</h1>")
+    ///     }
+    /// }
+    /// ```
+    pub fn preferred_of<'c>(&self, media_types: &[&'c str]) -> Option<&'c str> {
+        negotiation::preferred(self.quality_list("Accept"), media_types,
+                               |accepted, media_type| negotiation::media_type_quality(accepted, media_type))
+    }
+
+    /// Returns the language ranges of the Accept-Language header, most
+    /// preferred first, e.g. `de-DE` and `en` for `en;q=0.8, de-DE`.
+    pub fn accepted_languages(&self) -> Vec<QualityItem> {
+        self.quality_list("Accept-Language").unwrap_or(Vec::new())
+    }
+
+    /// Returns the content codings of the Accept-Encoding header, most
+    /// preferred first.
+    pub fn accepted_encodings(&self) -> Vec<QualityItem> {
+        self.quality_list("Accept-Encoding").unwrap_or(Vec::new())
+    }
+
+    /// Picks the content coding the client prefers among the ones the
+    /// response can be sent with, e.g. `["gzip", "identity"]`, for serving
+    /// compressed content. Ties go to the earlier one. Clients without the
+    /// Accept-Encoding header only get `identity`. Returns `None` if the
+    /// client accepts none of them.
+    pub fn preferred_encoding<'c>(&self, codings: &[&'c str]) -> Option<&'c str> {
+        negotiation::preferred(Some(self.accepted_encodings()), codings,
+                               |accepted, coding| negotiation::encoding_quality(accepted, coding))
+    }
+
+    fn quality_list(&self, name: &str) -> Option<Vec<QualityItem>> {
+        header_utils::find(&self.origin.headers, name)
+            .map(|header| negotiation::parse_quality_list(header.as_slice()))
+    }
 }
@@ -0,0 +1,86 @@
+use std::rand;
+use request::Request;
+use response::Response;
+use middleware::{Continue, Middleware, MiddlewareResult};
+use header_utils;
+
+/// The header carrying the id of a request.
+pub const REQUEST_ID_HEADER: &'static str = "X-Request-Id";
+
+// Incoming ids longer than this are replaced by a generated one
+const MAX_ID_LENGTH: uint = 128;
+
+struct RequestIdData {
+    id: String
+}
+
+/// A middleware giving every request a unique id, which can be used to
+/// correlate log lines across services. An id sent by the client or a
+/// proxy in the `X-Request-Id` header is kept, otherwise a random one is
+/// generated. The id is echoed in the `X-Request-Id` header of the response,
+/// which also makes it show up in the `Json` access logs.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, Request, Response, HttpRouter, RequestIdMiddleware, RequestId};
+///
+/// fn handler(request: &Request, response: &mut Response) {
+///     response.send(format!("Your request has the id {}", request.request_id().unwrap()));
+/// }
+///
+/// let mut server = Nickel::new();
+/// server.utilize(RequestIdMiddleware);
+/// server.get("/", handler);
+/// ```
+#[deriving(Clone)]
+pub struct RequestIdMiddleware;
+
+impl Middleware for RequestIdMiddleware {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        let id = match header_utils::find(&req.origin.headers, REQUEST_ID_HEADER) {
+            Some(ref id) if is_valid_id(id.as_slice()) => id.clone(),
+            _ => generate_id()
+        };
+
+        res.origin.headers.extensions.insert(REQUEST_ID_HEADER.to_string(), id.clone());
+        req.map.insert(RequestIdData { id: id });
+        Ok(Continue)
+    }
+}
+
+pub trait RequestId {
+    /// Returns the id of the request, if the `RequestIdMiddleware` is in use.
+    fn request_id(&self) -> Option<&str>;
+}
+
+impl<'a, 'b> RequestId for Request<'a, 'b> {
+    fn request_id(&self) -> Option<&str> {
+        self.map.get::<RequestIdData>().map(|data| data.id.as_slice())
+    }
+}
+
+// Ids end up in headers and logs, so only visible ascii is accepted
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_ID_LENGTH && id.bytes().all(|b| b > 0x20 && b < 0x7F)
+}
+
+fn generate_id() -> String {
+    format!("{:016x}{:016x}", rand::random::<u64>(), rand::random::<u64>())
+}
+
+#[test]
+fn validates_incoming_ids() {
+    assert!(is_valid_id("f3b8c0e2-7d1a-4c55-9a0e-1b2c3d4e5f60"));
+    assert!(!is_valid_id(""));
+    assert!(!is_valid_id("has spaces"));
+    assert!(!is_valid_id("line\r\nbreak"));
+    assert!(!is_valid_id(String::from_char(129, 'a').as_slice()));
+}
+
+#[test]
+fn generates_unique_ids() {
+    let id = generate_id();
+    assert_eq!(id.len(), 32);
+    assert!(is_valid_id(id.as_slice()));
+    assert!(id != generate_id());
+}
@@ -1,34 +1,195 @@
-use std::sync::RWLock;
-use std::collections::HashMap;
-use std::collections::hash_map::{Occupied, Vacant};
-use std::io::{IoResult, File};
-use std::io::util::copy;
+use std::mem;
+use std::cmp;
+use std::hash;
+use std::io::{IoResult, IoError, File, FileNotFound, TypeFile};
+use std::io::fs::PathExtensions;
+use std::io::SeekSet;
+use std::io::util::{copy, LimitReader};
 use std::path::BytesContainer;
 use serialize::Encodable;
+use serialize::json;
+use serialize::json::Json;
 use http;
 use http::server::ResponseWriter;
+use http::headers::connection::Token;
 use time;
 use mimes;
+use cookies;
+use cookies::CookieOptions;
+use header_utils;
 use mustache;
-use mustache::{Template, Encoder, Error};
+use mustache::{Encoder, Error};
+use template_engine::Templates;
+use event_stream::EventStream;
+use request::Request;
+use middleware::{Halt, MiddlewareResult};
+use nickel_error::{NickelError, ErrorWithStatusCode};
+use websocket;
+use websocket::WebSocketHandler;
 
-pub type TemplateCache = RWLock<HashMap<&'static str, Template>>;
+/// A hook which runs right before the headers of a response get written
+/// and once the response is complete. Middleware can use it to add headers
+/// which depend on the outcome of the request, such as a session cookie, or
+/// to record the outcome, e.g. for logging.
+pub trait ResponseHook {
+    fn before_send(&mut self, _res: &mut ResponseWriter) {}
+
+    /// Runs after the middleware stack finished with the response.
+    fn after_send(&mut self, _res: &Response) {}
+}
+
+/// The kind of ETag to compute for a response, see `Response::etag`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ETag {
+    /// Marks responses which are byte-for-byte identical.
+    Strong,
+    /// Marks responses which are semantically equivalent, e.g. if they only
+    /// differ in an embedded timestamp.
+    Weak
+}
 
 ///A container for the response
 pub struct Response<'a, 'b: 'a> {
     ///the original `http::server::ResponseWriter`
     pub origin: &'a mut ResponseWriter<'b>,
-    templates: &'a TemplateCache
+    request: &'a http::server::Request,
+    templates: &'a Templates,
+    hooks: Vec<Box<ResponseHook + 'static>>,
+    started: bool,
+    bytes_written: u64,
+    etag: Option<ETag>,
+    // the body passed to `send`, held back until it's clear whether it's
+    // the whole body
+    pending: Option<Vec<u8>>,
+    // the length of the body left out of the response to a HEAD request
+    head_length: uint,
+    upgrade: Option<Box<WebSocketHandler + Send + 'static>>
 }
 
 impl<'a, 'b> Response<'a, 'b> {
     pub fn from_internal<'c, 'd>(response: &'c mut ResponseWriter<'d>,
-                                 templates: &'c TemplateCache)
+                                 request: &'c http::server::Request,
+                                 templates: &'c Templates)
                                 -> Response<'c, 'd> {
         Response {
             origin: response,
-            templates: templates
+            request: request,
+            templates: templates,
+            hooks: Vec::new(),
+            started: false,
+            bytes_written: 0,
+            etag: None,
+            pending: None,
+            head_length: 0,
+            upgrade: None
+        }
+    }
+
+    /// Registers a hook which runs right before the headers of the response
+    /// get written, i.e. before the first write to the response or after the
+    /// middleware stack finished if nothing was written at all.
+    pub fn on_send<H: ResponseHook + 'static>(&mut self, hook: H) {
+        self.hooks.push(box hook);
+    }
+
+    /// Runs the hooks registered with `on_send` and sets the default headers.
+    /// This happens only once per response and is done automatically by all
+    /// methods writing to the response. It only needs to be called manually
+    /// before writing to `origin` directly.
+    pub fn start(&mut self) {
+        if self.started {
+            return
+        }
+        self.started = true;
+
+        for hook in self.hooks.iter_mut() {
+            hook.before_send(self.origin);
+        }
+
+        Response::set_headers(self.origin);
+
+        // more follows the body held back by `send`, so it's sent chunked
+        match self.pending.take() {
+            Some(body) => {
+                self.origin.headers.content_length = None;
+                let _ = self.write_body(body.as_slice());
+            },
+            None => {}
+        }
+    }
+
+    /// Completes the response by starting it, if that didn't happen yet,
+    /// and running the `after_send` hooks. This is done by the server once
+    /// the middleware stack is done.
+    #[doc(hidden)]
+    pub fn finish(&mut self) {
+        // a body passed to `send` alone is the whole body, so its length
+        // is known
+        match self.pending.take() {
+            Some(body) => {
+                self.origin.headers.content_length = Some(body.len());
+                self.start();
+                let _ = self.write_body(body.as_slice());
+            },
+            None => self.start()
+        }
+
+        // nothing was written for a HEAD request, so the length of the body
+        // it left out can still be sent. Without it, rust-http would send
+        // an empty chunked body, which a HEAD response mustn't have.
+        if self.is_head() && self.origin.headers.content_length.is_none() {
+            self.origin.headers.content_length = Some(self.head_length);
         }
+
+        let mut hooks = mem::replace(&mut self.hooks, Vec::new());
+        for hook in hooks.iter_mut() {
+            hook.after_send(&*self);
+        }
+    }
+
+    /// Accepts the upgrade of the connection to the WebSocket protocol. The
+    /// `101 Switching Protocols` response is sent once the middleware is
+    /// done, and then the connection is handed to the handler, see the
+    /// `websocket` module. Requests which don't ask for a WebSocket fail
+    /// with a `400 Bad Request`.
+    pub fn websocket<H: WebSocketHandler + 'static>(&mut self, req: &Request, handler: H)
+                                                   -> MiddlewareResult {
+        let key = match websocket::header_key(req) {
+            Some(key) if websocket::is_upgrade_request(req) && !self.is_started() => key,
+            _ => return Err(NickelError::new("Expected a WebSocket upgrade request",
+                                             ErrorWithStatusCode(http::status::BadRequest)))
+        };
+
+        self.origin.status = http::status::SwitchingProtocols;
+        self.origin.headers.connection = Some(vec![Token("Upgrade".to_string())]);
+        // without a length rust-http sends a chunked body, whose terminator
+        // would end up in the WebSocket stream
+        self.origin.headers.content_length = Some(0);
+        let extensions = &mut self.origin.headers.extensions;
+        extensions.insert("Upgrade".to_string(), "websocket".to_string());
+        extensions.insert("Sec-WebSocket-Accept".to_string(), websocket::accept_key(key.as_slice()));
+
+        self.upgrade = Some(box handler as Box<WebSocketHandler + Send>);
+        Ok(Halt)
+    }
+
+    /// Returns the handler the connection was handed to with `websocket`.
+    #[doc(hidden)]
+    pub fn take_upgrade(&mut self) -> Option<Box<WebSocketHandler + Send + 'static>> {
+        self.upgrade.take()
+    }
+
+    /// The number of body bytes written so far. Writes to `origin` itself
+    /// aren't counted.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Returns true once the headers were written, which happens on the first
+    /// write to the response, or once a body was passed to `send`. From then
+    /// on, the status and headers shouldn't be changed anymore.
+    pub fn is_started(&self) -> bool {
+        self.started || self.pending.is_some()
     }
 
     /// Sets the content type by it's short form.
@@ -47,7 +208,23 @@ impl<'a, 'b> Response<'a, 'b> {
         self
     }
 
-    /// Sets the status code and returns the response for chaining
+    /// Sets the status and returns the response for chaining
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # use nickel::{Request, Response};
+    /// use nickel::status::NotFound;
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.status(NotFound).send("Nothing to see here");
+    /// }
+    /// ```
+    pub fn status(&mut self, status: http::status::Status) -> &mut Response<'a,'b> {
+        self.origin.status = status;
+        self
+    }
+
+    /// Sets the status code and returns the response for chaining. This is
+    /// the same as `status`.
     ///
     /// # Example
     /// ```{rust}
@@ -61,11 +238,92 @@ impl<'a, 'b> Response<'a, 'b> {
     /// # }
     /// ```
     pub fn status_code(&mut self, status: http::status::Status) -> &mut Response<'a,'b> {
+        self.status(status)
+    }
+
+    /// Sets the status by its numeric code and returns the response for
+    /// chaining. Codes without a registered status are sent with the reason
+    /// phrase "Unknown".
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # use nickel::{Request, Response};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.set_status(201).send("Created");
+    /// }
+    /// ```
+    pub fn set_status(&mut self, code: u16) -> &mut Response<'a,'b> {
+        self.status(status_from_code(code))
+    }
+
+    /// The status which will be sent, `200 OK` unless it was changed.
+    pub fn current_status(&self) -> &http::status::Status {
+        &self.origin.status
+    }
+
+    /// Redirects the client to the given url with a `302 Found`.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # use nickel::{Request, Response};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.redirect("/login");
+    /// }
+    /// ```
+    pub fn redirect(&mut self, url: &str) {
+        self.redirect_with(http::status::Found, url)
+    }
+
+    /// Redirects the client to the given url with the given status, which
+    /// should be one of the 3xx codes, e.g. `http::status::MovedPermanently`.
+    /// A small HTML body linking to the url is sent for clients which don't
+    /// follow the redirect.
+    pub fn redirect_with(&mut self, status: http::status::Status, url: &str) {
         self.origin.status = status;
+        // rust-http only accepts absolute urls for its location header
+        self.origin.headers.extensions.insert("Location".to_string(), url.to_string());
+        self.set_html_type();
+
+        let url = escape_html(url);
+        self.send(format!("<html><body>Redirecting to <a href=\"{}\">{}</a>.</body></html>",
+                          url, url));
+    }
+
+    /// Adds a cookie to the response. Cookies need to be set before anything
+    /// is written to the response. Any number of cookies can be set.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # use nickel::{Request, Response};
+    /// use nickel::cookies::CookieOptions;
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     let mut options = CookieOptions::new();
+    ///     options.http_only = true;
+    ///     response.set_cookie("session", "abc123", options);
+    ///     response.set_cookie("theme", "dark", CookieOptions::new());
+    ///     response.send("hello world");
+    /// }
+    /// ```
+    pub fn set_cookie(&mut self, name: &str, value: &str, options: CookieOptions)
+                      -> &mut Response<'a,'b> {
+        let cookie = cookies::serialize(name, value, &options);
+        header_utils::append_set_cookie(&mut self.origin.headers, cookie);
         self
     }
 
-    /// Writes a response
+    /// Tells the client to delete the cookie with the given name. The path and
+    /// domain of the options need to match the ones the cookie was set with.
+    pub fn clear_cookie(&mut self, name: &str, options: CookieOptions) -> &mut Response<'a,'b> {
+        let mut options = options;
+        options.max_age = Some(0);
+        options.expires = Some(time::at_utc(time::Timespec::new(0, 0)));
+        self.set_cookie(name, "", options)
+    }
+
+    /// Writes a response. It can be called several times to send the body
+    /// in parts. If it's called just once and nothing else is written, the
+    /// body is sent with its Content-Length once the middleware is done,
+    /// otherwise it's sent chunked.
     ///
     /// # Example
     /// ```{rust}
@@ -75,23 +333,233 @@ impl<'a, 'b> Response<'a, 'b> {
     /// }
     /// ```
     pub fn send<T: BytesContainer> (&mut self, text: T) {
-        // TODO: This needs to be more sophisticated to return the correct headers
-        // not just "some headers" :)
-        Response::set_headers(self.origin);
-        let _ = self.origin.write(text.container_as_bytes());
+        let body = text.container_as_bytes();
+        let etag = match self.etag {
+            Some(ref kind) if !self.is_started() => Some(compute_etag(kind, body)),
+            _ => None
+        };
+        match etag {
+            Some(etag) => if self.set_etag(etag.as_slice()) {
+                return self.not_modified()
+            },
+            None => {}
+        }
+
+        if !self.started && self.pending.is_none() {
+            self.pending = Some(body.to_vec());
+        } else {
+            let _ = self.write(body);
+        }
+    }
+
+    /// Enables ETags for the body passed to `send`. The ETag is computed from
+    /// the body and if it matches the `If-None-Match` header of a GET or HEAD
+    /// request, an empty `304 Not Modified` is sent instead of the body.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # use nickel::{Request, Response};
+    /// use nickel::ETag;
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.etag(ETag::Strong).send("a large document");
+    /// }
+    /// ```
+    pub fn etag(&mut self, kind: ETag) -> &mut Response<'a,'b> {
+        self.etag = Some(kind);
+        self
+    }
+
+    /// Sets the ETag header to the given value, which needs to be quoted,
+    /// e.g. `"\"v42\""` or `"W/\"v42\""`, and returns whether the client
+    /// has a fresh copy already, i.e. whether the ETag matches the
+    /// `If-None-Match` header of a GET or HEAD request. In that case
+    /// `not_modified` should be sent instead of the body.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # use nickel::{Request, Response};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     if response.set_etag("\"v42\"") {
+    ///         return response.not_modified()
+    ///     }
+    ///     response.send("version 42 of the document");
+    /// }
+    /// ```
+    pub fn set_etag(&mut self, etag: &str) -> bool {
+        self.origin.headers.extensions.insert("ETag".to_string(), etag.to_string());
+
+        match self.request.method {
+            http::method::Get | http::method::Head => {},
+            _ => return false
+        }
+        header_utils::find(&self.request.headers, "If-None-Match")
+            .map_or(false, |header| etag_matches(header.as_slice(), etag))
+    }
+
+    /// Sets the Last-Modified header to the given time and returns whether
+    /// the client has a fresh copy already, i.e. whether the resource didn't
+    /// change since the `If-Modified-Since` header of a GET or HEAD request.
+    /// In that case `not_modified` should be sent instead of the body.
+    ///
+    /// The header is ignored if the request has an `If-None-Match` header,
+    /// as ETags are the more precise validator.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # extern crate nickel;
+    /// # extern crate time;
+    /// # use nickel::{Request, Response};
+    /// # fn main() {}
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     let last_change = time::at_utc(time::Timespec::new(1415000000, 0));
+    ///     if response.set_last_modified(last_change) {
+    ///         return response.not_modified()
+    ///     }
+    ///     response.send("unchanged since November 2014");
+    /// }
+    /// ```
+    pub fn set_last_modified(&mut self, modified: time::Tm) -> bool {
+        let modified = modified.to_timespec().sec;
+        self.origin.headers.last_modified = Some(time::at_utc(time::Timespec::new(modified, 0)));
+
+        match self.request.method {
+            http::method::Get | http::method::Head => {},
+            _ => return false
+        }
+        let headers = &self.request.headers;
+        if header_utils::find(headers, "If-None-Match").is_some() {
+            return false
+        }
+        header_utils::find(headers, "If-Modified-Since")
+            .and_then(|header| parse_http_date(header.as_slice()))
+            .map_or(false, |since| modified <= since.to_timespec().sec)
+    }
+
+    /// Sends an empty `304 Not Modified` response.
+    pub fn not_modified(&mut self) {
+        self.origin.status = http::status::NotModified;
+        // A 304 doesn't have a body, not even an empty chunked one
+        self.origin.headers.content_length = Some(0);
+        self.origin.headers.content_type = None;
+        self.start();
+    }
+
+    /// Writes a chunk of the body and flushes it to the client right away.
+    /// Responses written this way are sent with `Transfer-Encoding: chunked`,
+    /// so the body doesn't need to be known upfront.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # use nickel::{Request, Response};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     for i in range(0u, 10) {
+    ///         response.write_chunk(format!("line {}\n", i).as_bytes()).unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn write_chunk(&mut self, data: &[u8]) -> IoResult<()> {
+        if !self.started {
+            self.origin.headers.content_length = None;
+            self.start();
+        }
+        try!(self.write(data));
+        self.flush()
+    }
+
+    /// Returns a `Writer` which sends every write as a chunk, see
+    /// `write_chunk`. This is handy to stream the output of anything that
+    /// writes to a `Writer`.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # use nickel::{Request, Response};
+    /// use std::io::util::copy;
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     let mut log = std::io::File::open(&Path::new("server.log"));
+    ///     copy(&mut log, &mut response.stream()).unwrap();
+    /// }
+    /// ```
+    pub fn stream<'c>(&'c mut self) -> ResponseStream<'c, 'a, 'b> {
+        ResponseStream { response: self }
+    }
+
+    /// Starts a stream of server-sent events. The response is sent as
+    /// `text/event-stream` and isn't cached by clients.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # use nickel::{Request, Response};
+    /// use std::io::timer::sleep;
+    /// use std::time::Duration;
+    ///
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     let mut events = response.start_events();
+    ///     for i in range(0u, 10) {
+    ///         if events.send_event("tick", i.to_string().as_slice()).is_err() {
+    ///             // the client went away
+    ///             return
+    ///         }
+    ///         sleep(Duration::seconds(1));
+    ///     }
+    /// }
+    /// ```
+    pub fn start_events<'c>(&'c mut self) -> EventStream<'c, 'a, 'b> {
+        self.origin.headers.content_type = Some(http::headers::content_type::MediaType {
+            type_: "text".to_string(),
+            subtype: "event-stream".to_string(),
+            parameters: vec![]
+        });
+        self.origin.headers.cache_control = Some("no-cache".to_string());
+        EventStream::new(self)
+    }
+
+    /// Serializes the given value to JSON and sends it as
+    /// `application/json`, along with its Content-Length.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # extern crate nickel;
+    /// # extern crate serialize;
+    /// # use nickel::{Request, Response};
+    /// # fn main() {}
+    /// #[deriving(Encodable)]
+    /// struct Person {
+    ///     first_name: String,
+    ///     last_name: String
+    /// }
+    ///
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     let person = Person {
+    ///         first_name: "John".to_string(),
+    ///         last_name: "Doe".to_string()
+    ///     };
+    ///     response.json(&person);
+    /// }
+    /// ```
+    pub fn json<'c, T: Encodable<json::Encoder<'c>, IoError>>(&mut self, value: &T) {
+        let body = json::encode(value);
+        self.origin.headers.content_type = Some(mimes::get_media_type(mimes::MediaType::Json));
+        self.origin.headers.content_length = Some(body.len());
+        let _ = self.write(body.as_bytes());
     }
 
     fn set_headers(response_writer: &mut http::server::ResponseWriter) {
         let ref mut headers = response_writer.headers;
         headers.date = Some(time::now_utc());
-
-        // we don't need to set this https://github.com/Ogeon/rustful/issues/3#issuecomment-44787613
-        headers.content_length = None;
-
         headers.server = Some(String::from_str("Nickel"));
     }
 
-    /// Writes a file to the output.
+    /// Writes a file to the output. The Content-Type is derived from the file
+    /// extension (see `mimes::from_extension`) and the Content-Length and Last-Modified headers are set
+    /// from the file's metadata. If the file didn't change since the date
+    /// of the `If-Modified-Since` header, an empty `304 Not Modified` is sent.
+    ///
+    /// Single byte ranges requested with a `Range` header are answered with
+    /// `206 Partial Content`, unsatisfiable ones with `416`. Requests for
+    /// several ranges get the whole file.
+    ///
+    /// If there is no file at the given path, a 404 response is sent and
+    /// a `FileNotFound` error is returned.
     ///
     /// # Example
     /// ```{rust}
@@ -102,17 +570,64 @@ impl<'a, 'b> Response<'a, 'b> {
     /// }
     /// ```
     pub fn send_file(&mut self, path: &Path) -> IoResult<()> {
+        let stat = path.stat();
+        if !stat.as_ref().map_or(false, |stat| stat.kind == TypeFile) {
+            self.origin.status = http::status::NotFound;
+            self.send("Not Found");
+            return Err(IoError {
+                kind: FileNotFound,
+                desc: "No file at the requested path",
+                detail: Some(format!("{}", path.display()))
+            })
+        }
+        let stat = stat.unwrap();
         let mut file = try!(File::open(path));
-        self.origin.headers.content_length = None;
 
-        self.origin.headers.content_type = path.extension_str()
-                                               .and_then(from_str)
-                                               .map(mimes::get_media_type);
-        self.origin.headers.server = Some(String::from_str("Nickel"));
-        copy(&mut file, self.origin)
+        if self.set_last_modified(to_tm(stat.modified)) {
+            self.not_modified();
+            return Ok(())
+        }
+
+        let size = stat.size;
+        let range = match self.request.method {
+            http::method::Get => header_utils::find(&self.request.headers, "Range")
+                                     .and_then(|header| parse_range(header.as_slice(), size)),
+            _ => None
+        };
+
+        self.origin.headers.extensions.insert("Accept-Ranges".to_string(), "bytes".to_string());
+        self.origin.headers.content_type = mimes::from_path(path);
+
+        match range {
+            None => {
+                self.origin.headers.content_length = Some(size as uint);
+                copy(&mut file, self)
+            },
+            Some(Ok((start, end))) => {
+                let len = end - start + 1;
+                self.origin.status = http::status::PartialContent;
+                self.origin.headers.content_length = Some(len as uint);
+                self.origin.headers.extensions.insert("Content-Range".to_string(),
+                                                      format!("bytes {}-{}/{}", start, end, size));
+                try!(file.seek(start as i64, SeekSet));
+                copy(&mut LimitReader::new(file, len as uint), self)
+            },
+            Some(Err(())) => {
+                self.origin.status = http::status::RequestedRangeNotSatisfiable;
+                self.origin.headers.content_type = None;
+                self.origin.headers.extensions.insert("Content-Range".to_string(),
+                                                      format!("bytes */{}", size));
+                self.send("Requested Range Not Satisfiable");
+                Ok(())
+            }
+        }
     }
 
-    /// Renders the given template bound with the given data.
+    /// Renders the given template bound with the given data. Templates are
+    /// compiled on first use and cached afterwards. Unless a Content-Type was
+    /// set already, the response is sent as `text/html; charset=utf-8`.
+    ///
+    /// If the template can't be loaded, a 500 response is sent instead.
     ///
     /// # Example
     /// ```{rust}
@@ -126,34 +641,276 @@ impl<'a, 'b> Response<'a, 'b> {
     /// ```
     pub fn render<'a, T: Encodable<Encoder<'a>, Error>>
         (&mut self, path: &'static str, data: &T) {
+            self.set_html_type();
+
+            let templates = self.templates;
+
             // Fast path doesn't need writer lock
-            match self.templates.read().get(&path) {
-                Some(t) => {
-                    let _ = t.render(self.origin, data);
+            match templates.cache.read().get(&path) {
+                Some(&(ref t, modified)) if !templates.is_stale(path, modified) => {
+                    let _ = t.render(self, data);
                     return
                 },
-                None => {}
+                _ => {}
             }
 
-            // We didn't find the template, get writers lock
-            let mut templates = self.templates.write();
-            // Search again incase there was a race to compile the template
-            let template = match templates.entry(path) {
-                Vacant(entry) => {
-                    let mut file = File::open(&Path::new(path));
-                    let raw_template =
-                        file.read_to_string()
-                            .ok()
-                            .expect(format!("Couldn't open the template file: {}",
-                                            path).as_slice());
-
-                    entry.set(mustache::compile_str(raw_template.as_slice()))
-                },
-                Occupied(entry) => entry.into_mut()
+            // We didn't find the template or it changed, get writers lock.
+            // A concurrent request might compile the template as well, but
+            // both results are equally good.
+            let mut cache = templates.cache.write();
+            let modified = Templates::modified(path);
+            let mut file = File::open(&Path::new(path));
+            let raw_template = match file.read_to_string() {
+                Ok(raw_template) => raw_template,
+                Err(err) => {
+                    error!("Couldn't open the template file {}: {}", path, err);
+                    self.origin.status = http::status::InternalServerError;
+                    self.send("Internal Server Error");
+                    return
+                }
             };
 
-            let _ = template.render(self.origin, data);
+            cache.insert(path, (mustache::compile_str(raw_template.as_slice()), modified));
+            let &(ref template, _) = cache.get(&path).unwrap();
+
+            let _ = template.render(self, data);
+    }
+
+    /// Renders the given template with the engine registered for its file
+    /// extension, falling back to the built-in mustache engine. Just like
+    /// `render`, the response is sent as HTML unless a Content-Type was set
+    /// already and a 500 response is sent if the template can't be loaded.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// # extern crate nickel;
+    /// # extern crate serialize;
+    /// # use nickel::{Request, Response};
+    /// # fn main() {}
+    /// use std::collections::TreeMap;
+    /// use serialize::json::{Object, ToJson};
+    ///
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     let mut data = TreeMap::new();
+    ///     data.insert("name".to_string(), "user".to_json());
+    ///     response.render_template("examples/assets/template.tpl", &Object(data));
+    /// }
+    /// ```
+    pub fn render_template(&mut self, path: &str, data: &Json) {
+        self.set_html_type();
+
+        let templates = self.templates;
+        match templates.get(path) {
+            Ok(template) => {
+                let _ = template.render(data, self);
+            },
+            Err(err) => {
+                error!("{}", err);
+                self.origin.status = http::status::InternalServerError;
+                self.send("Internal Server Error");
+            }
+        }
+    }
+
+    fn write_body(&mut self, buf: &[u8]) -> IoResult<()> {
+        // HEAD requests get the headers of a GET, including its length,
+        // but no body
+        if self.is_head() {
+            self.head_length += buf.len();
+            return Ok(())
+        }
+        self.bytes_written += buf.len() as u64;
+        self.origin.write(buf)
+    }
+
+    fn is_head(&self) -> bool {
+        self.request.method == http::method::Head
+    }
+
+    fn set_html_type(&mut self) {
+        if self.origin.headers.content_type.is_none() {
+            let mut media_type = mimes::get_media_type(mimes::MediaType::Html);
+            media_type.parameters.push(("charset".to_string(), "utf-8".to_string()));
+            self.origin.headers.content_type = Some(media_type);
+        }
+    }
+}
+
+/// Writing to the response starts it, i.e. runs the `before_send` hooks,
+/// and counts the bytes written.
+impl<'a, 'b> Writer for Response<'a, 'b> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.start();
+        self.write_body(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        // the headers of a HEAD response wait for the length of the body
+        if self.is_head() {
+            return Ok(())
+        }
+        self.origin.flush()
+    }
+}
+
+/// A `Writer` flushing every write to the client, see `Response::stream`.
+pub struct ResponseStream<'c, 'a: 'c, 'b: 'a> {
+    response: &'c mut Response<'a, 'b>
+}
+
+impl<'c, 'a, 'b> Writer for ResponseStream<'c, 'a, 'b> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.response.write_chunk(buf)
+    }
+}
+
+fn compute_etag(kind: &ETag, body: &[u8]) -> String {
+    let tag = format!("\"{:x}-{:016x}\"", body.len(), hash::hash(&body));
+    match *kind {
+        ETag::Strong => tag,
+        ETag::Weak => format!("W/{}", tag)
+    }
+}
+
+// Checks an If-None-Match header against an ETag, using the weak
+// comparison which is required for this header.
+fn etag_matches(header: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| {
+        let tag = tag.trim();
+        if tag.starts_with("W/") { tag.slice_from(2).to_string() } else { tag.to_string() }
+    };
+
+    let etag = opaque(etag);
+    header.trim() == "*" || header.split(',').any(|tag| opaque(tag) == etag)
+}
+
+fn status_from_code(code: u16) -> http::status::Status {
+    FromPrimitive::from_u16(code).unwrap_or_else(|| {
+        http::status::UnregisteredStatus(code, "Unknown".to_string())
+    })
+}
+
+// Parses the preferred date format of HTTP/1.1, e.g.
+// `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn parse_http_date(date: &str) -> Option<time::Tm> {
+    time::strptime(date.trim(), "%a, %d %b %Y %H:%M:%S GMT").ok()
+}
+
+// Parses a Range header with a single byte range, returning the first and
+// last byte of the range or an error if it's not satisfiable. Headers which
+// can't be parsed or ask for several ranges are ignored, which results in
+// the whole file being sent.
+fn parse_range(header: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let header = header.trim();
+    if !header.starts_with("bytes=") || header.contains_char(',') {
+        return None
+    }
+
+    let mut parts = header.slice_from(6).splitn(1, '-').map(|part| part.trim());
+    let (start, end) = match (parts.next(), parts.next()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return None
+    };
+
+    if size == 0 {
+        return Some(Err(()))
+    }
+
+    let (first, last) = match (from_str::<u64>(start), from_str::<u64>(end)) {
+        // the last n bytes
+        (None, Some(suffix)) if start.is_empty() => {
+            if suffix == 0 { return Some(Err(())) }
+            (size - cmp::min(suffix, size), size - 1)
+        },
+        (Some(start), None) if end.is_empty() => (start, size - 1),
+        (Some(start), Some(end)) if start <= end => (start, cmp::min(end, size - 1)),
+        _ => return None
+    };
+
+    if first >= size {
+        Some(Err(()))
+    } else {
+        Some(Ok((first, last)))
+    }
+}
+
+// Converts a file timestamp in milliseconds to a time::Tm
+fn to_tm(millis: u64) -> time::Tm {
+    let spec = time::Timespec::new((millis / 1000) as i64, ((millis % 1000) * 1_000_000) as i32);
+    time::at_utc(spec)
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c)
+        }
     }
+    escaped
+}
+
+#[test]
+fn compares_etags_weakly() {
+    assert!(etag_matches("\"abc\"", "\"abc\""));
+    assert!(etag_matches("\"xyz\", W/\"abc\"", "\"abc\""));
+    assert!(etag_matches("\"abc\"", "W/\"abc\""));
+    assert!(etag_matches("*", "\"abc\""));
+    assert!(!etag_matches("\"abcd\"", "\"abc\""));
+}
+
+#[test]
+fn computes_etags_from_the_body() {
+    let strong = compute_etag(&ETag::Strong, b"hello");
+    assert!(strong.as_slice().starts_with("\"5-"));
+    assert_eq!(strong, compute_etag(&ETag::Strong, b"hello"));
+    assert!(strong != compute_etag(&ETag::Strong, b"hellp"));
+    assert_eq!(compute_etag(&ETag::Weak, b"hello"), format!("W/{}", strong));
+}
+
+#[test]
+fn converts_status_codes() {
+    assert_eq!(status_from_code(404), http::status::NotFound);
+    let status = status_from_code(299);
+    assert_eq!(status.code(), 299);
+    assert_eq!(status.reason().as_slice(), "Unknown");
+}
+
+#[test]
+fn parses_http_dates() {
+    let date = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+    assert_eq!(date.to_timespec().sec, 784111777);
+    assert!(parse_http_date("yesterday").is_none());
+}
+
+#[test]
+fn parses_byte_ranges() {
+    assert_eq!(parse_range("bytes=0-499", 1000), Some(Ok((0, 499))));
+    assert_eq!(parse_range("bytes=500-", 1000), Some(Ok((500, 999))));
+    assert_eq!(parse_range("bytes=-200", 1000), Some(Ok((800, 999))));
+    assert_eq!(parse_range("bytes=900-2000", 1000), Some(Ok((900, 999))));
+    assert_eq!(parse_range("bytes=-2000", 1000), Some(Ok((0, 999))));
+    assert_eq!(parse_range("bytes=1000-", 1000), Some(Err(())));
+    assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+    assert_eq!(parse_range("bytes=5-1", 1000), None);
+    assert_eq!(parse_range("items=0-1", 1000), None);
+}
+
+#[test]
+fn converts_file_times() {
+    let tm = to_tm(1_415_000_000_500);
+    assert_eq!(tm.to_timespec(), time::Timespec::new(1_415_000_000, 500_000_000));
+}
+
+#[test]
+fn escapes_html() {
+    assert_eq!(escape_html("/search?q=<a>&b=\"c\"").as_slice(),
+               "/search?q=&lt;a&gt;&amp;b=&quot;c&quot;");
 }
 
 #[test]
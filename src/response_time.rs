@@ -0,0 +1,74 @@
+use http::server::ResponseWriter;
+use time;
+
+use request::Request;
+use response::{Response, ResponseHook};
+use middleware::{Continue, Middleware, MiddlewareResult};
+
+/// The header carrying the time it took to produce the response.
+pub const RESPONSE_TIME_HEADER: &'static str = "X-Response-Time";
+
+struct RequestStart {
+    ns: u64
+}
+
+/// A middleware measuring the time from entering the middleware stack until
+/// the response gets sent, which is reported in milliseconds in the
+/// `X-Response-Time` header, e.g. `X-Response-Time: 3.142ms`. Register it
+/// first to include the time spent in all other middleware.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, ResponseTime};
+///
+/// let mut server = Nickel::new();
+/// server.utilize(ResponseTime);
+/// ```
+#[deriving(Clone)]
+pub struct ResponseTime;
+
+impl Middleware for ResponseTime {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        let start = time::precise_time_ns();
+        req.map.insert(RequestStart { ns: start });
+        res.on_send(SetResponseTime { start: start });
+        Ok(Continue)
+    }
+}
+
+pub trait RequestTiming {
+    /// Returns the milliseconds since the request entered the `ResponseTime`
+    /// middleware, if it's in use.
+    fn elapsed_ms(&self) -> Option<f64>;
+}
+
+impl<'a, 'b> RequestTiming for Request<'a, 'b> {
+    fn elapsed_ms(&self) -> Option<f64> {
+        self.map.get::<RequestStart>().map(|start| to_ms(time::precise_time_ns() - start.ns))
+    }
+}
+
+struct SetResponseTime {
+    start: u64
+}
+
+impl ResponseHook for SetResponseTime {
+    fn before_send(&mut self, res: &mut ResponseWriter) {
+        let elapsed = to_ms(time::precise_time_ns() - self.start);
+        res.headers.extensions.insert(RESPONSE_TIME_HEADER.to_string(), format_ms(elapsed));
+    }
+}
+
+fn to_ms(ns: u64) -> f64 {
+    ns as f64 / 1_000_000.0
+}
+
+fn format_ms(ms: f64) -> String {
+    format!("{:.3}ms", ms)
+}
+
+#[test]
+fn formats_milliseconds() {
+    assert_eq!(format_ms(to_ms(3_141_592)).as_slice(), "3.142ms");
+    assert_eq!(format_ms(to_ms(0)).as_slice(), "0.000ms");
+}
@@ -0,0 +1,88 @@
+use std::mem;
+use std::sync::{Arc, RWLock};
+
+use request::Request;
+use response::Response;
+use middleware::{Middleware, MiddlewareResult};
+use super::Router;
+
+/// A router whose routes can be changed while the server is running, e.g.
+/// to add endpoints created through an admin page. Clones share the routes,
+/// so one clone goes into the middleware stack while others are kept to
+/// change it. Changes wait for the requests the router is handling.
+///
+/// # Example
+/// ```{rust}
+/// # extern crate http;
+/// # extern crate nickel;
+/// # fn main() {
+/// use nickel::{Nickel, Request, Response, HttpRouter, DynamicRouter};
+/// use http::method::Get;
+///
+/// fn handler(request: &Request, response: &mut Response) {
+///     response.send("A new endpoint");
+/// }
+///
+/// let router = DynamicRouter::new(Nickel::router());
+/// let mut server = Nickel::new();
+/// server.utilize(router.clone());
+///
+/// // e.g. in another task after the server started
+/// router.update(|router| router.get("/new", handler));
+/// router.update(|router| { router.remove_route(&Get, "/new"); });
+/// # }
+/// ```
+#[deriving(Clone)]
+pub struct DynamicRouter {
+    router: Arc<RWLock<Router>>
+}
+
+impl DynamicRouter {
+    pub fn new(router: Router) -> DynamicRouter {
+        DynamicRouter {
+            router: Arc::new(RWLock::new(router))
+        }
+    }
+
+    /// Changes the routes, which the requests arriving afterwards see.
+    pub fn update(&self, f: |&mut Router|) {
+        let mut router = self.router.write();
+        f(&mut *router);
+    }
+}
+
+impl Middleware for DynamicRouter {
+    fn invoke<'a, 'b>(&'a self, req: &mut Request<'b, 'a>, res: &mut Response)
+                        -> MiddlewareResult {
+        let router = self.router.read();
+
+        // The route result put into the request borrows from the router, which
+        // is only guaranteed to stay the same while the lock is held. So the
+        // result is removed from the request again before the lock is released.
+        let result = unsafe {
+            let router: &'a Router = mem::transmute(&*router);
+            router.invoke(req, res)
+        };
+        req.route_result = None;
+        result
+    }
+}
+
+#[test]
+fn shares_changes_between_clones() {
+    use http::method;
+    use router::HttpRouter;
+
+    fn handler(_request: &Request, response: &mut Response) {
+        let _ = response.origin.write("hello from foo".as_bytes());
+    }
+
+    let router = DynamicRouter::new(Router::new());
+    let clone = router.clone();
+
+    clone.update(|router| router.get("/new", handler));
+    assert!(router.router.read().match_route(&method::Get, "/new").is_some());
+
+    clone.update(|router| { router.remove_route(&method::Get, "/new"); });
+    assert!(router.router.read().match_route(&method::Get, "/new").is_none());
+}
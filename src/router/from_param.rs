@@ -0,0 +1,79 @@
+/// Types which can be made from the value of a route variable, which
+/// `Request::param_as` gives. Implementing it for the types of the
+/// application lets handlers get them right from the path.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Request, Response, FromParam};
+///
+/// struct Sku(String);
+///
+/// impl FromParam for Sku {
+///     fn from_param(value: &str) -> Option<Sku> {
+///         if value.len() == 8 && value.chars().all(|c| c.is_digit(10)) {
+///             Some(Sku(value.to_string()))
+///         } else {
+///             None
+///         }
+///     }
+/// }
+///
+/// // registered for "/products/:sku"
+/// fn handler(request: &Request, response: &mut Response) {
+///     match request.param_as::<Sku>("sku") {
+///         Ok(Sku(sku)) => response.send(format!("Product {}", sku)),
+///         Err(_) => response.send("No such product")
+///     }
+/// }
+/// ```
+pub trait FromParam {
+    /// Converts the percent-decoded value, or returns `None` if it's not a
+    /// valid value of the type.
+    fn from_param(value: &str) -> Option<Self>;
+}
+
+macro_rules! from_str_impl(
+    ($($ty:ty),+) => ($(
+        impl FromParam for $ty {
+            fn from_param(value: &str) -> Option<$ty> {
+                from_str(value)
+            }
+        }
+    )+)
+)
+
+from_str_impl!(uint, u8, u16, u32, u64, int, i8, i16, i32, i64, f32, f64, char)
+
+impl FromParam for bool {
+    fn from_param(value: &str) -> Option<bool> {
+        match value {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None
+        }
+    }
+}
+
+impl FromParam for String {
+    fn from_param(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+}
+
+impl<T: FromParam> FromParam for Option<T> {
+    /// Gives `None` for values which aren't valid instead of failing.
+    fn from_param(value: &str) -> Option<Option<T>> {
+        Some(FromParam::from_param(value))
+    }
+}
+
+#[test]
+fn converts_params() {
+    assert_eq!(FromParam::from_param("42"), Some(42u));
+    assert_eq!(FromParam::from_param("-42"), None::<uint>);
+    assert_eq!(FromParam::from_param("-42"), Some(-42i));
+    assert_eq!(FromParam::from_param("1"), Some(true));
+    assert_eq!(FromParam::from_param("yes"), None::<bool>);
+    assert_eq!(FromParam::from_param("abc"), Some("abc".to_string()));
+    assert_eq!(FromParam::from_param("abc"), Some(None::<u8>));
+}
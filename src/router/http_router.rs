@@ -1,5 +1,7 @@
-use http::method::{ Method, Get, Post, Put, Delete };
+use http::method::{ Method, Get, Post, Put, Delete, Patch, Head, Options };
 use router::RequestHandler;
+use router::request_handler::Redirect;
+use std::sync::Arc;
 
 pub trait HttpRouter {
     /// Registers a handler to be used for a specified method.
@@ -18,7 +20,7 @@ pub trait HttpRouter {
     ///         response.send("Get request!");
     ///     };
     ///     fn modify_handler(request: &Request, response: &mut Response) {
-    ///         response.send(format!("Method is: {}", request.origin.method));
+    ///         response.send(format!("Method is: {}", request.method()));
     ///     };
     ///
     ///     let mut server = Nickel::new();
@@ -168,4 +170,125 @@ pub trait HttpRouter {
     fn delete<H: RequestHandler>(&mut self, uri: &str, handler: H) {
         self.add_route(Delete, uri, handler);
     }
+
+    /// Registers a handler to be used for a specific PATCH request.
+    /// A handler added through this API will be attached to the default router.
+    /// Consider creating the router middleware manually for advanced functionality.
+    ///
+    /// Take a look at `get(...)` for a more detailed description.
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.send("This matches a PATCH request to /a/patch/request");
+    /// };
+    ///
+    /// let mut server = Nickel::new();
+    /// server.patch("/a/patch/request", handler);
+    /// ```
+    fn patch<H: RequestHandler>(&mut self, uri: &str, handler: H) {
+        self.add_route(Patch, uri, handler);
+    }
+
+    /// Registers a handler to be used for a specific HEAD request.
+    /// A handler added through this API will be attached to the default router.
+    /// Consider creating the router middleware manually for advanced functionality.
+    ///
+    /// Take a look at `get(...)` for a more detailed description.
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.send("");
+    /// };
+    ///
+    /// let mut server = Nickel::new();
+    /// server.head("/a/head/request", handler);
+    /// ```
+    fn head<H: RequestHandler>(&mut self, uri: &str, handler: H) {
+        self.add_route(Head, uri, handler);
+    }
+
+    /// Registers a handler to be used for a specific OPTIONS request.
+    /// A handler added through this API will be attached to the default router.
+    /// Consider creating the router middleware manually for advanced functionality.
+    ///
+    /// Take a look at `get(...)` for a more detailed description.
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.send("This matches an OPTIONS request to /a/options/request");
+    /// };
+    ///
+    /// let mut server = Nickel::new();
+    /// server.options("/a/options/request", handler);
+    /// ```
+    fn options<H: RequestHandler>(&mut self, uri: &str, handler: H) {
+        self.add_route(Options, uri, handler);
+    }
+
+    /// Registers a handler for several methods at once.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// extern crate nickel;
+    /// extern crate http;
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    /// use http::method::{Get, Post};
+    ///
+    /// fn main() {
+    ///     fn form_handler(request: &Request, response: &mut Response) {
+    ///         response.send(format!("Method is: {}", request.method()));
+    ///     };
+    ///
+    ///     let mut server = Nickel::new();
+    ///     server.add(&[Get, Post], "/form", form_handler);
+    /// }
+    /// ```
+    fn add<H: RequestHandler>(&mut self, methods: &[Method], uri: &str, handler: H) {
+        let handler = Arc::new(handler);
+        for method in methods.iter() {
+            self.add_route(method.clone(), uri, handler.clone());
+        }
+    }
+
+    /// Registers a handler for the methods GET, POST, PUT, PATCH and DELETE.
+    /// HEAD and OPTIONS requests are answered by the router as usual.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.send("This matches all requests to /webhook");
+    /// };
+    ///
+    /// let mut server = Nickel::new();
+    /// server.any("/webhook", handler);
+    /// ```
+    fn any<H: RequestHandler>(&mut self, uri: &str, handler: H) {
+        self.add(&[Get, Post, Put, Patch, Delete], uri, handler);
+    }
+
+    /// Registers a GET route redirecting to another path with the given
+    /// status, e.g. 301 for moved pages, so HEAD requests are redirected
+    /// as well. Variables of the route can be used in the new path.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, HttpRouter};
+    ///
+    /// let mut server = Nickel::new();
+    /// server.redirect("/old-path", "/new-path", 301);
+    /// server.redirect("/users/:id", "/people/:id", 301);
+    /// ```
+    fn redirect(&mut self, uri: &str, location: &str, status: u16) {
+        self.add_route(Get, uri, Redirect::new(location, status));
+    }
 }
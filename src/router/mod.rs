@@ -1,55 +1,580 @@
 //!Router asigns handlers to paths and resolves them per request
 pub use self::http_router::HttpRouter;
-pub use self::request_handler::{RequestHandler, ResponseFinalizer};
-pub use self::router::{Router, Route, RouteResult};
+pub use self::request_handler::{RequestHandler, ResponseFinalizer, FnHandler};
+pub use self::router::{Router, Route, RouteResult, RouteGuard, RouteInfo, Routes, ParamValue, ParamError, TrailingSlash, ShadowedRoutes};
+pub use self::dynamic_router::DynamicRouter;
+pub use self::from_param::FromParam;
 pub mod http_router;
 pub mod request_handler;
 
 pub mod router;
+mod route_index;
+mod dynamic_router;
+mod from_param;
 
 /// The path_utils collects some small helper methods that operate on the path
 mod path_utils {
-    use regex::Regex;
+    use regex::{Regex, Captures, quote};
+    use url::percent_encoding::{utf8_percent_encode, percent_decode};
+    use url::percent_encoding::{DEFAULT_ENCODE_SET, USERINFO_ENCODE_SET};
     use std::collections::HashMap;
+    use super::ParamValue;
 
-    // matches named variables (e.g. :userid)
-    static REGEX_VAR_SEQ: Regex                 = regex!(r":([,a-zA-Z0-9_-]*)");
     static VAR_SEQ:&'static str                 = "[,a-zA-Z0-9_-]*";
     static VAR_SEQ_WITH_SLASH:&'static str      = "[,/a-zA-Z0-9_-]*";
-    static VAR_SEQ_WITH_CAPTURE:&'static str    = "([,a-zA-Z0-9%_-]*)";
+    static SEGMENT_SEQ:&'static str             = "[,a-zA-Z0-9%_-]*";
+    static WILDCARD_SEQ:&'static str            = "[,a-zA-Z0-9%_.-]*";
+    static DOUBLE_WILDCARD_SEQ:&'static str     = "[,/a-zA-Z0-9%_.-]*";
+    static UINT_SEQ:&'static str                = "[0-9]+";
+    static INT_SEQ:&'static str                 = "-?[0-9]+";
+    static FLOAT_SEQ:&'static str               = "-?[0-9]+(?:\\.[0-9]+)?";
+    static BOOL_SEQ:&'static str                = "true|false";
+    static HOST_LABEL_SEQ:&'static str          = "[a-zA-Z0-9-]+";
+    // one or more labels of a host name
+    static HOST_WILDCARD_SEQ:&'static str       = "[a-zA-Z0-9-]+(?:\\.[a-zA-Z0-9-]+)*";
     // matches request params (e.g. ?foo=true&bar=false)
-    static REGEX_PARAM_SEQ:&'static str         = "(\\?[a-zA-Z0-9%_=&-]*)?";
+    static REGEX_PARAM_SEQ:&'static str         = "(?:\\?[a-zA-Z0-9%_=&-]*)?";
     static REGEX_START:&'static str             = "^";
     static REGEX_END:&'static str               = "$";
+
+    /// The type a variable is declared with, e.g. `:id:uint`. Routes only
+    /// match if the segment can be converted to it.
+    #[deriving(Clone, PartialEq, Show)]
+    pub enum ParamType {
+        Str,
+        Uint,
+        Int,
+        Float,
+        Bool
+    }
+
+    impl ParamType {
+        fn from_name(name: &str) -> ParamType {
+            match name {
+                "str" => ParamType::Str,
+                "uint" => ParamType::Uint,
+                "int" => ParamType::Int,
+                "float" => ParamType::Float,
+                "bool" => ParamType::Bool,
+                _ => panic!("Unknown route parameter type: {}", name)
+            }
+        }
+
+        fn pattern(&self) -> &'static str {
+            match *self {
+                ParamType::Str => SEGMENT_SEQ,
+                ParamType::Uint => UINT_SEQ,
+                ParamType::Int => INT_SEQ,
+                ParamType::Float => FLOAT_SEQ,
+                ParamType::Bool => BOOL_SEQ
+            }
+        }
+
+        // Converts a captured segment, plain strings are kept as they are.
+        // Fails if the value doesn't fit, e.g. a uint with 30 digits.
+        pub fn convert(&self, value: &str) -> Result<Option<ParamValue>, ()> {
+            let converted = match *self {
+                ParamType::Str => return Ok(None),
+                ParamType::Uint => from_str(value).map(ParamValue::Uint),
+                ParamType::Int => from_str(value).map(ParamValue::Int),
+                ParamType::Float => from_str(value).map(ParamValue::Float),
+                ParamType::Bool => from_str(value).map(ParamValue::Bool)
+            };
+            converted.map(|value| Some(value)).ok_or(())
+        }
+    }
+
+    // What a variable or wildcard matches
+    #[deriving(PartialEq, Show)]
+    enum Matcher {
+        Typed(ParamType),
+        // a custom regex, e.g. :slug([a-z0-9-]+)
+        Pattern(String),
+        Wildcard,
+        DoubleWildcard
+    }
+
+    #[deriving(PartialEq, Show)]
+    enum Token {
+        // literal parts are used as regex as they are
+        Literal(String),
+        // named variables (e.g. :userid, :userid:uint or :userid([0-9]+)) as
+        // well as simple (*) and double (**) wildcards, which may be named
+        // too (e.g. *file or **path)
+        Variable(String, Matcher),
+        // an optional part, e.g. (/:format)? or /:page?
+        Optional(Vec<Token>)
+    }
+
+    impl Token {
+        // anonymous wildcards don't capture anything
+        fn is_capture(&self) -> bool {
+            match *self {
+                Token::Variable(ref name, Matcher::Wildcard) |
+                Token::Variable(ref name, Matcher::DoubleWildcard) => !name.is_empty(),
+                Token::Variable(..) => true,
+                Token::Literal(..) | Token::Optional(..) => false
+            }
+        }
+    }
+
+    fn is_var_char(c: char) -> bool {
+        c.is_ascii() && (c.is_alphanumeric() || c == ',' || c == '_' || c == '-')
+    }
+
+    fn is_wildcard_char(c: char) -> bool {
+        c.is_ascii() && (c.is_alphanumeric() || c == '_')
+    }
+
+    fn take_while(chars: &[char], pos: &mut uint, predicate: |char| -> bool) -> String {
+        let start = *pos;
+        while *pos < chars.len() && predicate(chars[*pos]) {
+            *pos += 1;
+        }
+        String::from_chars(chars[start..*pos])
+    }
+
+    // Takes the regex between the parentheses starting at `pos`, which may
+    // contain groups, escaped characters and character classes of its own.
+    fn take_group(route_path: &str, chars: &[char], pos: &mut uint) -> String {
+        let start = *pos + 1;
+        let mut depth = 0u;
+        let mut in_class = false;
+
+        while *pos < chars.len() {
+            match chars[*pos] {
+                '\\' => *pos += 1,
+                '[' => in_class = true,
+                ']' => in_class = false,
+                '(' if !in_class => depth += 1,
+                ')' if !in_class => {
+                    depth -= 1;
+                    if depth == 0 {
+                        *pos += 1;
+                        return String::from_chars(chars[start..*pos - 1])
+                    }
+                },
+                _ => {}
+            }
+            *pos += 1;
+        }
+
+        panic!("Unbalanced parentheses in route {}", route_path)
+    }
+
+    fn tokenize (route_path: &str) -> Vec<Token> {
+        let chars: Vec<char> = route_path.chars().collect();
+        tokenize_chars(route_path, chars.as_slice())
+    }
+
+    fn tokenize_chars (route_path: &str, chars: &[char]) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut pos = 0u;
+
+        while pos < chars.len() {
+            let c = chars[pos];
+
+            // a group followed by ? is optional, other groups are just regex
+            if c == '(' {
+                let mut end = pos;
+                let group = take_group(route_path, chars, &mut end);
+                if end < chars.len() && chars[end] == '?' {
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(literal));
+                        literal = String::new();
+                    }
+                    let group: Vec<char> = group.as_slice().chars().collect();
+                    tokens.push(Token::Optional(tokenize_chars(route_path, group.as_slice())));
+                    pos = end + 1;
+                    continue
+                }
+            }
+
+            if c != ':' && c != '*' {
+                literal.push(c);
+                pos += 1;
+                continue
+            }
+            pos += 1;
+
+            let variable = if c == ':' {
+                let name = take_while(chars, &mut pos, |c| is_var_char(c));
+                let next = if pos < chars.len() { Some(chars[pos]) } else { None };
+                // a group followed by ? is an optional part after the
+                // variable, e.g. :id(.:format)?, rather than its regex
+                let optional_group = next == Some('(') && {
+                    let mut end = pos;
+                    take_group(route_path, chars, &mut end);
+                    end < chars.len() && chars[end] == '?'
+                };
+                let matcher = match next {
+                    Some('(') if !optional_group => {
+                        Matcher::Pattern(take_group(route_path, chars, &mut pos))
+                    },
+                    Some(':') if pos + 1 < chars.len() && chars[pos + 1].is_lowercase() => {
+                        pos += 1;
+                        let type_name = take_while(chars, &mut pos, |c| c.is_lowercase());
+                        Matcher::Typed(ParamType::from_name(type_name.as_slice()))
+                    },
+                    _ => Matcher::Typed(ParamType::Str)
+                };
+                Token::Variable(name, matcher)
+            } else {
+                let matcher = if pos < chars.len() && chars[pos] == '*' {
+                    pos += 1;
+                    Matcher::DoubleWildcard
+                } else {
+                    Matcher::Wildcard
+                };
+                let name = take_while(chars, &mut pos, |c| is_wildcard_char(c));
+                Token::Variable(name, matcher)
+            };
+
+            // a variable marked with ? is optional, along with the slash in
+            // front of it, e.g. /articles/:page? matches /articles, too
+            if c == ':' && pos < chars.len() && chars[pos] == '?' {
+                pos += 1;
+                let mut optional = Vec::new();
+                if literal.as_slice().ends_with("/") {
+                    literal.pop();
+                    optional.push(Token::Literal("/".to_string()));
+                }
+                optional.push(variable);
+
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(literal));
+                    literal = String::new();
+                }
+                tokens.push(Token::Optional(optional));
+            } else {
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(literal));
+                    literal = String::new();
+                }
+                tokens.push(variable);
+            }
+        }
+
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        tokens
+    }
+
     pub fn create_regex (route_path: &str) -> Regex {
-        let updated_path =
-            route_path.to_string()
-                      // first mark all double wildcards for replacement.
-                      // We can't directly replace them since the replacement
-                      // does contain the * symbol as well, which would get
-                      // overwritten with the next replace call
-                      .replace("**", "___DOUBLE_WILDCARD___")
-                      // then replace the regular wildcard symbols (*) with the
-                      // appropriate regex
-                      .replace("*", VAR_SEQ)
-                      // now replace the previously marked double wild cards (**)
-                      .replace("___DOUBLE_WILDCARD___", VAR_SEQ_WITH_SLASH);
-
-        // then replace the variable symbols (:variable) with the appropriate regex
-        let result = [REGEX_START,
-                      REGEX_VAR_SEQ.replace_all(updated_path.as_slice(),
-                                                VAR_SEQ_WITH_CAPTURE)
-                                   .as_slice(),
-                      REGEX_PARAM_SEQ,
-                      REGEX_END].concat();
-
-        Regex::new(result.as_slice()).ok().unwrap()
+        build_regex(route_path, false)
+    }
+
+    // the literal parts and custom patterns ignore the case as well
+    pub fn create_case_insensitive_regex (route_path: &str) -> Regex {
+        build_regex(route_path, true)
+    }
+
+    fn build_regex (route_path: &str, ignore_case: bool) -> Regex {
+        // replace the variable symbols (:variable) and the wildcard symbols
+        // (*, **, *name, **name) with the appropriate regex. Only variables and
+        // named wildcards introduce a capture group, which is named after
+        // its position so groups inside custom patterns don't shift it.
+        let mut result = String::from_str(if ignore_case { "(?i)" } else { "" });
+        result.push_str(REGEX_START);
+        push_regex(tokenize(route_path).as_slice(), &mut result, &mut 0u);
+        result.push_str(REGEX_PARAM_SEQ);
+        result.push_str(REGEX_END);
+
+        match Regex::new(result.as_slice()) {
+            Ok(regex) => regex,
+            Err(err) => panic!("Invalid route {}: {}", route_path, err)
+        }
+    }
+
+    fn push_regex (tokens: &[Token], result: &mut String, index: &mut uint) {
+        for token in tokens.iter() {
+            let pattern = match *token {
+                Token::Literal(ref literal) => {
+                    result.push_str(literal.as_slice());
+                    continue
+                },
+                Token::Optional(ref tokens) => {
+                    result.push_str("(?:");
+                    push_regex(tokens.as_slice(), result, index);
+                    result.push_str(")?");
+                    continue
+                },
+                Token::Variable(ref name, ref matcher) => match *matcher {
+                    Matcher::Typed(ref param_type) => param_type.pattern().to_string(),
+                    Matcher::Pattern(ref pattern) => pattern.clone(),
+                    Matcher::Wildcard if name.is_empty() => VAR_SEQ.to_string(),
+                    Matcher::Wildcard => WILDCARD_SEQ.to_string(),
+                    Matcher::DoubleWildcard if name.is_empty() => VAR_SEQ_WITH_SLASH.to_string(),
+                    Matcher::DoubleWildcard => DOUBLE_WILDCARD_SEQ.to_string()
+                }
+            };
+
+            if token.is_capture() {
+                result.push_str(format!("(?P<{}>{})", capture_name(*index), pattern).as_slice());
+                *index += 1;
+            } else {
+                result.push_str(pattern.as_slice());
+            }
+        }
+    }
+
+    // The parts of a host pattern, e.g. :tenant.*.example.com
+    #[deriving(PartialEq, Show)]
+    enum HostPart {
+        Literal(String),
+        // a single label
+        Variable(String),
+        // any number of labels
+        Wildcard
+    }
+
+    fn split_host (pattern: &str) -> Vec<HostPart> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut pos = 0u;
+
+        while pos < chars.len() {
+            let c = chars[pos];
+            pos += 1;
+            if c != ':' && c != '*' {
+                literal.push(c);
+                continue
+            }
+
+            if !literal.is_empty() {
+                parts.push(HostPart::Literal(literal));
+                literal = String::new();
+            }
+            if c == ':' {
+                parts.push(HostPart::Variable(take_while(chars.as_slice(), &mut pos, |c| is_wildcard_char(c))));
+            } else {
+                parts.push(HostPart::Wildcard);
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(HostPart::Literal(literal));
+        }
+        parts
+    }
+
+    // host patterns are matched case-insensitively, a * stands for any
+    // number of labels, e.g. *.example.com, and a variable for a single
+    // label, e.g. :tenant.example.com
+    pub fn create_host_regex (pattern: &str) -> Regex {
+        let mut result = String::from_str("(?i)^");
+        for part in split_host(pattern).iter() {
+            match *part {
+                HostPart::Literal(ref literal) => result.push_str(quote(literal.as_slice()).as_slice()),
+                HostPart::Variable(_) => result.push_str(format!("({})", HOST_LABEL_SEQ).as_slice()),
+                HostPart::Wildcard => result.push_str(HOST_WILDCARD_SEQ)
+            }
+        }
+        result.push_str(REGEX_END);
+
+        match Regex::new(result.as_slice()) {
+            Ok(regex) => regex,
+            Err(err) => panic!("Invalid host {}: {}", pattern, err)
+        }
+    }
+
+    // the names of the variables of a host pattern in the order of their
+    // captures
+    pub fn get_host_variables (pattern: &str) -> Vec<String> {
+        split_host(pattern).into_iter().filter_map(|part| {
+            match part {
+                HostPart::Variable(name) => Some(name),
+                _ => None
+            }
+        }).collect()
+    }
+
+    // whether the route path only matches itself, i.e. it has no variables,
+    // wildcards or regex syntax
+    pub fn is_static (route_path: &str) -> bool {
+        !route_path.chars().any(|c| "\\^$|?*+()[]{}:".contains_char(c))
+    }
+
+    // percent-decodes a captured value, which fails for malformed escapes
+    // and values which aren't UTF-8
+    pub fn decode (value: &str) -> Option<String> {
+        let bytes = value.as_bytes();
+        let is_hex = |b: u8| (b as char).to_digit(16).is_some();
+
+        // percent_decode passes malformed escapes on as they are
+        let mut pos = 0u;
+        while pos < bytes.len() {
+            if bytes[pos] == b'%' {
+                if pos + 2 >= bytes.len() || !is_hex(bytes[pos + 1]) || !is_hex(bytes[pos + 2]) {
+                    return None
+                }
+                pos += 3;
+            } else {
+                pos += 1;
+            }
+        }
+
+        String::from_utf8(percent_decode(bytes)).ok()
+    }
+
+    fn capture_name (index: uint) -> String {
+        format!("v{}", index)
+    }
+
+    // returns the value captured for the variable at the given position
+    pub fn capture<'t> (captures: &Captures<'t>, index: uint) -> &'t str {
+        captures.name(capture_name(index).as_slice())
     }
 
     pub fn get_variable_info (route_path: &str) -> HashMap<String, uint> {
-        REGEX_VAR_SEQ.captures_iter(route_path)
+        get_variables(route_path).into_iter()
              .enumerate()
-             .map(|(i, matched)| (matched.at(1).to_string(), i))
+             .map(|(i, (name, _))| (name, i))
              .collect()
     }
+
+    // the types of the variables in the order of their captures
+    pub fn get_variable_types (route_path: &str) -> Vec<ParamType> {
+        get_variables(route_path).into_iter().map(|(_, param_type)| param_type).collect()
+    }
+
+    fn get_variables (route_path: &str) -> Vec<(String, ParamType)> {
+        let mut variables = Vec::new();
+        collect_variables(tokenize(route_path).into_iter(), &mut variables);
+        variables
+    }
+
+    fn collect_variables<I: Iterator<Token>> (tokens: I, variables: &mut Vec<(String, ParamType)>) {
+        for token in tokens {
+            let capture = token.is_capture();
+            match token {
+                Token::Optional(tokens) => collect_variables(tokens.into_iter(), variables),
+                Token::Variable(name, Matcher::Typed(param_type)) => variables.push((name, param_type)),
+                Token::Variable(name, _) => if capture { variables.push((name, ParamType::Str)) },
+                Token::Literal(..) => {}
+            }
+        }
+    }
+
+    // builds a concrete path by substituting the variables and named wildcards
+    // of the route path. Returns `None` if a value is missing or the route
+    // path contains anonymous wildcards, which can't be filled in. Optional
+    // parts are left out if their values are missing or empty.
+    pub fn fill_variables (route_path: &str, params: &[(&str, &str)]) -> Option<String> {
+        let mut path = String::new();
+        if fill(tokenize(route_path).as_slice(), params, false, &mut path) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    fn fill (tokens: &[Token], params: &[(&str, &str)], optional: bool, path: &mut String) -> bool {
+        for token in tokens.iter() {
+            match *token {
+                Token::Literal(ref literal) => path.push_str(literal.as_slice()),
+                Token::Optional(ref tokens) => {
+                    let mut part = String::new();
+                    if fill(tokens.as_slice(), params, true, &mut part) {
+                        path.push_str(part.as_slice());
+                    }
+                },
+                Token::Variable(ref name, ref matcher) => {
+                    match params.iter().find(|&&(key, _)| key == name.as_slice()) {
+                        Some(&(_, value)) if !name.is_empty() && !(optional && value.is_empty()) => {
+                            path.push_str(encode(value, *matcher == Matcher::DoubleWildcard).as_slice())
+                        },
+                        _ => return false
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // percent-encodes a value filled into a path. Slashes only stay as they
+    // are for double wildcards, which span several segments.
+    fn encode (value: &str, keep_slashes: bool) -> String {
+        // the encode sets leave `%` alone, which would turn into an escape
+        let value = value.replace("%", "%25");
+        if keep_slashes {
+            utf8_percent_encode(value.as_slice(), DEFAULT_ENCODE_SET)
+        } else {
+            utf8_percent_encode(value.as_slice(), USERINFO_ENCODE_SET)
+        }
+    }
+
+    #[test]
+    fn encodes_filled_values () {
+        assert_eq!(fill_variables("/users/:name", &[("name", "John Doe")]),
+                   Some("/users/John%20Doe".to_string()));
+        assert_eq!(fill_variables("/users/:name", &[("name", "a/b?c#d")]),
+                   Some("/users/a%2Fb%3Fc%23d".to_string()));
+        assert_eq!(fill_variables("/files/**path", &[("path", "my docs/100%.txt")]),
+                   Some("/files/my%20docs/100%25.txt".to_string()));
+        assert_eq!(fill_variables("/users/:name", &[("name", "Jürgen")]),
+                   Some("/users/J%C3%BCrgen".to_string()));
+    }
+
+    #[test]
+    fn decodes_captured_values () {
+        assert_eq!(decode("J%C3%BCrgen"), Some("Jürgen".to_string()));
+        assert_eq!(decode("a%2Fb"), Some("a/b".to_string()));
+        assert_eq!(decode("plain"), Some("plain".to_string()));
+        assert_eq!(decode("100%"), None);
+        assert_eq!(decode("%zz"), None);
+        assert_eq!(decode("%FF"), None);
+    }
+
+    #[test]
+    fn splits_host_patterns () {
+        assert_eq!(split_host(":tenant.*.example.com"), vec![
+            HostPart::Variable("tenant".to_string()),
+            HostPart::Literal(".".to_string()),
+            HostPart::Wildcard,
+            HostPart::Literal(".example.com".to_string())
+        ]);
+    }
+
+    #[test]
+    fn tokenizes_routes () {
+        assert_eq!(tokenize("/users/:id:uint/*/**path"), vec![
+            Token::Literal("/users/".to_string()),
+            Token::Variable("id".to_string(), Matcher::Typed(ParamType::Uint)),
+            Token::Literal("/".to_string()),
+            Token::Variable("".to_string(), Matcher::Wildcard),
+            Token::Literal("/".to_string()),
+            Token::Variable("path".to_string(), Matcher::DoubleWildcard)
+        ]);
+
+        assert_eq!(tokenize("/posts/:slug([a-z(]+|(x)\\))/edit"), vec![
+            Token::Literal("/posts/".to_string()),
+            Token::Variable("slug".to_string(), Matcher::Pattern("[a-z(]+|(x)\\)".to_string())),
+            Token::Literal("/edit".to_string())
+        ]);
+
+        assert_eq!(tokenize("/articles/:page?(.:format)?"), vec![
+            Token::Literal("/articles".to_string()),
+            Token::Optional(vec![
+                Token::Literal("/".to_string()),
+                Token::Variable("page".to_string(), Matcher::Typed(ParamType::Str))
+            ]),
+            Token::Optional(vec![
+                Token::Literal(".".to_string()),
+                Token::Variable("format".to_string(), Matcher::Typed(ParamType::Str))
+            ])
+        ]);
+
+        assert_eq!(tokenize("/reports/:id(\\.:format([a-z]+))?"), vec![
+            Token::Literal("/reports/".to_string()),
+            Token::Variable("id".to_string(), Matcher::Typed(ParamType::Str)),
+            Token::Optional(vec![
+                Token::Literal("\\.".to_string()),
+                Token::Variable("format".to_string(), Matcher::Pattern("[a-z]+".to_string()))
+            ])
+        ]);
+    }
 }
@@ -3,9 +3,12 @@ use response::Response;
 use http::status;
 use http::headers;
 use std::fmt::Show;
-use middleware::{MiddlewareResult, Halt};
+use middleware::{MiddlewareResult, Action, Halt};
+use nickel_error::NickelError;
 use serialize::json;
 use mimes::MediaType;
+use std::sync::Arc;
+use super::path_utils;
 
 /// Handles a HTTP request
 /// This is pre-implemented for any function which takes a
@@ -38,6 +41,90 @@ impl<T: Send + Sync, R: ResponseFinalizer + 'static> RequestHandler for (fn(&Req
     }
 }
 
+impl RequestHandler for Box<RequestHandler + Send + Sync> {
+    fn handle(&self, req: &Request, res: &mut Response) -> MiddlewareResult {
+        (**self).handle(req, res)
+    }
+}
+
+impl<H: RequestHandler> RequestHandler for Arc<H> {
+    fn handle(&self, req: &Request, res: &mut Response) -> MiddlewareResult {
+        (**self).handle(req, res)
+    }
+}
+
+/// Turns a closure into a `RequestHandler`. Other than plain functions,
+/// closures can capture configuration or shared state for the handler.
+///
+/// # Example
+///
+/// ```{rust}
+/// # #![feature(unboxed_closures)]
+/// # extern crate nickel;
+/// # fn main() {
+/// use nickel::{Nickel, Request, Response, HttpRouter, FnHandler};
+///
+/// let greeting = "Hello from a closure".to_string();
+/// let mut server = Nickel::new();
+/// server.get("/", FnHandler::new(move |&: _req: &Request, _res: &mut Response| {
+///     greeting.clone()
+/// }));
+/// # }
+/// ```
+pub struct FnHandler<F> {
+    f: F
+}
+
+impl<F> FnHandler<F> {
+    pub fn new(f: F) -> FnHandler<F> {
+        FnHandler { f: f }
+    }
+}
+
+impl<R, F> RequestHandler for FnHandler<F>
+        where R: ResponseFinalizer, F: Fn(&Request, &mut Response) -> R + Send + Sync {
+    fn handle(&self, req: &Request, res: &mut Response) -> MiddlewareResult {
+        let r = (self.f)(req, res);
+        r.respond(res)
+    }
+}
+
+/// Redirects to a fixed location, see `HttpRouter::redirect`. Variables of
+/// the route which appear in the location are filled in, e.g. a route for
+/// `/users/:id` can redirect to `/people/:id`.
+pub struct Redirect {
+    location: String,
+    status: status::Status
+}
+
+impl Redirect {
+    pub fn new(location: &str, status: u16) -> Redirect {
+        match FromPrimitive::from_u16(status) {
+            Some(status) => Redirect { location: location.to_string(), status: status },
+            // This is a logic error
+            None => panic!("Bad status code")
+        }
+    }
+}
+
+impl RequestHandler for Redirect {
+    fn handle(&self, req: &Request, res: &mut Response) -> MiddlewareResult {
+        let params: Vec<(&str, &str)> = match req.route_result {
+            Some(ref route_result) => route_result.route.variables.keys().map(|name| {
+                (name.as_slice(), route_result.param(name.as_slice()))
+            }).collect(),
+            None => Vec::new()
+        };
+
+        // locations which aren't route paths, e.g. on other hosts, are taken
+        // as they are
+        let location = path_utils::fill_variables(self.location.as_slice(), params.as_slice())
+                                  .unwrap_or_else(|| self.location.clone());
+        res.redirect_with(self.status.clone(), location.as_slice());
+        Ok(Halt)
+    }
+}
+
 /// This trait provides convenience for translating a number
 /// of common return types into a `MiddlewareResult` while
 /// also modifying the `Response` as required.
@@ -54,10 +141,33 @@ impl ResponseFinalizer for () {
     }
 }
 
-impl ResponseFinalizer for MiddlewareResult {
+impl ResponseFinalizer for Action {
     fn respond(self, res: &mut Response) -> MiddlewareResult {
         maybe_set_type(res, MediaType::Html);
-        self
+        Ok(self)
+    }
+}
+
+/// Handlers can fail with a `NickelError`, which is passed on to the error
+/// handlers. Together with `try!` this keeps the error handling out of the
+/// way, e.g. errors of file operations get converted to `NickelError`s.
+///
+/// # Example
+/// ```{rust}
+/// use std::io::File;
+/// use nickel::{Request, Response, NickelError};
+///
+/// fn handler(_request: &Request, _response: &mut Response) -> Result<String, NickelError> {
+///     let mut file = try!(File::open(&Path::new("motd.txt")));
+///     Ok(try!(file.read_to_string()))
+/// }
+/// ```
+impl<T: ResponseFinalizer> ResponseFinalizer for Result<T, NickelError> {
+    fn respond(self, res: &mut Response) -> MiddlewareResult {
+        match self {
+            Ok(value) => value.respond(res),
+            Err(err) => Err(err)
+        }
     }
 }
 
@@ -75,7 +185,7 @@ impl<'a, S: Show> ResponseFinalizer for &'a [S] {
         res.origin.status = status::Ok;
         for ref s in self.iter() {
             // FIXME : failure unhandled
-            let _ = write!(res.origin, "{}", s);
+            let _ = write!(res, "{}", s);
         }
         Ok(Halt)
     }
@@ -0,0 +1,123 @@
+use std::ascii::AsciiExt;
+use std::collections::HashMap;
+
+/// A trie of the literal segments the paths of routes start with. Looking
+/// up a path gives the routes which may match it, so the router only needs
+/// to run their regexes instead of the ones of all routes. Segments with
+/// variables, wildcards or regex syntax end the literal part of a path.
+pub struct RouteIndex {
+    root: Node,
+    case_insensitive: bool
+}
+
+struct Node {
+    children: HashMap<String, Node>,
+    // the routes whose literal segments end at this node
+    routes: Vec<uint>
+}
+
+impl Node {
+    fn new() -> Node {
+        Node {
+            children: HashMap::new(),
+            routes: Vec::new()
+        }
+    }
+}
+
+impl RouteIndex {
+    pub fn new(case_insensitive: bool) -> RouteIndex {
+        RouteIndex {
+            root: Node::new(),
+            case_insensitive: case_insensitive
+        }
+    }
+
+    /// Adds the route at the given position in the router.
+    pub fn insert(&mut self, route_path: &str, route: uint) {
+        let case_insensitive = self.case_insensitive;
+        let mut node = &mut self.root;
+
+        for segment in route_path.split('/') {
+            if !is_literal(segment) {
+                break
+            }
+
+            let key = to_key(segment, case_insensitive);
+            if !node.children.contains_key(&key) {
+                node.children.insert(key.clone(), Node::new());
+            }
+            let current = node;
+            node = current.children.get_mut(&key).unwrap();
+        }
+
+        node.routes.push(route);
+    }
+
+    /// Returns the positions of the routes which may match the path, in the
+    /// order they were added to the router.
+    pub fn candidates(&self, path: &str) -> Vec<uint> {
+        let path = match path.find('?') {
+            Some(pos) => path.slice_to(pos),
+            None => path
+        };
+
+        let mut node = &self.root;
+        let mut candidates = node.routes.clone();
+        for segment in path.split('/') {
+            match node.children.get(&to_key(segment, self.case_insensitive)) {
+                Some(child) => {
+                    candidates.push_all(child.routes.as_slice());
+                    node = child;
+                },
+                None => break
+            }
+        }
+
+        candidates.sort();
+        candidates
+    }
+}
+
+// the literal parts of a path are regex, so only segments without any
+// special characters can be looked up as they are
+fn is_literal(segment: &str) -> bool {
+    segment.chars().all(|c| {
+        c.is_ascii() && (c.is_alphanumeric() || c == '-' || c == '_' || c == ',' || c == '~')
+    })
+}
+
+fn to_key(segment: &str, case_insensitive: bool) -> String {
+    if case_insensitive {
+        segment.to_ascii_lower()
+    } else {
+        segment.to_string()
+    }
+}
+
+#[test]
+fn finds_candidate_routes() {
+    let mut index = RouteIndex::new(false);
+    index.insert("/users/:id", 0);
+    index.insert("/users/new", 1);
+    index.insert("/posts/*", 2);
+    index.insert("/**", 3);
+    index.insert("/users", 4);
+    index.insert("/posts(/:page)?", 5);
+
+    assert_eq!(index.candidates("/users/new"), vec![0, 1, 3, 4, 5]);
+    assert_eq!(index.candidates("/users/4711?foo=bar"), vec![0, 3, 4, 5]);
+    assert_eq!(index.candidates("/posts"), vec![2, 3, 5]);
+    assert_eq!(index.candidates("/posts/1"), vec![2, 3, 5]);
+    assert_eq!(index.candidates("/Users/new"), vec![3, 5]);
+    assert_eq!(index.candidates("no/slash"), vec![]);
+}
+
+#[test]
+fn finds_candidate_routes_case_insensitively() {
+    let mut index = RouteIndex::new(true);
+    index.insert("/About", 0);
+
+    assert_eq!(index.candidates("/about"), vec![0]);
+    assert_eq!(index.candidates("/ABOUT/"), vec![0]);
+}
@@ -1,38 +1,228 @@
-use middleware::{Middleware, Continue, MiddlewareResult};
+use middleware::{Middleware, Continue, Halt, MiddlewareResult};
 use super::path_utils;
-use http::server::request::AbsolutePath;
+use super::path_utils::ParamType;
+use super::route_index::RouteIndex;
 use request::Request;
 use response::Response;
-use router::{HttpRouter, RequestHandler};
-use http::method::Method;
+use router::{HttpRouter, RequestHandler, FromParam};
+use http::method::{Method, Get, Head, Options};
+#[cfg(test)]
+use http::method;
+use http::status::{BadRequest, MovedPermanently, MethodNotAllowed};
+use nickel_error::{NickelError, ErrorWithStatusCode};
 use regex::Regex;
 use std::collections::HashMap;
+use header_utils;
+
+// an optional extension captured as `format`, e.g. `.json`
+const FORMAT_SUFFIX: &'static str = "(\\.:format([a-zA-Z0-9]+))?";
 
 /// A Route is the basic data structure that stores both the path
 /// and the handler that gets executed for the route.
-/// The path can contain variable pattern such as `user/:userid/invoices`
+/// The path can contain variable pattern such as `user/:userid/invoices`.
+/// Variables can be declared with one of the types `uint`, `int`, `float`
+/// or `bool`, e.g. `user/:userid:uint/invoices`, in which case the route
+/// only matches if the segment can be converted to the type, or with a
+/// regex of their own, e.g. `posts/:slug([a-z0-9-]+)`.
+/// Parts can be made optional, either a variable along with the slash in
+/// front of it, e.g. `articles/:page?`, or any group followed by `?`, e.g.
+/// `reports/:id(.:format)?`. Absent variables are empty.
 pub struct Route {
     pub path: String,
     pub method: Method,
+    pub name: Option<String>,
     pub handler: Box<RequestHandler + Send + Sync + 'static>,
     pub variables: HashMap<String, uint>,
-    matcher: Regex
+    matcher: Regex,
+    types: Vec<ParamType>,
+    middleware: Vec<Box<Middleware + Send + Sync + 'static>>,
+    guard: Option<Box<RouteGuard + Send + Sync + 'static>>
+}
+
+/// Decides whether a route handles a request, in addition to its method and
+/// path, e.g. by looking at a header or a feature flag. If a guard declines
+/// a request, the routes registered after it get a chance to match.
+pub trait RouteGuard: Send + Sync {
+    fn accepts(&self, &Request) -> bool;
+}
+
+impl RouteGuard for fn(&Request) -> bool {
+    fn accepts(&self, req: &Request) -> bool {
+        (*self)(req)
+    }
+}
+
+impl Route {
+    // routes with a guard only match requests it accepts, so they never
+    // match if there is no request to check
+    fn accepts(&self, req: Option<&Request>) -> bool {
+        match (&self.guard, req) {
+            (&Some(ref guard), Some(req)) => guard.accepts(req),
+            (&Some(_), None) => false,
+            (&None, _) => true
+        }
+    }
+
+    fn match_path<'a>(&'a self, path: &str) -> Option<RouteResult<'a>> {
+        let captures = match self.matcher.captures(path) {
+            Some(captures) => captures,
+            None => return None
+        };
+
+        // values which can't be decoded are kept as they are, the router
+        // rejects the request then
+        let mut params = Vec::with_capacity(self.variables.len());
+        let mut malformed = false;
+        for pos in range(0, self.variables.len()) {
+            let raw = path_utils::capture(&captures, pos);
+            match path_utils::decode(raw) {
+                Some(decoded) => params.push(decoded),
+                None => {
+                    malformed = true;
+                    params.push(raw.to_string());
+                }
+            }
+        }
+
+        let mut values = Vec::with_capacity(params.len());
+        for (param, param_type) in params.iter().zip(self.types.iter()) {
+            // optional variables which are absent are empty
+            if param.is_empty() {
+                values.push(None);
+                continue
+            }
+            match param_type.convert(param.as_slice()) {
+                Ok(value) => values.push(value),
+                Err(()) => return None
+            }
+        }
+
+        Some(RouteResult {
+            route: self,
+            params: params,
+            values: values,
+            middleware: self.middleware.iter().collect(),
+            host_params: Vec::new(),
+            malformed: malformed
+        })
+    }
+}
+
+/// The converted value of a variable with a type, e.g. `:id:uint`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ParamValue {
+    Uint(u64),
+    Int(i64),
+    Float(f64),
+    Bool(bool)
+}
+
+/// The reasons `RouteResult::param_as` can't give the value of a variable.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ParamError {
+    /// The route has no variable with the name, or it's an optional one
+    /// which the path left out.
+    Missing(String),
+    /// The value with the name of the variable, which doesn't parse as the
+    /// requested type.
+    Invalid(String, String)
 }
 
 /// A RouteResult is what the router returns when `match_route` is called.
 /// It contains the matched `route` and also a `params` property holding
 /// a HashMap with the keys being the variable names and the value being the
-/// evaluated string
+/// evaluated string, which is percent-decoded. Requests with params which
+/// can't be decoded are rejected with a `400 Bad Request` by the router.
 pub struct RouteResult<'a> {
     pub route: &'a Route,
-    params: Vec<String>
+    params: Vec<String>,
+    values: Vec<Option<ParamValue>>,
+    // the middleware of the routers the route lives in and of the route
+    middleware: Vec<&'a Box<Middleware + Send + Sync + 'static>>,
+    // the variables of the hosts of those routers
+    host_params: Vec<(String, String)>,
+    // whether a param has an invalid percent-encoding
+    malformed: bool
 }
 
 impl<'a> RouteResult<'a> {
+    /// Returns the value of a variable of the path or of the host of the
+    /// router, see `Router::host`.
     pub fn param(&self, key: &str) -> &str {
-        let idx = self.route.variables.get(key).unwrap();
-        self.params[*idx].as_slice()
+        self.find_param(key).unwrap()
+    }
+
+    /// Returns the value of a variable like `param`, or `None` if there's no
+    /// variable with the name.
+    pub fn find_param(&self, key: &str) -> Option<&str> {
+        match self.route.variables.get(key) {
+            Some(idx) => Some(self.params[*idx].as_slice()),
+            None => self.host_params.iter().find(|&&(ref name, _)| {
+                name.as_slice() == key
+            }).map(|&(_, ref value)| value.as_slice())
+        }
+    }
+
+    /// Returns the value of a variable converted to the given type, see
+    /// `FromParam`.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Request, Response};
+    /// // registered for "/users/:id"
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     match request.param_as::<uint>("id") {
+    ///         Ok(id) => response.send(format!("User {}", id)),
+    ///         Err(err) => response.send(format!("No user: {}", err))
+    ///     }
+    /// }
+    /// ```
+    pub fn param_as<T: FromParam>(&self, key: &str) -> Result<T, ParamError> {
+        match self.find_param(key) {
+            None | Some("") => Err(ParamError::Missing(key.to_string())),
+            Some(value) => FromParam::from_param(value).ok_or_else(|| {
+                ParamError::Invalid(key.to_string(), value.to_string())
+            })
+        }
     }
+
+    /// Returns the converted value of a variable declared with a type, or
+    /// `None` for plain variables, optional ones which are absent and
+    /// variables of the host.
+    pub fn value(&self, key: &str) -> Option<&ParamValue> {
+        match self.route.variables.get(key) {
+            Some(idx) => self.values[*idx].as_ref(),
+            None => None
+        }
+    }
+}
+
+/// How the router treats a trailing slash which the path of a route
+/// doesn't have, or a missing one which it does have.
+#[deriving(Clone, PartialEq, Show)]
+pub enum TrailingSlash {
+    /// `/foo/` and `/foo` are different paths, which is the default.
+    Strict,
+    /// `/foo/` and `/foo` both match the route registered for either one.
+    Lenient,
+    /// Requests for the other form are redirected to the path of the route
+    /// with a 301.
+    Redirect
+}
+
+/// What the router does when a route is registered which an earlier route
+/// for the same method always matches first, e.g. a second `/users/:id` or
+/// `/users/new` after `/users/:id`. The later route is still reached when
+/// the handler of the earlier one declines the request with `Continue`, so
+/// this is only a lint.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ShadowedRoutes {
+    /// Shadowed routes are registered silently.
+    Allow,
+    /// A warning is logged, which is the default.
+    Warn,
+    /// Registering the route panics.
+    Deny
 }
 
 /// The Router's job is it to hold routes and to resolve them later against
@@ -40,69 +230,900 @@ impl<'a> RouteResult<'a> {
 /// added to the middleware stack with `server.utilize(router)`.
 pub struct Router{
     routes: Vec<Route>,
+    index: RouteIndex,
+    mounts: Vec<Mount>,
+    trailing_slash: TrailingSlash,
+    case_insensitive: bool,
+    format_suffix: bool,
+    shadowed_routes: ShadowedRoutes,
+    middleware: Vec<Box<Middleware + Send + Sync + 'static>>,
+    host: Option<Host>
+}
+
+/// The host a router is restricted to.
+struct Host {
+    matcher: Regex,
+    variables: Vec<String>
+}
+
+/// Describes a registered route, see `Router::routes`.
+#[deriving(Show)]
+pub struct RouteInfo<'a> {
+    pub method: &'a Method,
+    /// The path the route was registered with, including the prefixes of
+    /// the routers it's mounted in.
+    pub path: String,
+    pub name: Option<&'a str>,
+    /// The names of the variables in the order they appear in the path.
+    pub variables: Vec<&'a str>
+}
+
+/// An iterator over the routes of a router, see `Router::routes`.
+pub struct Routes<'a> {
+    // in reverse order, so the next one can be popped off
+    routes: Vec<RouteInfo<'a>>
+}
+
+impl<'a> Iterator<RouteInfo<'a>> for Routes<'a> {
+    fn next(&mut self) -> Option<RouteInfo<'a>> {
+        self.routes.pop()
+    }
+}
+
+/// A Mount holds a sub router which only handles paths below `prefix`.
+struct Mount {
+    prefix: String,
+    router: Router
+}
+
+impl Mount {
+    // Returns the path relative to the prefix or `None` if the
+    // path doesn't live below the prefix.
+    fn strip_prefix(&self, path: &str) -> Option<String> {
+        if !path.starts_with(self.prefix.as_slice()) {
+            return None
+        }
+
+        let rest = path.slice_from(self.prefix.len());
+        if rest.is_empty() || rest.starts_with("?") {
+            Some(format!("/{}", rest))
+        } else if rest.starts_with("/") {
+            Some(rest.to_string())
+        } else {
+            // e.g. the prefix /api shouldn't match /apis
+            None
+        }
+    }
 }
 
 impl<'a> Router {
     pub fn new () -> Router {
         Router {
-            routes: Vec::new()
+            routes: Vec::new(),
+            index: RouteIndex::new(false),
+            mounts: Vec::new(),
+            trailing_slash: TrailingSlash::Strict,
+            case_insensitive: false,
+            format_suffix: false,
+            shadowed_routes: ShadowedRoutes::Warn,
+            middleware: Vec::new(),
+            host: None
+        }
+    }
+
+    /// Restricts the router to requests for the given host, which is either
+    /// a host name or a pattern like `*.example.com`, where `*` stands for
+    /// one or more labels. Single labels can be captured by variables, e.g.
+    /// `:tenant.example.com`, whose values are available through
+    /// `request.param("tenant")` like the ones of the path. The port of the
+    /// `Host` header is ignored. Several
+    /// routers with different hosts can be mounted at `/` of one router to
+    /// serve several sites from one server. As the host is taken from the
+    /// request, `match_route` never matches the routes of such a router.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    ///
+    /// fn blog(request: &Request, response: &mut Response) {
+    ///     response.send("The blog");
+    /// };
+    ///
+    /// fn shop(request: &Request, response: &mut Response) {
+    ///     response.send("The shop");
+    /// };
+    ///
+    /// let mut blog_router = Nickel::router();
+    /// blog_router.host("blog.example.com");
+    /// blog_router.get("/", blog);
+    ///
+    /// let mut shop_router = Nickel::router();
+    /// shop_router.host("*.shop.example.com");
+    /// shop_router.get("/", shop);
+    ///
+    /// let mut router = Nickel::router();
+    /// router.mount("/", blog_router);
+    /// router.mount("/", shop_router);
+    /// ```
+    pub fn host(&mut self, pattern: &str) {
+        self.host = Some(Host {
+            matcher: path_utils::create_host_regex(pattern),
+            variables: path_utils::get_host_variables(pattern)
+        });
+    }
+
+    // Returns the values of the variables of the host, or `None` if the
+    // router has a host which doesn't match the request's.
+    fn match_host(&self, req: Option<&Request>) -> Option<Vec<(String, String)>> {
+        let host = match self.host {
+            Some(ref host) => host,
+            None => return Some(Vec::new())
+        };
+        let header = match req.and_then(|req| header_utils::find(&req.origin.headers, "Host")) {
+            Some(header) => header,
+            None => return None
+        };
+
+        host.matcher.captures(header_utils::strip_port(header.as_slice())).map(|captures| {
+            host.variables.iter().enumerate().map(|(i, name)| {
+                (name.clone(), captures.at(i + 1).to_string())
+            }).collect()
+        })
+    }
+
+    /// Adds a middleware which only runs for requests matching one of the
+    /// routes of this router or the routers mounted in it, right before the
+    /// handler of the route. Together with `mount` this gives a group of
+    /// routes middleware of their own, e.g. an authentication check for all
+    /// routes below `/admin`.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter, BasicAuth};
+    ///
+    /// fn check(user: &str, password: &str) -> bool {
+    ///     user == "admin" && password == "secret"
+    /// }
+    ///
+    /// fn dashboard(request: &Request, response: &mut Response) {
+    ///     response.send("Admins only");
+    /// };
+    ///
+    /// let mut admin = Nickel::router();
+    /// admin.utilize(BasicAuth::new("Admin area", check));
+    /// admin.get("/dashboard", dashboard);
+    ///
+    /// let mut router = Nickel::router();
+    /// router.mount("/admin", admin);
+    /// ```
+    pub fn utilize<T: Middleware>(&mut self, middleware: T) {
+        self.middleware.push(box middleware);
+    }
+
+    /// Appends the routes of another router, which are matched after the
+    /// ones registered so far. Routers mounted in the other router are
+    /// mounted in this one. The settings of this router apply to all
+    /// routes then. To keep the middleware or host of the other router,
+    /// mount it instead.
+    pub fn extend(&mut self, other: Router) {
+        self.extend_prefixed("", "", other);
+    }
+
+    /// Appends the routes of another router like `extend`, with the prefix
+    /// added to their paths and the name prefix to their names, e.g.
+    /// `extend_prefixed("/admin", "admin_", users)` turns the route
+    /// `user_show` for `/users/:id` into `admin_user_show` for
+    /// `/admin/users/:id`.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    ///
+    /// fn user_handler(request: &Request, response: &mut Response) {
+    ///     response.send(format!("This is user: {}", request.param("id")));
+    /// };
+    ///
+    /// let mut users = Nickel::router();
+    /// users.get_named("user_show", "/users/:id", user_handler);
+    ///
+    /// let mut router = Nickel::router();
+    /// router.extend_prefixed("/admin", "admin_", users);
+    ///
+    /// assert_eq!(router.url_for("admin_user_show", &[("id", "4711")]),
+    ///            Some("/admin/users/4711".to_string()));
+    /// ```
+    pub fn extend_prefixed(&mut self, prefix: &str, name_prefix: &str, other: Router) {
+        assert!(other.middleware.is_empty() && other.host.is_none(),
+                "Routers with middleware or a host need to be mounted");
+
+        let prefix = prefix.trim_right_chars('/');
+        let Router { routes, mounts, .. } = other;
+        for mut route in routes.into_iter() {
+            route.path = format!("{}{}", prefix, route.path);
+            route.name = route.name.map(|name| format!("{}{}", name_prefix, name));
+            self.routes.push(route);
+        }
+        for mut mount in mounts.into_iter() {
+            mount.prefix = format!("{}{}", prefix, mount.prefix);
+            self.mounts.push(mount);
+        }
+
+        self.rebuild();
+    }
+
+    /// Removes the routes registered for the method and path, which needs to
+    /// be the same the routes were registered with. Returns whether there
+    /// was such a route. Routes of mounted routers are left alone.
+    pub fn remove_route(&mut self, method: &Method, path: &str) -> bool {
+        let count = self.routes.len();
+        self.routes.retain(|route| route.method != *method || route.path.as_slice() != path);
+        self.rebuild();
+        self.routes.len() != count
+    }
+
+    /// Makes the routes match paths regardless of their case, e.g. `/About`
+    /// matches a route for `/about`. The values of variables are passed on
+    /// as they were sent. Mounted routers have their own setting.
+    pub fn case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+        self.rebuild();
+    }
+
+    /// Lets the routes also match paths with an extension like `.json`,
+    /// which is available as the `format` param, e.g. a route for
+    /// `/reports/:id` matches `/reports/7.json` with the format `json` as
+    /// well as `/reports/7` with an empty format. Routes ending with a slash
+    /// or having a `format` variable of their own are left alone.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    ///
+    /// fn report(request: &Request, response: &mut Response) {
+    ///     match request.param("format") {
+    ///         "json" => response.send("{\"id\": 7}"),
+    ///         _ => response.send("<h1>Report 7</h1>")
+    ///     }
+    /// };
+    ///
+    /// let mut router = Nickel::router();
+    /// router.format_suffix(true);
+    /// router.get("/reports/:id", report);
+    /// ```
+    pub fn format_suffix(&mut self, enabled: bool) {
+        self.format_suffix = enabled;
+        self.rebuild();
+    }
+
+    /// Sets what happens when a route is registered which an earlier route
+    /// always matches first, see `ShadowedRoutes`. Routes with guards are
+    /// ignored. Routers whose handlers decline requests on purpose can
+    /// disable the check.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, ShadowedRoutes};
+    ///
+    /// let mut router = Nickel::router();
+    /// router.shadowed_routes(ShadowedRoutes::Allow);
+    /// ```
+    pub fn shadowed_routes(&mut self, lint: ShadowedRoutes) {
+        self.shadowed_routes = lint;
+    }
+
+    // Finds an earlier route which always matches before a new one. Only
+    // routes without variables can be checked against the other patterns.
+    fn shadowing_route(&self, method: &Method, path: &str, pattern: &str) -> Option<&Route> {
+        if self.shadowed_routes == ShadowedRoutes::Allow {
+            return None
+        }
+        let is_static = path_utils::is_static(pattern);
+        self.routes.iter()
+            .filter(|route| route.method == *method && route.guard.is_none())
+            .find(|route| route.path.as_slice() == path || (is_static && route.matcher.is_match(pattern)))
+    }
+
+    // compiles the routes again after a setting changed
+    fn rebuild(&mut self) {
+        let case_insensitive = self.case_insensitive;
+        let format_suffix = self.format_suffix;
+
+        self.index = RouteIndex::new(case_insensitive);
+        for (i, route) in self.routes.iter_mut().enumerate() {
+            let pattern = route_pattern(route.path.as_slice(), format_suffix);
+            route.matcher = compile(pattern.as_slice(), case_insensitive);
+            route.variables = path_utils::get_variable_info(pattern.as_slice());
+            route.types = path_utils::get_variable_types(pattern.as_slice());
+            self.index.insert(pattern.as_slice(), i);
         }
     }
 
+    /// Sets how paths which only differ from a route by a trailing slash are
+    /// treated, see `TrailingSlash`. Mounted routers decide on their own
+    /// whether they are lenient, redirects are only sent by the router which
+    /// is in the middleware stack.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, TrailingSlash};
+    ///
+    /// let mut router = Nickel::router();
+    /// router.trailing_slash(TrailingSlash::Redirect);
+    /// ```
+    pub fn trailing_slash(&mut self, mode: TrailingSlash) {
+        self.trailing_slash = mode;
+    }
+
+    /// Mounts a sub router under the given path prefix. The prefix is stripped
+    /// from the path before it is matched against the routes of the sub router.
+    /// Routes of the router itself take precedence over mounted routers.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    ///
+    /// fn users_handler(request: &Request, response: &mut Response) {
+    ///     response.send("This matches /api/users");
+    /// };
+    ///
+    /// let mut api = Nickel::router();
+    /// api.get("/users", users_handler);
+    ///
+    /// let mut router = Nickel::router();
+    /// router.mount("/api", api);
+    ///
+    /// let mut server = Nickel::new();
+    /// server.utilize(router);
+    /// ```
+    pub fn mount(&mut self, prefix: &str, router: Router) {
+        let prefix = prefix.trim_right_chars('/');
+        // mounting at "/" leaves the prefix empty which matches every path
+        let prefix = if prefix.is_empty() || prefix.starts_with("/") {
+            prefix.to_string()
+        } else {
+            format!("/{}", prefix)
+        };
+
+        self.mounts.push(Mount {
+            prefix: prefix,
+            router: router
+        });
+    }
+
+    /// Resolves the route for the method and path. HEAD requests are
+    /// handled by the GET route of the path, unless there's a HEAD route.
+    /// Routes with a guard never match here, see `match_request`.
     pub fn match_route(&'a self, method: &Method, path: &str) -> Option<RouteResult<'a>> {
-        self.routes.iter().find(|item| item.method == *method && item.matcher.is_match(path))
-            .map(|route| {
-                let vec = match route.matcher.captures(path) {
-                    Some(captures) => {
-                        range(0, route.variables.len()).map(|pos|
-                            captures.at(pos + 1).to_string()
-                        ).collect()
-                    },
-                    None => vec![],
-                };
-                RouteResult {
-                    route: route,
-                    params: vec
-                }
-            })
+        self.resolve(method, path, None, 0)
     }
-}
 
-impl HttpRouter for Router {
-    fn add_route<H: RequestHandler>(&mut self, method: Method, path: &str, handler: H) {
-        let matcher = path_utils::create_regex(path);
-        let variable_infos = path_utils::get_variable_info(path);
+    /// Resolves the route for the method and path like `match_route`, but
+    /// also checks the guards of the routes against the request.
+    pub fn match_request(&'a self, req: &Request, method: &Method, path: &str)
+                         -> Option<RouteResult<'a>> {
+        self.resolve(method, path, Some(req), 0)
+    }
+
+    // Returns the matching route after skipping the given number of matches,
+    // which is how the routes following one whose handler declined the
+    // request are found.
+    fn resolve(&'a self, method: &Method, path: &str, req: Option<&Request>, skip: uint)
+               -> Option<RouteResult<'a>> {
+        let mut skip = skip;
+        let route_result = self.match_method(method, path, req, &mut skip);
+        match *method {
+            Head if route_result.is_none() => self.match_method(&Get, path, req, &mut skip),
+            _ => route_result
+        }
+    }
+
+    fn match_method(&'a self, method: &Method, path: &str, req: Option<&Request>, skip: &mut uint)
+                    -> Option<RouteResult<'a>> {
+        let exact_match = self.match_exact(method, path, req, skip);
+        if exact_match.is_some() || self.trailing_slash != TrailingSlash::Lenient {
+            return exact_match
+        }
+
+        match toggle_trailing_slash(path) {
+            Some(path) => self.match_exact(method, path.as_slice(), req, skip),
+            None => None
+        }
+    }
+
+    fn match_exact(&'a self, method: &Method, path: &str, req: Option<&Request>, skip: &mut uint)
+                   -> Option<RouteResult<'a>> {
+        let host_params = match self.match_host(req) {
+            Some(host_params) => host_params,
+            None => return None
+        };
+
+        // routes whose typed variables don't convert or whose guards decline
+        // don't match, so the next one gets a chance
+        for i in self.index.candidates(path).into_iter() {
+            let route = &self.routes[i];
+            if route.method != *method || !route.accepts(req) {
+                continue
+            }
+
+            match route.match_path(path) {
+                Some(_) if *skip > 0 => *skip -= 1,
+                Some(route_result) => return Some(self.scope(route_result, host_params)),
+                None => {}
+            }
+        }
+
+        for mount in self.mounts.iter() {
+            let sub_match = mount.strip_prefix(path).and_then(|sub_path| {
+                mount.router.match_method(method, sub_path.as_slice(), req, skip)
+            });
+
+            if sub_match.is_some() {
+                return sub_match.map(|route_result| self.scope(route_result, host_params))
+            }
+        }
+
+        None
+    }
+
+    // Adds the middleware and host variables of the router to the result of
+    // one of its routes. The middleware of the router runs before the one of
+    // mounted routers and routes.
+    fn scope(&'a self, mut route_result: RouteResult<'a>, host_params: Vec<(String, String)>)
+             -> RouteResult<'a> {
+        let mut middleware: Vec<&'a Box<Middleware + Send + Sync + 'static>> =
+            self.middleware.iter().collect();
+        middleware.extend(route_result.middleware.into_iter());
+        route_result.middleware = middleware;
+        route_result.host_params.extend(host_params.into_iter());
+        route_result
+    }
+
+    /// Returns the methods of all routes matching the given path, which is
+    /// what the `Allow` header lists when the method of a request has no
+    /// route. HEAD and OPTIONS are added as the router answers them, too.
+    /// The hosts of routers and the guards of routes aren't checked.
+    pub fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        self.methods_for(path, None)
+    }
+
+    fn methods_for(&self, path: &str, req: Option<&Request>) -> Vec<Method> {
+        let mut methods = Vec::new();
+        self.collect_methods(path, req, &mut methods);
+        if methods.contains(&Get) && !methods.contains(&Head) {
+            methods.push(Head);
+        }
+        if !methods.is_empty() && !methods.contains(&Options) {
+            methods.push(Options);
+        }
+        methods
+    }
+
+    fn collect_methods(&self, path: &str, req: Option<&Request>, methods: &mut Vec<Method>) {
+        if req.is_some() && self.match_host(req).is_none() {
+            return
+        }
+
+        let other = match self.trailing_slash {
+            TrailingSlash::Lenient => toggle_trailing_slash(path),
+            _ => None
+        };
+
+        let mut candidates = self.index.candidates(path);
+        match other {
+            Some(ref other) => candidates.push_all(self.index.candidates(other.as_slice()).as_slice()),
+            None => {}
+        }
+
+        for route in candidates.into_iter().map(|i| &self.routes[i]) {
+            // a route declined by its guard isn't allowed either
+            if req.is_some() && !route.accepts(req) {
+                continue
+            }
+            let matches = route.match_path(path).is_some() ||
+                other.as_ref().map_or(false, |other| route.match_path(other.as_slice()).is_some());
+            if matches && !methods.contains(&route.method) {
+                methods.push(route.method.clone());
+            }
+        }
+
+        for mount in self.mounts.iter() {
+            match mount.strip_prefix(path) {
+                Some(sub_path) => mount.router.collect_methods(sub_path.as_slice(), req, methods),
+                None => {}
+            }
+        }
+    }
+
+    /// Registers a handler for the given method and path with a name that
+    /// can later be used to generate URLs for the route with `url_for`.
+    pub fn add_named_route<H: RequestHandler>(&mut self, name: &str, method: Method,
+                                              path: &str, handler: H) {
+        self.push_route(Some(name.to_string()), method, path, handler);
+    }
+
+    /// Registers a named handler to be used for a specific GET request.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response};
+    ///
+    /// fn user_handler(request: &Request, response: &mut Response) {
+    ///     response.send(format!("This is user: {}", request.param("id")));
+    /// };
+    ///
+    /// let mut router = Nickel::router();
+    /// router.get_named("user_show", "/users/:id", user_handler);
+    ///
+    /// assert_eq!(router.url_for("user_show", &[("id", "4711")]),
+    ///            Some("/users/4711".to_string()));
+    /// ```
+    pub fn get_named<H: RequestHandler>(&mut self, name: &str, path: &str, handler: H) {
+        self.add_named_route(name, Get, path, handler);
+    }
+
+    /// Generates the URL for the route registered under the given name by
+    /// substituting its variables with the given params. Routes of mounted
+    /// routers are prefixed with their mount point.
+    ///
+    /// The params get percent-encoded, slashes included, except for those
+    /// of double wildcards like `**path`.
+    ///
+    /// Returns `None` if there is no such route or a param is missing.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let own_route = self.routes.iter().find(|route| {
+            route.name.as_ref().map_or(false, |n| n.as_slice() == name)
+        });
+
+        match own_route {
+            Some(route) => return path_utils::fill_variables(route.path.as_slice(), params),
+            None => {}
+        }
+
+        for mount in self.mounts.iter() {
+            match mount.router.url_for(name, params) {
+                Some(url) => return Some(format!("{}{}", mount.prefix, url)),
+                None => {}
+            }
+        }
+
+        None
+    }
+
+    /// Registers a handler for the given method and path, which runs after
+    /// the given middleware. The middleware only runs for requests matching
+    /// the route and can halt them before they reach the handler.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// # extern crate http;
+    /// # extern crate nickel;
+    /// # fn main() {
+    /// use nickel::{Nickel, Request, Response, Middleware, BasicAuth, Logger, LogFormat};
+    /// use http::method::Get;
+    ///
+    /// fn check(user: &str, password: &str) -> bool {
+    ///     user == "admin" && password == "secret"
+    /// }
+    ///
+    /// fn page(request: &Request, response: &mut Response) {
+    ///     response.send(format!("This is page {}", request.param("page")));
+    /// };
+    ///
+    /// let mut router = Nickel::router();
+    /// router.add_route_with_middleware(Get, "/admin/:page", vec![
+    ///     box BasicAuth::new("Admin area", check) as Box<Middleware + Send + Sync>,
+    ///     box Logger::stdout(LogFormat::Common) as Box<Middleware + Send + Sync>
+    /// ], page);
+    /// # }
+    /// ```
+    pub fn add_route_with_middleware<H: RequestHandler>(&mut self, method: Method, path: &str,
+                                                        middleware: Vec<Box<Middleware + Send + Sync>>,
+                                                        handler: H) {
+        self.push_route(None, method, path, handler);
+        self.routes.last_mut().unwrap().middleware = middleware;
+    }
+
+    /// Registers a handler for the given method and path, which only
+    /// handles requests the guard accepts. Other requests are matched
+    /// against the routes registered later on.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// # extern crate http;
+    /// # extern crate nickel;
+    /// # fn main() {
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    /// use http::method::Get;
+    ///
+    /// fn wants_v2(request: &Request) -> bool {
+    ///     request.origin.headers.extensions.get("X-Api-Version")
+    ///            .map_or(false, |version| version.as_slice() == "2")
+    /// }
+    ///
+    /// fn v2_handler(request: &Request, response: &mut Response) {
+    ///     response.send("Version 2");
+    /// };
+    ///
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.send("Version 1");
+    /// };
+    ///
+    /// let mut router = Nickel::router();
+    /// router.add_guarded_route(Get, "/items", wants_v2, v2_handler);
+    /// router.get("/items", handler);
+    /// # }
+    /// ```
+    pub fn add_guarded_route<G: RouteGuard, H: RequestHandler>(&mut self, method: Method, path: &str,
+                                                              guard: G, handler: H) {
+        self.push_route(None, method, path, handler);
+        self.routes.last_mut().unwrap().guard = Some(box guard);
+    }
+
+    /// Returns the routes of the router, followed by the ones of mounted
+    /// routers, in the order they are matched. This is handy to print the
+    /// routes at startup or to generate clients.
+    ///
+    /// # Example
+    ///
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, Response, HttpRouter};
+    ///
+    /// fn handler(request: &Request, response: &mut Response) {
+    ///     response.send("Hello");
+    /// };
+    ///
+    /// let mut router = Nickel::router();
+    /// router.get("/users/:id", handler);
+    ///
+    /// for route in router.routes() {
+    ///     println!("{} {} {}", route.method, route.path, route.variables);
+    /// }
+    /// ```
+    pub fn routes(&'a self) -> Routes<'a> {
+        let mut routes = Vec::new();
+        self.collect_routes("", &mut routes);
+        routes.reverse();
+        Routes { routes: routes }
+    }
+
+    fn collect_routes(&'a self, prefix: &str, routes: &mut Vec<RouteInfo<'a>>) {
+        for route in self.routes.iter() {
+            let mut variables: Vec<(&uint, &str)> = route.variables.iter().map(|(name, index)| {
+                (index, name.as_slice())
+            }).collect();
+            variables.sort();
+
+            routes.push(RouteInfo {
+                method: &route.method,
+                path: format!("{}{}", prefix, route.path),
+                name: route.name.as_ref().map(|name| name.as_slice()),
+                variables: variables.into_iter().map(|(_, name)| name).collect()
+            });
+        }
+
+        for mount in self.mounts.iter() {
+            mount.router.collect_routes(format!("{}{}", prefix, mount.prefix).as_slice(), routes);
+        }
+    }
+
+    fn push_route<H: RequestHandler>(&mut self, name: Option<String>, method: Method,
+                                     path: &str, handler: H) {
+        let pattern = route_pattern(path, self.format_suffix);
+        match self.shadowing_route(&method, path, pattern.as_slice()) {
+            Some(route) => {
+                let message = format!("The route {} {} is only reached if the route {} {} declines",
+                                      method, path, route.method, route.path);
+                if self.shadowed_routes == ShadowedRoutes::Deny {
+                    panic!("{}", message)
+                }
+                warn!("{}", message);
+            },
+            None => {}
+        }
+
+        let matcher = compile(pattern.as_slice(), self.case_insensitive);
+        let variable_infos = path_utils::get_variable_info(pattern.as_slice());
         let route = Route {
             path: path.to_string(),
             method: method,
+            name: name,
             matcher: matcher,
             handler: box handler,
-            variables: variable_infos
+            variables: variable_infos,
+            types: path_utils::get_variable_types(pattern.as_slice()),
+            middleware: Vec::new(),
+            guard: None
         };
+        self.index.insert(pattern.as_slice(), self.routes.len());
         self.routes.push(route);
     }
 }
 
+impl HttpRouter for Router {
+    fn add_route<H: RequestHandler>(&mut self, method: Method, path: &str, handler: H) {
+        self.push_route(None, method, path, handler);
+    }
+}
+
 impl Middleware for Router {
     fn invoke<'a, 'b>(&'a self, req: &mut Request<'b, 'a>, res: &mut Response)
                         -> MiddlewareResult {
-        match req.origin.request_uri {
-            AbsolutePath(ref url) => {
-                match self.match_route(&req.origin.method, url.as_slice()) {
-                    Some(route_result) => {
-                        res.origin.status = ::http::status::Ok;
-                        let handler = &route_result.route.handler;
-                        req.route_result = Some(route_result);
-                        handler.handle(req, res)
-                    },
-                    None => Ok(Continue)
+        let method = req.method().clone();
+        let url = match req.path() {
+            Some(path) => path.to_string(),
+            None => return Ok(Continue)
+        };
+
+        // handlers can decline a request by returning `Continue`, the next
+        // matching route gets it then
+        let mut skip = 0u;
+        let mut ran: Vec<&Box<Middleware + Send + Sync + 'static>> = Vec::new();
+        loop {
+            match self.resolve(&method, url.as_slice(), Some(&*req), skip) {
+                Some(ref route_result) if route_result.malformed => {
+                    return Err(NickelError::new("Invalid percent-encoding in the path",
+                                                ErrorWithStatusCode(BadRequest)))
+                },
+                Some(route_result) => {
+                    res.origin.status = ::http::status::Ok;
+                    let handler = &route_result.route.handler;
+                    let middleware = route_result.middleware.clone();
+                    req.route_result = Some(route_result);
+
+                    // the middleware of the routers the declining route
+                    // shares with this one already ran
+                    let shared = ran.iter().zip(middleware.iter()).take_while(|&(a, b)| {
+                        *a as *const Box<Middleware + Send + Sync> ==
+                            *b as *const Box<Middleware + Send + Sync>
+                    }).count();
+                    for middleware in middleware.slice_from(shared).iter() {
+                        match try!(middleware.invoke(req, res)) {
+                            Halt => return Ok(Halt),
+                            Continue => {}
+                        }
+                    }
+                    match try!(handler.handle(req, res)) {
+                        Halt => return Ok(Halt),
+                        Continue => {
+                            skip += 1;
+                            ran = middleware;
+                        }
+                    }
+                },
+                // all matching routes declined, so it's up to the next middleware
+                None if skip > 0 => return Ok(Continue),
+                None => return self.no_route(req, &method, url.as_slice(), res)
+            }
+        }
+    }
+}
+
+impl Router {
+    // Requests for paths with routes for other methods are left to later
+    // middleware, which may handle them, and answered with a 405 by the
+    // `MethodNotAllowedHandler` otherwise.
+    fn no_route(&self, req: &mut Request, method: &Method, url: &str, res: &mut Response)
+                -> MiddlewareResult {
+        let allowed = self.methods_for(url, Some(&*req));
+        if allowed.is_empty() {
+            return self.redirect_trailing_slash(&*req, method, url, res)
+        }
+
+        match req.map.get_mut::<AllowedMethods>() {
+            Some(other_routers) => {
+                let AllowedMethods(ref mut methods) = *other_routers;
+                for method in allowed.into_iter() {
+                    if !methods.contains(&method) {
+                        methods.push(method);
+                    }
                 }
+                return Ok(Continue)
+            },
+            None => {}
+        }
+        req.map.insert(AllowedMethods(allowed));
+        Ok(Continue)
+    }
+
+    fn redirect_trailing_slash(&self, req: &Request, method: &Method, url: &str,
+                               res: &mut Response) -> MiddlewareResult {
+        if self.trailing_slash != TrailingSlash::Redirect {
+            return Ok(Continue)
+        }
+
+        match toggle_trailing_slash(url) {
+            Some(ref other) if self.match_request(req, method, other.as_slice()).is_some() => {
+                res.redirect_with(MovedPermanently, other.as_slice());
+                Ok(Halt)
             },
             _ => Ok(Continue)
         }
     }
 }
 
+// The methods routers have routes for on the path of a request, none of
+// which is for the method of the request
+struct AllowedMethods(Vec<Method>);
+
+/// Answers requests which routers have routes for, just not for their
+/// method, with a `405 Method Not Allowed` listing the allowed methods in
+/// the `Allow` header. OPTIONS requests without a route of their own just
+/// get the allowed methods. Routers leave this to this middleware, so the
+/// ones after them still get to handle the request. `Nickel` adds it at the
+/// end of the middleware stack.
+pub struct MethodNotAllowedHandler;
+
+impl Middleware for MethodNotAllowedHandler {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        let allowed = match req.map.get::<AllowedMethods>() {
+            Some(&AllowedMethods(ref methods)) => methods.clone(),
+            None => return Ok(Continue)
+        };
+
+        res.origin.headers.allow = Some(allowed);
+        match *req.method() {
+            Options => {
+                res.origin.status = ::http::status::Ok;
+                res.origin.headers.content_length = Some(0);
+                res.start();
+                Ok(Halt)
+            },
+            _ => Err(NickelError::new("Method Not Allowed", ErrorWithStatusCode(MethodNotAllowed)))
+        }
+    }
+}
+
+// The path the route is compiled from, which may have an optional format
+// suffix, see `Router::format_suffix`
+fn route_pattern(path: &str, format_suffix: bool) -> String {
+    if !format_suffix || path.is_empty() || path.ends_with("/") || path.contains(":format") {
+        path.to_string()
+    } else {
+        format!("{}{}", path, FORMAT_SUFFIX)
+    }
+}
+
+fn compile(path: &str, case_insensitive: bool) -> Regex {
+    if case_insensitive {
+        path_utils::create_case_insensitive_regex(path)
+    } else {
+        path_utils::create_regex(path)
+    }
+}
+
+// Adds a trailing slash to the path or removes it, keeping the query string.
+// The root path is left alone.
+fn toggle_trailing_slash(url: &str) -> Option<String> {
+    let (path, query) = match url.find('?') {
+        Some(pos) => (url.slice_to(pos), url.slice_from(pos)),
+        None => (url, "")
+    };
+
+    if path.is_empty() || path == "/" {
+        None
+    } else if path.ends_with("/") {
+        Some(format!("{}{}", path.slice_to(path.len() - 1), query))
+    } else {
+        Some(format!("{}/{}", path, query))
+    }
+}
+
+// a handler for the tests which only check which route matches
+#[cfg(test)]
+fn handler (_request: &Request, response: &mut Response) -> () {
+    let _ = response.origin.write("hello from foo".as_bytes());
+}
+
 #[test]
 fn creates_map_with_var_variable_infos () {
     let map = path_utils::get_variable_info("foo/:uid/bar/:groupid");
@@ -166,16 +1187,8 @@ fn creates_valid_regex_for_routes () {
 
 #[test]
 fn can_match_var_routes () {
-    use http::method;
-    use request::Request;
-    use response::Response;
-
     let route_store = &mut Router::new();
 
-    fn handler (_request: &Request, response: &mut Response) -> () {
-        let _ = response.origin.write("hello from foo".as_bytes());
-    };
-
     route_store.add_route(method::Get, "/foo/:userid", handler);
     route_store.add_route(method::Get, "/bar", handler);
 
@@ -206,6 +1219,628 @@ fn can_match_var_routes () {
     assert!(route_result.is_some());
 
     let route_result = route_result.unwrap();
-    assert_eq!(route_result.param("userid"), "John%20Doe");
+    assert_eq!(route_result.param("userid"), "John Doe");
+}
+
+#[test]
+fn matches_routes_by_method () {
+    let route_store = &mut Router::new();
+
+    fn get_handler (_request: &Request, response: &mut Response) -> () {
+        let _ = response.origin.write("hello from get".as_bytes());
+    };
+
+    fn post_handler (_request: &Request, response: &mut Response) -> () {
+        let _ = response.origin.write("hello from post".as_bytes());
+    };
+
+    route_store.add_route(method::Get, "/foo/:id", get_handler);
+    route_store.add_route(method::Post, "/foo/:id", post_handler);
+    route_store.patch("/bar", get_handler);
+
+    let route_result = route_store.match_route(&method::Get, "/foo/4711").unwrap();
+    assert_eq!(route_result.route.method, method::Get);
+
+    let route_result = route_store.match_route(&method::Post, "/foo/4711").unwrap();
+    assert_eq!(route_result.route.method, method::Post);
+    assert_eq!(route_result.param("id"), "4711");
+
+    let route_result = route_store.match_route(&method::Put, "/foo/4711");
+    assert!(route_result.is_none());
+
+    let route_result = route_store.match_route(&method::Patch, "/bar");
+    assert!(route_result.is_some());
+
+    let route_result = route_store.match_route(&method::Get, "/bar");
+    assert!(route_result.is_none());
+}
+
+#[test]
+fn creates_map_with_named_wildcard_variable_infos () {
+    let map = path_utils::get_variable_info("static/*/:uid/*file/**path");
+
+    assert_eq!(map.len(), 3);
+    assert_eq!(map["uid".to_string()], 0);
+    assert_eq!(map["file".to_string()], 1);
+    assert_eq!(map["path".to_string()], 2);
+}
+
+#[test]
+fn creates_regex_with_named_wildcard_captures () {
+    let regex = path_utils::create_regex("static/*file");
+    let caps = regex.captures("static/app.js").unwrap();
+
+    assert_eq!(caps.at(1), "app.js");
+    assert_eq!(regex.is_match("static/js/app.js"), false);
+
+    let regex = path_utils::create_regex("files/**path");
+    let caps = regex.captures("files/some/nested/file.txt?foo=bar").unwrap();
+
+    assert_eq!(caps.at(1), "some/nested/file.txt");
+
+    let regex = path_utils::create_regex("foo/*/:uid/**rest");
+    let caps = regex.captures("foo/test/4711/bar/5490").unwrap();
+
+    assert_eq!(caps.at(1), "4711");
+    assert_eq!(caps.at(2), "bar/5490");
+}
+
+#[test]
+fn can_match_named_wildcard_routes () {
+    let route_store = &mut Router::new();
+
+    route_store.add_route(method::Get, "/static/*file", handler);
+    route_store.add_route(method::Get, "/files/**path", handler);
+
+    let route_result = route_store.match_route(&method::Get, "/static/style.css").unwrap();
+    assert_eq!(route_result.param("file"), "style.css");
+
+    let route_result = route_store.match_route(&method::Get, "/files/a/b/c.txt").unwrap();
+    assert_eq!(route_result.param("path"), "a/b/c.txt");
+
+    let route_result = route_store.match_route(&method::Get, "/static/css/style.css");
+    assert!(route_result.is_none());
+}
+
+#[test]
+fn can_match_mounted_routes () {
+    let mut api = Router::new();
+    api.add_route(method::Get, "/users/:userid", handler);
+    api.add_route(method::Get, "/", handler);
+
+    let route_store = &mut Router::new();
+    route_store.add_route(method::Get, "/bar", handler);
+    route_store.mount("/api/", api);
+
+    let route_result = route_store.match_route(&method::Get, "/api/users/4711").unwrap();
+    assert_eq!(route_result.route.path.as_slice(), "/users/:userid");
+    assert_eq!(route_result.param("userid"), "4711");
+
+    let route_result = route_store.match_route(&method::Get, "/api").unwrap();
+    assert_eq!(route_result.route.path.as_slice(), "/");
+
+    let route_result = route_store.match_route(&method::Get, "/api?foo=bar").unwrap();
+    assert_eq!(route_result.route.path.as_slice(), "/");
+
+    let route_result = route_store.match_route(&method::Get, "/apis/users/4711");
+    assert!(route_result.is_none());
+
+    let route_result = route_store.match_route(&method::Get, "/users/4711");
+    assert!(route_result.is_none());
+
+    let route_result = route_store.match_route(&method::Get, "/bar");
+    assert!(route_result.is_some());
+}
+
+#[test]
+fn generates_urls_for_named_routes () {
+    let mut api = Router::new();
+    api.get_named("user_show", "/users/:id", handler);
+    api.add_named_route("user_files", method::Get, "/users/:id/files/**path", handler);
+    api.add_named_route("anonymous", method::Get, "/some/*/route", handler);
+
+    let route_store = &mut Router::new();
+    route_store.add_named_route("bar", method::Post, "/bar", handler);
+    route_store.get_named("articles", "/articles/:page?", handler);
+    route_store.mount("/api", api);
+
+    assert_eq!(route_store.url_for("bar", &[]), Some("/bar".to_string()));
+    assert_eq!(route_store.url_for("user_show", &[("id", "4711")]),
+               Some("/api/users/4711".to_string()));
+    assert_eq!(route_store.url_for("user_files", &[("id", "4711"), ("path", "a/b.txt")]),
+               Some("/api/users/4711/files/a/b.txt".to_string()));
+    assert_eq!(route_store.url_for("articles", &[("page", "2")]), Some("/articles/2".to_string()));
+    assert_eq!(route_store.url_for("articles", &[]), Some("/articles".to_string()));
+
+    assert_eq!(route_store.url_for("user_show", &[]), None);
+    assert_eq!(route_store.url_for("anonymous", &[]), None);
+    assert_eq!(route_store.url_for("unknown", &[]), None);
+}
+
+#[test]
+fn can_match_typed_var_routes () {
+    let route_store = &mut Router::new();
+
+    route_store.add_route(method::Get, "/users/:id:uint", handler);
+    route_store.add_route(method::Get, "/users/:name", handler);
+    route_store.add_route(method::Get, "/prices/:price:float/:reduced:bool", handler);
+
+    let route_result = route_store.match_route(&method::Get, "/users/4711").unwrap();
+    assert_eq!(route_result.route.path.as_slice(), "/users/:id:uint");
+    assert_eq!(route_result.param("id"), "4711");
+    assert_eq!(route_result.value("id"), Some(&ParamValue::Uint(4711)));
+
+    let route_result = route_store.match_route(&method::Get, "/users/john").unwrap();
+    assert_eq!(route_result.route.path.as_slice(), "/users/:name");
+    assert_eq!(route_result.value("name"), None);
+
+    // too large for a u64, so the next route matches
+    let route_result = route_store.match_route(&method::Get, "/users/123456789012345678901234567890").unwrap();
+    assert_eq!(route_result.route.path.as_slice(), "/users/:name");
+
+    let route_result = route_store.match_route(&method::Get, "/prices/-1.5/true").unwrap();
+    assert_eq!(route_result.value("price"), Some(&ParamValue::Float(-1.5)));
+    assert_eq!(route_result.value("reduced"), Some(&ParamValue::Bool(true)));
+
+    assert!(route_store.match_route(&method::Get, "/prices/1.5/yes").is_none());
+}
+
+#[test]
+fn can_match_var_routes_with_custom_regex () {
+    let route_store = &mut Router::new();
+
+    route_store.add_route(method::Get, "/posts/:slug([a-z0-9-]+)", handler);
+    route_store.add_route(method::Get, "/api/:version(v(1|2))/:id", handler);
+
+    let route_result = route_store.match_route(&method::Get, "/posts/hello-world-2").unwrap();
+    assert_eq!(route_result.param("slug"), "hello-world-2");
+    assert!(route_store.match_route(&method::Get, "/posts/Hello").is_none());
+
+    // the group inside the regex doesn't shift the following variables
+    let route_result = route_store.match_route(&method::Get, "/api/v2/4711").unwrap();
+    assert_eq!(route_result.param("version"), "v2");
+    assert_eq!(route_result.param("id"), "4711");
+    assert!(route_store.match_route(&method::Get, "/api/v3/4711").is_none());
+}
+
+#[test]
+fn can_match_optional_route_parts () {
+    let route_store = &mut Router::new();
+
+    route_store.add_route(method::Get, "/articles/:page:uint?", handler);
+    route_store.add_route(method::Get, "/reports/:id:uint(.:format)?", handler);
+    route_store.add_route(method::Get, "/invoices/:id(.:format)?", handler);
+
+    let route_result = route_store.match_route(&method::Get, "/articles").unwrap();
+    assert_eq!(route_result.param("page"), "");
+    assert_eq!(route_result.value("page"), None);
+
+    let route_result = route_store.match_route(&method::Get, "/articles/3").unwrap();
+    assert_eq!(route_result.value("page"), Some(&ParamValue::Uint(3)));
+    assert!(route_store.match_route(&method::Get, "/articles/").is_none());
+
+    let route_result = route_store.match_route(&method::Get, "/reports/12.json").unwrap();
+    assert_eq!(route_result.param("id"), "12");
+    assert_eq!(route_result.param("format"), "json");
+
+    let route_result = route_store.match_route(&method::Get, "/reports/12").unwrap();
+    assert_eq!(route_result.param("format"), "");
+
+    let route_result = route_store.match_route(&method::Get, "/invoices/ab-12.pdf").unwrap();
+    assert_eq!(route_result.param("id"), "ab-12");
+    assert_eq!(route_result.param("format"), "pdf");
+
+    let route_result = route_store.match_route(&method::Get, "/invoices/ab-12").unwrap();
+    assert_eq!(route_result.param("id"), "ab-12");
+    assert_eq!(route_result.param("format"), "");
+}
+
+#[test]
+fn toggles_trailing_slashes () {
+    assert_eq!(toggle_trailing_slash("/foo"), Some("/foo/".to_string()));
+    assert_eq!(toggle_trailing_slash("/foo/?a=b"), Some("/foo?a=b".to_string()));
+    assert_eq!(toggle_trailing_slash("/"), None);
+    assert_eq!(toggle_trailing_slash("/?a=b"), None);
+}
+
+#[test]
+fn can_match_routes_with_lenient_trailing_slashes () {
+    let route_store = &mut Router::new();
+
+    route_store.add_route(method::Get, "/foo", handler);
+    route_store.add_route(method::Get, "/bar/", handler);
+
+    assert!(route_store.match_route(&method::Get, "/foo/").is_none());
+
+    route_store.trailing_slash(TrailingSlash::Lenient);
+    assert!(route_store.match_route(&method::Get, "/foo").is_some());
+    assert!(route_store.match_route(&method::Get, "/foo/").is_some());
+    assert!(route_store.match_route(&method::Get, "/foo/?page=2").is_some());
+    assert!(route_store.match_route(&method::Get, "/bar").is_some());
+}
+
+#[test]
+fn can_match_routes_case_insensitively () {
+    let route_store = &mut Router::new();
+
+    route_store.add_route(method::Get, "/about", handler);
+    assert!(route_store.match_route(&method::Get, "/About").is_none());
+
+    route_store.case_insensitive(true);
+    route_store.add_route(method::Get, "/users/:name", handler);
+
+    assert!(route_store.match_route(&method::Get, "/About").is_some());
+    let route_result = route_store.match_route(&method::Get, "/USERS/John").unwrap();
+    assert_eq!(route_result.param("name"), "John");
+}
+
+#[test]
+fn lists_allowed_methods_of_paths () {
+    let mut api = Router::new();
+    api.add_route(method::Delete, "/users/:id", handler);
+
+    let route_store = &mut Router::new();
+    route_store.add_route(method::Get, "/api/users/:id", handler);
+    route_store.add_route(method::Put, "/api/users/:id", handler);
+    route_store.add_route(method::Get, "/api/users/:id", handler);
+    route_store.mount("/api", api);
+
+    assert_eq!(route_store.allowed_methods("/api/users/4711"),
+               vec![method::Get, method::Put, method::Delete, method::Head, method::Options]);
+    assert_eq!(route_store.allowed_methods("/api/users"), vec![]);
+}
+
+#[test]
+fn leaves_other_methods_to_later_routers () {
+    use std::io::net::ip::Ipv4Addr;
+    use std::io::net::tcp::TcpStream;
+    use nickel::Nickel;
+
+    fn handler (_request: &Request, _response: &mut Response) -> &'static str {
+        "hello from the second router"
+    }
+
+    let mut first = Router::new();
+    first.add_route(method::Post, "/users", handler);
+    let mut second = Router::new();
+    second.add_route(method::Get, "/users", handler);
+
+    let mut server = Nickel::new();
+    server.utilize(first);
+    server.utilize(second);
+    let listening = server.listen(Ipv4Addr(127, 0, 0, 1), 0);
+    let address = listening.socket_name();
+
+    let request = |request: &str| {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write(request.as_bytes()).unwrap();
+        stream.read_to_string().unwrap()
+    };
+    let handled = request("GET /users HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    let not_allowed = request("DELETE /users HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    listening.shutdown(0);
+
+    assert!(handled.as_slice().contains("hello from the second router"));
+    assert!(not_allowed.as_slice().starts_with("HTTP/1.1 405"));
+    assert!(not_allowed.as_slice().contains("Allow: POST, GET, HEAD, OPTIONS"));
 }
 
+#[test]
+fn routes_head_requests_to_get_routes () {
+    let route_store = &mut Router::new();
+    route_store.add_route(method::Get, "/health", handler);
+    route_store.add_route(method::Get, "/users", handler);
+    route_store.add_route(method::Head, "/users", handler);
+    route_store.add_route(method::Post, "/login", handler);
+
+    let route_result = route_store.match_route(&method::Head, "/health").unwrap();
+    assert_eq!(route_result.route.method, method::Get);
+    let route_result = route_store.match_route(&method::Head, "/users").unwrap();
+    assert_eq!(route_result.route.method, method::Head);
+    assert!(route_store.match_route(&method::Head, "/login").is_none());
+}
+
+#[test]
+fn registers_redirects_as_get_routes () {
+    let route_store = &mut Router::new();
+    route_store.redirect("/old-path", "/new-path", 301);
+
+    assert!(route_store.match_route(&method::Get, "/old-path").is_some());
+    assert!(route_store.match_route(&method::Post, "/old-path").is_none());
+}
+
+#[test]
+fn collects_the_middleware_of_routes () {
+    struct Noop;
+    impl Middleware for Noop {}
+
+    let mut admin = Router::new();
+    admin.utilize(Noop);
+    admin.add_route_with_middleware(method::Get, "/users", vec![box Noop as Box<Middleware + Send + Sync>,
+                                                               box Noop as Box<Middleware + Send + Sync>], handler);
+    admin.add_route(method::Get, "/stats", handler);
+
+    let route_store = &mut Router::new();
+    route_store.utilize(Noop);
+    route_store.add_route(method::Get, "/", handler);
+    route_store.mount("/admin", admin);
+
+    assert_eq!(route_store.match_route(&method::Get, "/").unwrap().middleware.len(), 1);
+    assert_eq!(route_store.match_route(&method::Get, "/admin/stats").unwrap().middleware.len(), 2);
+    assert_eq!(route_store.match_route(&method::Get, "/admin/users").unwrap().middleware.len(), 4);
+}
+
+#[test]
+fn skips_guarded_routes_without_a_request () {
+    fn guard (_request: &Request) -> bool { true }
+
+    let route_store = &mut Router::new();
+    route_store.add_guarded_route(method::Get, "/items", guard, handler);
+    route_store.add_route(method::Get, "/items", handler);
+
+    let route_result = route_store.match_route(&method::Get, "/items").unwrap();
+    assert!(route_result.route.guard.is_none());
+}
+
+#[test]
+fn leaves_paths_of_declining_guards_unrouted () {
+    use std::io::net::ip::Ipv4Addr;
+    use std::io::net::tcp::TcpStream;
+    use nickel::Nickel;
+
+    fn handler (_request: &Request, _response: &mut Response) -> &'static str {
+        "hello from the beta"
+    }
+
+    fn guard (_request: &Request) -> bool { false }
+
+    let mut route_store = Router::new();
+    route_store.add_guarded_route(method::Get, "/beta", guard, handler);
+    assert_eq!(route_store.allowed_methods("/beta"),
+               vec![method::Get, method::Head, method::Options]);
+
+    let mut server = Nickel::new();
+    server.utilize(route_store);
+    let listening = server.listen(Ipv4Addr(127, 0, 0, 1), 0);
+
+    let mut stream = TcpStream::connect(listening.socket_name()).unwrap();
+    stream.write(b"GET /beta HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let response = stream.read_to_string().unwrap();
+    listening.shutdown(0);
+
+    assert!(response.as_slice().starts_with("HTTP/1.1 404"));
+}
+
+#[test]
+fn creates_host_regex () {
+    let regex = path_utils::create_host_regex("*.Example.com");
+    assert!(regex.is_match("shop.example.com"));
+    assert!(regex.is_match("a.b.EXAMPLE.com"));
+    assert!(!regex.is_match("example.com"));
+    assert!(!regex.is_match("shop.example.com.evil.org"));
+    assert!(!regex.is_match("shopexample.com"));
+
+    let regex = path_utils::create_host_regex("example.com");
+    assert!(regex.is_match("example.com"));
+    assert!(!regex.is_match("examplexcom"));
+
+    let regex = path_utils::create_host_regex(":tenant.example.com");
+    let caps = regex.captures("acme.example.com").unwrap();
+    assert_eq!(caps.at(1), "acme");
+    assert!(!regex.is_match("a.b.example.com"));
+    assert_eq!(path_utils::get_host_variables(":tenant.*.example.com"), vec!["tenant".to_string()]);
+}
+
+#[test]
+fn can_match_format_suffixes () {
+    let route_store = &mut Router::new();
+    route_store.add_route(method::Get, "/reports/:id", handler);
+    route_store.add_route(method::Get, "/reports", handler);
+    route_store.add_route(method::Get, "/files/:name:uint", handler);
+    assert!(route_store.match_route(&method::Get, "/reports/7.json").is_none());
+
+    route_store.format_suffix(true);
+    let route_result = route_store.match_route(&method::Get, "/reports/7.json").unwrap();
+    assert_eq!(route_result.param("id"), "7");
+    assert_eq!(route_result.param("format"), "json");
+
+    let route_result = route_store.match_route(&method::Get, "/reports/7").unwrap();
+    assert_eq!(route_result.param("format"), "");
+
+    let route_result = route_store.match_route(&method::Get, "/reports.html?page=2").unwrap();
+    assert_eq!(route_result.param("format"), "html");
+    assert!(route_store.match_route(&method::Get, "/reportsxhtml").is_none());
+
+    let route_result = route_store.match_route(&method::Get, "/files/3.txt").unwrap();
+    assert_eq!(route_result.param("name"), "3");
+    assert_eq!(route_result.param("format"), "txt");
+}
+
+#[test]
+fn lists_routes () {
+    let mut api = Router::new();
+    api.get_named("user_files", "/users/:id/files/**path", handler);
+
+    let route_store = &mut Router::new();
+    route_store.add_route(method::Post, "/login", handler);
+    route_store.mount("/api", api);
+
+    let routes: Vec<RouteInfo> = route_store.routes().collect();
+    assert_eq!(routes.len(), 2);
+    assert_eq!(routes[0].method, &method::Post);
+    assert_eq!(routes[0].path.as_slice(), "/login");
+    assert_eq!(routes[0].name, None);
+    assert_eq!(routes[1].path.as_slice(), "/api/users/:id/files/**path");
+    assert_eq!(routes[1].name, Some("user_files"));
+    assert_eq!(routes[1].variables, vec!["id", "path"]);
+}
+
+#[test]
+fn finds_shadowed_routes () {
+    let route_store = &mut Router::new();
+    route_store.add_route(method::Get, "/users/:id", handler);
+    route_store.add_route(method::Get, "/posts/:id:uint", handler);
+
+    assert!(route_store.shadowing_route(&method::Get, "/users/:id", "/users/:id").is_some());
+    assert!(route_store.shadowing_route(&method::Get, "/users/new", "/users/new").is_some());
+    assert!(route_store.shadowing_route(&method::Post, "/users/new", "/users/new").is_none());
+    assert!(route_store.shadowing_route(&method::Get, "/posts/new", "/posts/new").is_none());
+    assert!(route_store.shadowing_route(&method::Get, "/users/:name", "/users/:name").is_none());
+}
+
+#[test]
+#[should_fail]
+fn can_deny_shadowed_routes () {
+    let route_store = &mut Router::new();
+    route_store.shadowed_routes(ShadowedRoutes::Deny);
+    route_store.add_route(method::Get, "/users/:id", handler);
+    route_store.add_route(method::Get, "/users/new", handler);
+}
+
+#[test]
+fn can_allow_shadowed_routes () {
+    let route_store = &mut Router::new();
+    route_store.add_route(method::Get, "/users/:id", handler);
+    route_store.shadowed_routes(ShadowedRoutes::Allow);
+
+    assert!(route_store.shadowing_route(&method::Get, "/users/:id", "/users/:id").is_none());
+    assert!(route_store.shadowing_route(&method::Get, "/users/new", "/users/new").is_none());
+}
+
+#[test]
+fn registers_handlers_for_several_methods () {
+    let route_store = &mut Router::new();
+    route_store.add(&[method::Get, method::Post], "/form", handler);
+    route_store.any("/webhook", handler);
+
+    assert!(route_store.match_route(&method::Post, "/form").is_some());
+    assert!(route_store.match_route(&method::Put, "/form").is_none());
+    assert_eq!(route_store.allowed_methods("/webhook"),
+               vec![method::Get, method::Post, method::Put, method::Patch, method::Delete,
+                    method::Head, method::Options]);
+}
+
+#[test]
+fn skips_matches_of_declining_routes () {
+    let mut api = Router::new();
+    api.add_route(method::Get, "/users/:name", handler);
+
+    let route_store = &mut Router::new();
+    route_store.add_route(method::Get, "/api/users/:id:uint", handler);
+    route_store.add_route(method::Get, "/api/users/:id", handler);
+    route_store.mount("/api", api);
+
+    let path = "/api/users/4711";
+    assert_eq!(route_store.resolve(&method::Get, path, None, 0).unwrap().route.path.as_slice(),
+               "/api/users/:id:uint");
+    assert_eq!(route_store.resolve(&method::Get, path, None, 1).unwrap().route.path.as_slice(),
+               "/api/users/:id");
+    assert_eq!(route_store.resolve(&method::Get, path, None, 2).unwrap().route.path.as_slice(),
+               "/users/:name");
+    assert!(route_store.resolve(&method::Get, path, None, 3).is_none());
+}
+
+#[test]
+fn runs_router_middleware_once_for_declining_routes () {
+    use std::io::net::ip::Ipv4Addr;
+    use std::io::net::tcp::TcpStream;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUint, SeqCst};
+    use nickel::Nickel;
+
+    struct Counter(Arc<AtomicUint>);
+    impl Middleware for Counter {
+        fn invoke(&self, _req: &mut Request, _res: &mut Response) -> MiddlewareResult {
+            let Counter(ref count) = *self;
+            count.fetch_add(1, SeqCst);
+            Ok(Continue)
+        }
+    }
+
+    fn decline (_request: &Request, _response: &mut Response) -> MiddlewareResult {
+        Ok(Continue)
+    }
+
+    fn handler (_request: &Request, _response: &mut Response) -> &'static str {
+        "hello from the api"
+    }
+
+    let outer_count = Arc::new(AtomicUint::new(0));
+    let api_count = Arc::new(AtomicUint::new(0));
+
+    let mut api = Router::new();
+    api.utilize(Counter(api_count.clone()));
+    api.add_route(method::Get, "/users/:id", decline);
+    api.add_route(method::Get, "/users/:name", handler);
+
+    let mut route_store = Router::new();
+    route_store.utilize(Counter(outer_count.clone()));
+    route_store.add_route(method::Get, "/api/users/:id", decline);
+    route_store.mount("/api", api);
+
+    let mut server = Nickel::new();
+    server.utilize(route_store);
+    let listening = server.listen(Ipv4Addr(127, 0, 0, 1), 0);
+
+    let mut stream = TcpStream::connect(listening.socket_name()).unwrap();
+    stream.write(b"GET /api/users/4711 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let response = stream.read_to_string().unwrap();
+    listening.shutdown(0);
+
+    assert!(response.as_slice().contains("hello from the api"));
+    assert_eq!(outer_count.load(SeqCst), 1);
+    assert_eq!(api_count.load(SeqCst), 1);
+}
+
+#[test]
+fn extends_routers () {
+    let mut users = Router::new();
+    users.get_named("user_show", "/users/:id", handler);
+
+    let mut posts = Router::new();
+    posts.add_route(method::Get, "/posts/:id", handler);
+
+    let route_store = &mut Router::new();
+    route_store.case_insensitive(true);
+    route_store.add_route(method::Get, "/", handler);
+    route_store.extend_prefixed("/admin/", "admin_", users);
+    route_store.extend(posts);
+
+    let route_result = route_store.match_route(&method::Get, "/Admin/users/4711").unwrap();
+    assert_eq!(route_result.param("id"), "4711");
+    assert!(route_store.match_route(&method::Get, "/posts/1").is_some());
+    assert_eq!(route_store.url_for("admin_user_show", &[("id", "4711")]),
+               Some("/admin/users/4711".to_string()));
+}
+
+#[test]
+fn removes_routes () {
+    let route_store = &mut Router::new();
+    route_store.add_route(method::Get, "/users/:id", handler);
+    route_store.add_route(method::Get, "/posts/:id", handler);
+    route_store.add_route(method::Delete, "/posts/:id", handler);
+
+    assert!(route_store.remove_route(&method::Get, "/posts/:id"));
+    assert!(!route_store.remove_route(&method::Get, "/posts/:id"));
+    assert!(route_store.match_route(&method::Get, "/posts/1").is_none());
+    assert!(route_store.match_route(&method::Delete, "/posts/1").is_some());
+    assert!(route_store.match_route(&method::Get, "/users/1").is_some());
+}
+
+#[test]
+fn parses_params () {
+    let route_store = &mut Router::new();
+
+    route_store.add_route(method::Get, "/articles/:id/:page?", handler);
+
+    let route_result = route_store.match_route(&method::Get, "/articles/4711").unwrap();
+    assert_eq!(route_result.param_as::<uint>("id"), Ok(4711));
+    assert_eq!(route_result.param_as::<uint>("page"), Err(ParamError::Missing("page".to_string())));
+    assert_eq!(route_result.param_as::<uint>("author"), Err(ParamError::Missing("author".to_string())));
+    assert_eq!(route_result.find_param("author"), None);
+
+    let route_result = route_store.match_route(&method::Get, "/articles/abc").unwrap();
+    assert_eq!(route_result.param_as::<uint>("id"),
+               Err(ParamError::Invalid("id".to_string(), "abc".to_string())));
+    assert_eq!(route_result.param_as::<String>("id"), Ok("abc".to_string()));
+}
@@ -0,0 +1,104 @@
+use std::ascii::AsciiExt;
+use std::collections::TreeMap;
+
+use request::Request;
+use response::Response;
+use middleware::{Continue, Middleware, MiddlewareResult};
+
+const FRAME_OPTIONS: &'static str = "X-Frame-Options";
+const CONTENT_TYPE_OPTIONS: &'static str = "X-Content-Type-Options";
+const XSS_PROTECTION: &'static str = "X-XSS-Protection";
+const REFERRER_POLICY: &'static str = "Referrer-Policy";
+
+/// A middleware adding headers which make browsers apply some baseline
+/// protections to all responses:
+///
+/// * `X-Frame-Options: SAMEORIGIN`
+/// * `X-Content-Type-Options: nosniff`
+/// * `X-XSS-Protection: 1; mode=block`
+/// * `Referrer-Policy: strict-origin-when-cross-origin`
+///
+/// The headers are added before the request is handled, so handlers can
+/// still change them for single responses.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, SecurityHeaders};
+///
+/// let mut server = Nickel::new();
+/// server.utilize(SecurityHeaders::new().frame_options("DENY")
+///                                      .without("X-XSS-Protection"));
+/// ```
+#[deriving(Clone)]
+pub struct SecurityHeaders {
+    headers: TreeMap<String, String>
+}
+
+impl SecurityHeaders {
+    pub fn new() -> SecurityHeaders {
+        SecurityHeaders { headers: TreeMap::new() }
+            .header(FRAME_OPTIONS, "SAMEORIGIN")
+            .header(CONTENT_TYPE_OPTIONS, "nosniff")
+            .header(XSS_PROTECTION, "1; mode=block")
+            .header(REFERRER_POLICY, "strict-origin-when-cross-origin")
+    }
+
+    /// Sets the `X-Frame-Options` header, e.g. to `DENY`.
+    pub fn frame_options(self, value: &str) -> SecurityHeaders {
+        self.header(FRAME_OPTIONS, value)
+    }
+
+    /// Sets the `X-Content-Type-Options` header.
+    pub fn content_type_options(self, value: &str) -> SecurityHeaders {
+        self.header(CONTENT_TYPE_OPTIONS, value)
+    }
+
+    /// Sets the `X-XSS-Protection` header, e.g. to `0`.
+    pub fn xss_protection(self, value: &str) -> SecurityHeaders {
+        self.header(XSS_PROTECTION, value)
+    }
+
+    /// Sets the `Referrer-Policy` header, e.g. to `no-referrer`.
+    pub fn referrer_policy(self, value: &str) -> SecurityHeaders {
+        self.header(REFERRER_POLICY, value)
+    }
+
+    /// Adds any other header, e.g. a `Content-Security-Policy`.
+    pub fn header(mut self, name: &str, value: &str) -> SecurityHeaders {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Leaves out one of the headers.
+    pub fn without(mut self, name: &str) -> SecurityHeaders {
+        let name = self.headers.keys()
+                               .find(|header| header.as_slice().eq_ignore_ascii_case(name))
+                               .map(|header| header.clone());
+        match name {
+            Some(name) => { self.headers.remove(&name); },
+            None => {}
+        }
+        self
+    }
+}
+
+impl Middleware for SecurityHeaders {
+    fn invoke(&self, _req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        for (name, value) in self.headers.iter() {
+            res.origin.headers.extensions.insert(name.clone(), value.clone());
+        }
+        Ok(Continue)
+    }
+}
+
+#[test]
+fn overrides_and_removes_headers() {
+    let headers = SecurityHeaders::new().frame_options("DENY")
+                                        .without("x-xss-protection")
+                                        .headers;
+
+    assert_eq!(headers.get(FRAME_OPTIONS).map(|value| value.as_slice()), Some("DENY"));
+    assert_eq!(headers.get(CONTENT_TYPE_OPTIONS).map(|value| value.as_slice()), Some("nosniff"));
+    assert!(headers.get(XSS_PROTECTION).is_none());
+    assert_eq!(headers.len(), 3);
+}
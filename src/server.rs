@@ -1,49 +1,590 @@
-use std::io::net::ip::{SocketAddr, IpAddr, Port};
-use std::sync::{Arc, RWLock};
+use std::cmp;
 use std::collections::HashMap;
+use std::io::{Acceptor, Listener, BufferedStream, EndOfFile};
+use std::io::net::addrinfo;
+use std::io::net::ip::{SocketAddr, Port};
+use std::io::net::tcp::{TcpListener, TcpAcceptor, TcpStream};
+use std::io::timer;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUint, SeqCst};
+use std::rt::unwind;
+use std::time::Duration;
+use time;
 
-use http::server::{Config, Request, ResponseWriter};
-use http::server::Server as HttpServer;
+use http::server::{Request, ResponseWriter};
+use http::headers::HeaderEnum;
+use http::headers::connection::Close;
+use http::status::{Status, InternalServerError, RequestTimeout, RequestHeaderFieldsTooLarge};
 
 use middleware::MiddlewareStack;
 use request;
 use response;
-use mustache;
+use template_engine::Templates;
+use websocket::{WebSocket, WebSocketHandler};
+
+/// How the server handles connections, see the methods of `Nickel`.
+pub struct ServerOptions {
+    /// The number of tasks accepting connections per address
+    pub acceptors: uint,
+    /// The number of tasks handling connections, `None` spawns a task for
+    /// every connection
+    pub workers: Option<uint>,
+    /// The milliseconds a client has to send the first request
+    pub read_timeout: Option<u64>,
+    pub keep_alive: bool,
+    /// The milliseconds a client has to send the next request on a
+    /// persistent connection
+    pub keep_alive_timeout: Option<u64>,
+    /// The number of requests after which a persistent connection is closed
+    pub max_keep_alive_requests: Option<uint>,
+    /// The number of headers a request may have. Like the other header
+    /// limits it's checked once rust-http read all headers.
+    pub max_headers: Option<uint>,
+    /// The bytes a single header may take
+    pub max_header_size: Option<uint>,
+    /// The bytes all headers of a request may take
+    pub max_header_section_size: Option<uint>
+}
+
+impl ServerOptions {
+    pub fn new() -> ServerOptions {
+        ServerOptions {
+            acceptors: 1,
+            workers: None,
+            read_timeout: Some(30_000),
+            keep_alive: true,
+            keep_alive_timeout: Some(5_000),
+            max_keep_alive_requests: None,
+            max_headers: Some(100),
+            max_header_size: Some(8 * 1024),
+            max_header_section_size: Some(64 * 1024)
+        }
+    }
+}
 
 pub struct Server {
     middleware_stack: MiddlewareStack,
-    ip: IpAddr,
-    port: Port,
-    templates: response::TemplateCache
+    templates: Templates,
+    options: ServerOptions
 }
 
-impl HttpServer for Arc<Server> {
-    fn get_config(&self) -> Config {
-        Config { bind_address: SocketAddr { ip: self.ip, port: self.port } }
+impl Server {
+    pub fn new(middleware_stack: MiddlewareStack, templates: Templates,
+               options: ServerOptions) -> Server {
+        Server {
+            middleware_stack: middleware_stack,
+            templates: templates,
+            options: options
+        }
     }
 
-    fn handle_request(&self, req: Request, res: &mut ResponseWriter) {
+    // Returns the handler taking over the connection if it was upgraded to
+    // the WebSocket protocol.
+    fn handle_request(&self, req: Request, res: &mut ResponseWriter)
+                      -> Option<Box<WebSocketHandler + Send + 'static>> {
 
         let nickel_req = &mut request::Request::from_internal(&req);
-        let nickel_res = &mut response::Response::from_internal(res, &self.templates);
+        let nickel_res = &mut response::Response::from_internal(res, &req, &self.templates);
+
+        // A panicking handler would take down the task along with the
+        // connection, so catch the panic and answer with a 500 instead.
+        let result = unsafe {
+            unwind::try(|| self.middleware_stack.invoke(nickel_req, nickel_res))
+        };
 
-        self.middleware_stack.invoke(nickel_req, nickel_res);
+        let upgrade = match result {
+            Ok(()) => nickel_res.take_upgrade(),
+            Err(cause) => {
+                let message = match cause.downcast_ref::<&'static str>() {
+                    Some(message) => message.to_string(),
+                    None => match cause.downcast_ref::<String>() {
+                        Some(message) => message.clone(),
+                        None => "Box<Any>".to_string()
+                    }
+                };
+                error!("Panicked while handling {} {}: {}", req.method, req.request_uri, message);
+
+                if !nickel_res.is_started() {
+                    nickel_res.origin.status = InternalServerError;
+                    nickel_res.send("Internal Server Error");
+                }
+                None
+            }
+        };
+
+        // Make sure the response hooks run even if nothing was written
+        nickel_res.finish();
+        upgrade
+    }
+
+    // rust-http's `serve_forever` can't be stopped, so connections are
+    // accepted here the same way, but with a way out.
+    //
+    // All addresses get bound before any connection is accepted, so either
+    // the server listens on all of them or it panics.
+    pub fn serve(self, addresses: &[SocketAddr]) -> Listening {
+        assert!(!addresses.is_empty(), "No address to listen on");
+
+        let mut acceptors = Vec::new();
+        let mut bound = Vec::new();
+        for &address in addresses.iter() {
+            let mut acceptor = match TcpListener::bind(address).listen() {
+                Ok(acceptor) => acceptor,
+                Err(err) => panic!("Couldn't listen on {}: {}", address, err)
+            };
+            // binding to port 0 picks a free port
+            match acceptor.socket_name() {
+                Ok(address) => bound.push(address),
+                Err(err) => panic!("Couldn't get the address of the socket: {}", err)
+            }
+            acceptors.push(acceptor);
+        }
+
+        let connections = Arc::new(Connections::new());
+        let (done_tx, done_rx) = channel();
+        let listening = Listening {
+            acceptors: acceptors.clone(),
+            connections: connections.clone(),
+            done: done_rx,
+            stopped: false,
+            addresses: bound
+        };
+
+        let server = Arc::new(self);
+        let workers = server.options.workers.map(|count| {
+            start_workers(cmp::max(count, 1), &server, &connections)
+        });
+        let acceptors_per_address = cmp::max(server.options.acceptors, 1);
+        let running = Arc::new(AtomicUint::new(acceptors_per_address * acceptors.len()));
+
+        // every address gets its own acceptor tasks
+        for acceptor in acceptors.iter() {
+            for _ in range(0, acceptors_per_address) {
+                let acceptor = acceptor.clone();
+                let server = server.clone();
+                let connections = connections.clone();
+                let workers = workers.clone();
+                let running = running.clone();
+                let done_tx = done_tx.clone();
+
+                spawn(proc() {
+                    accept(acceptor, &server, &connections, workers);
+
+                    // the last acceptor to stop waits for the connections
+                    if running.fetch_sub(1, SeqCst) == 1 {
+                        connections.drain();
+                        let _ = done_tx.send_opt(());
+                    }
+                });
+            }
+        }
+
+        listening
     }
 }
 
-impl Server {
-    pub fn new(middleware_stack: MiddlewareStack, ip: IpAddr, port: Port) -> Server {
-        Server {
-            middleware_stack: middleware_stack,
-            ip: ip,
-            port: port,
-            templates: RWLock::new(HashMap::<&'static str, mustache::Template>::new())
+/// Parses an address with a port, like `127.0.0.1:6767` or `[::1]:6767`, or
+/// resolves a host name with a port, like `localhost:6767`.
+pub fn resolve_address(address: &str) -> Result<Vec<SocketAddr>, String> {
+    match from_str::<SocketAddr>(address) {
+        Some(address) => return Ok(vec![address]),
+        None => {}
+    }
+
+    let (host, port) = match address.rfind(':') {
+        Some(colon) => (address.slice_to(colon), address.slice_from(colon + 1)),
+        None => return Err("the port is missing".to_string())
+    };
+    let port = match from_str::<Port>(port) {
+        Some(port) => port,
+        None => return Err(format!("invalid port {}", port))
+    };
+    // a bracketed IPv6 address would have been parsed already
+    if host.is_empty() || host.contains_char(':') || host.contains_char('[') {
+        return Err("invalid host".to_string())
+    }
+
+    match addrinfo::get_host_addresses(host) {
+        Ok(ips) => {
+            let mut addresses: Vec<SocketAddr> = Vec::new();
+            for ip in ips.into_iter() {
+                let address = SocketAddr { ip: ip, port: port };
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+            if addresses.is_empty() {
+                Err(format!("{} doesn't resolve to any address", host))
+            } else {
+                Ok(addresses)
+            }
+        },
+        Err(err) => Err(format!("couldn't resolve {}: {}", host, err))
+    }
+}
+
+fn accept(mut acceptor: TcpAcceptor, server: &Arc<Server>, connections: &Arc<Connections>,
+          workers: Option<Sender<TcpStream>>) {
+    for stream in acceptor.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            // the acceptor was closed by `Listening::shutdown`
+            Err(ref err) if err.kind == EndOfFile => break,
+            Err(err) => {
+                error!("Couldn't accept connection: {}", err);
+                continue
+            }
+        };
+
+        match workers {
+            Some(ref workers) => {
+                if workers.send_opt(stream).is_err() {
+                    break
+                }
+            },
+            None => {
+                let server = server.clone();
+                let connections = connections.clone();
+                spawn(proc() handle_connection(server, connections, stream));
+            }
         }
     }
+}
+
+// Starts a fixed number of tasks handling the connections passed through the
+// returned channel, one after the other.
+fn start_workers(count: uint, server: &Arc<Server>, connections: &Arc<Connections>)
+                 -> Sender<TcpStream> {
+    let (tx, rx) = channel();
+    let rx = Arc::new(Mutex::new(rx));
+
+    for _ in range(0, count) {
+        let rx = rx.clone();
+        let server = server.clone();
+        let connections = connections.clone();
 
-    // why do we need this? Is the http::Server.serve_forever method protected in C# terms?
-    pub fn serve(self) {
-        let arc = Arc::new(self);
-        arc.serve_forever();
+        spawn(proc() {
+            loop {
+                let stream = {
+                    let rx = rx.lock();
+                    rx.recv_opt()
+                };
+                match stream {
+                    Ok(stream) => handle_connection(server.clone(), connections.clone(), stream),
+                    // all acceptors stopped
+                    Err(()) => break
+                }
+            }
+        });
     }
+
+    tx
+}
+
+// Rejects requests with too many or too large headers before they get to
+// the middleware.
+//
+// FIXME: rust-http reads all headers before handing out the request, so
+// the limits can't keep it from reading them into memory.
+fn check_header_limits(sizes: &[uint], options: &ServerOptions) -> Result<(), Status> {
+    let exceeds = |limit: Option<uint>, value: uint| limit.map_or(false, |limit| value > limit);
+
+    if exceeds(options.max_headers, sizes.len()) ||
+       sizes.iter().any(|&size| exceeds(options.max_header_size, size)) ||
+       exceeds(options.max_header_section_size, sizes.iter().fold(0, |total, &size| total + size)) {
+        Err(RequestHeaderFieldsTooLarge)
+    } else {
+        Ok(())
+    }
+}
+
+fn handle_connection(server: Arc<Server>, connections: Arc<Connections>, stream: TcpStream) {
+    let id = match connections.add(stream.clone()) {
+        Some(id) => id,
+        None => return
+    };
+    let options = &server.options;
+    let mut stream = BufferedStream::new(stream);
+    let mut handled = 0u;
+
+    loop {
+        let timeout = if handled == 0 { options.read_timeout } else { options.keep_alive_timeout };
+        let deadline = timeout.map(|ms| time::precise_time_ns() + ms * 1_000_000);
+        stream.get_mut().set_read_timeout(timeout);
+        let (request, status) = Request::load(&mut stream);
+        stream.get_mut().set_read_timeout(None);
+
+        // idle connections get closed on shutdown, which fails the read
+        if connections.is_shutting_down() && status.is_err() {
+            break
+        }
+        let timed_out = status.is_err() &&
+                        deadline.map_or(false, |deadline| time::precise_time_ns() >= deadline);
+        // an idle persistent connection just gets closed
+        if timed_out && handled > 0 {
+            break
+        }
+        connections.set_busy(id, true);
+        handled += 1;
+
+        let close_connection = request.close_connection || timed_out || !options.keep_alive ||
+                               options.max_keep_alive_requests.map_or(false, |max| handled >= max) ||
+                               connections.is_shutting_down();
+        let upgrade = {
+            let mut response = ResponseWriter::new(&mut stream);
+            if close_connection {
+                response.headers.connection = Some(vec![Close]);
+            }
+
+            let status = status.and_then(|()| {
+                let sizes: Vec<uint> = request.headers.iter().map(|header| {
+                    header.header_name().len() + header.header_value().len()
+                }).collect();
+                check_header_limits(sizes.as_slice(), options)
+            });
+
+            let upgrade = match status {
+                Ok(()) => {
+                    let upgrade = server.handle_request(request, &mut response);
+                    // Ensure that we actually do send a response
+                    match response.try_write_headers() {
+                        Err(err) => {
+                            error!("Couldn't write headers: {}", err);
+                            break
+                        },
+                        Ok(_) => {}
+                    }
+                    upgrade
+                },
+                Err(status) => {
+                    // The request couldn't be parsed, so there's nothing to
+                    // hand to the middleware
+                    response.status = if timed_out { RequestTimeout } else { status };
+                    response.headers.content_length = Some(0);
+                    match response.write_headers() {
+                        Err(err) => {
+                            error!("Couldn't write headers: {}", err);
+                            break
+                        },
+                        Ok(_) => {}
+                    }
+                    None
+                }
+            };
+
+            // An upgraded connection speaks another protocol after the
+            // headers, so there's no body to finish
+            let finished = match upgrade {
+                Some(_) => response.flush(),
+                None => response.finish_response()
+            };
+            match finished {
+                Err(err) => {
+                    error!("Couldn't finish the response: {}", err);
+                    break
+                },
+                Ok(_) => {}
+            }
+            upgrade
+        };
+
+        match upgrade {
+            Some(handler) => {
+                // The handler keeps the connection until it returns, and
+                // it's closed afterwards. It doesn't count as busy, so a
+                // shutdown closes it instead of waiting for the drain timeout
+                connections.set_busy(id, false);
+                handler.handle(WebSocket::new(stream));
+                break
+            },
+            None => {}
+        }
+
+        connections.set_busy(id, false);
+        if close_connection || connections.is_shutting_down() {
+            break
+        }
+    }
+
+    connections.remove(id);
+}
+
+struct Connection {
+    stream: TcpStream,
+    busy: bool
+}
+
+// The open connections, so they can be closed on shutdown
+struct Connections {
+    open: Mutex<HashMap<uint, Connection>>,
+    next_id: AtomicUint,
+    shutting_down: AtomicBool,
+    drain_timeout_ms: AtomicUint
+}
+
+impl Connections {
+    fn new() -> Connections {
+        Connections {
+            open: Mutex::new(HashMap::new()),
+            next_id: AtomicUint::new(0),
+            shutting_down: AtomicBool::new(false),
+            drain_timeout_ms: AtomicUint::new(0)
+        }
+    }
+
+    // Returns `None` if the server is shutting down already
+    fn add(&self, stream: TcpStream) -> Option<uint> {
+        let mut open = self.open.lock();
+        if self.is_shutting_down() {
+            return None
+        }
+
+        let id = self.next_id.fetch_add(1, SeqCst);
+        open.insert(id, Connection { stream: stream, busy: false });
+        Some(id)
+    }
+
+    fn set_busy(&self, id: uint, busy: bool) {
+        match self.open.lock().get_mut(&id) {
+            Some(connection) => connection.busy = busy,
+            None => {}
+        }
+    }
+
+    fn remove(&self, id: uint) {
+        self.open.lock().remove(&id);
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(SeqCst)
+    }
+
+    // Closes the idle connections and waits for the busy ones until the drain
+    // timeout is up, after which they're closed, too.
+    fn drain(&self) {
+        self.shutting_down.store(true, SeqCst);
+        let deadline = time::precise_time_ns() + self.drain_timeout_ms.load(SeqCst) as u64 * 1_000_000;
+
+        loop {
+            {
+                let mut open = self.open.lock();
+                let timed_out = time::precise_time_ns() >= deadline;
+                for (_, connection) in open.iter_mut() {
+                    if timed_out || !connection.busy {
+                        let _ = connection.stream.close_read();
+                    }
+                    if timed_out {
+                        let _ = connection.stream.close_write();
+                    }
+                }
+                if open.is_empty() || timed_out {
+                    return
+                }
+            }
+            timer::sleep(Duration::milliseconds(10));
+        }
+    }
+}
+
+/// A running server, returned by `Nickel::listen`. Dropping it waits until
+/// the server stopped, which only happens after a `shutdown`, so a plain
+/// `server.listen(..)` serves forever.
+pub struct Listening {
+    acceptors: Vec<TcpAcceptor>,
+    connections: Arc<Connections>,
+    done: Receiver<()>,
+    stopped: bool,
+    addresses: Vec<SocketAddr>
+}
+
+impl Listening {
+    /// Returns the address the server is bound to. That's how to find out
+    /// which port got picked when listening on port 0.
+    ///
+    /// # Example
+    /// ```{rust,ignore}
+    /// let listening = server.listen(Ipv4Addr(127, 0, 0, 1), 0);
+    /// println!("Bound to port {}", listening.socket_name().port);
+    /// ```
+    ///
+    /// For a server listening on several addresses that's the first one.
+    pub fn socket_name(&self) -> SocketAddr {
+        self.addresses[0]
+    }
+
+    /// Returns all addresses the server is bound to.
+    pub fn socket_names(&self) -> &[SocketAddr] {
+        self.addresses.as_slice()
+    }
+
+    /// Stops accepting new connections on all addresses and closes idle ones. Requests in
+    /// progress get the given milliseconds to finish before their
+    /// connections are closed, too. Returns once all connections are closed.
+    ///
+    /// # Example
+    /// ```{rust,ignore}
+    /// let listening = server.listen(Ipv4Addr(127, 0, 0, 1), 6767);
+    /// // ... on the way to roll out a new version
+    /// listening.shutdown(5000);
+    /// ```
+    pub fn shutdown(mut self, drain_timeout_ms: u64) {
+        self.connections.drain_timeout_ms.store(drain_timeout_ms as uint, SeqCst);
+        for acceptor in self.acceptors.iter_mut() {
+            match acceptor.close_accept() {
+                Ok(()) => {},
+                Err(err) => error!("Couldn't stop accepting connections: {}", err)
+            }
+        }
+        self.join();
+    }
+
+    /// Blocks until the server stopped.
+    pub fn wait(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        if !self.stopped {
+            let _ = self.done.recv_opt();
+            self.stopped = true;
+        }
+    }
+}
+
+impl Drop for Listening {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+#[test]
+fn resolves_addresses() {
+    use std::io::net::ip::{Ipv4Addr, Ipv6Addr};
+
+    assert_eq!(resolve_address("127.0.0.1:6767"),
+               Ok(vec![SocketAddr { ip: Ipv4Addr(127, 0, 0, 1), port: 6767 }]));
+    assert_eq!(resolve_address("[::]:8080"),
+               Ok(vec![SocketAddr { ip: Ipv6Addr(0, 0, 0, 0, 0, 0, 0, 0), port: 8080 }]));
+    assert!(resolve_address("127.0.0.1").is_err());
+    assert!(resolve_address("::1:8080").is_err());
+    assert!(resolve_address("localhost:http").is_err());
+}
+
+#[test]
+fn limits_headers() {
+    let mut options = ServerOptions::new();
+    options.max_headers = Some(3);
+    options.max_header_size = Some(10);
+    options.max_header_section_size = Some(20);
+
+    assert_eq!(check_header_limits(&[5, 10], &options), Ok(()));
+    assert_eq!(check_header_limits(&[1, 1, 1, 1], &options), Err(RequestHeaderFieldsTooLarge));
+    assert_eq!(check_header_limits(&[11], &options), Err(RequestHeaderFieldsTooLarge));
+    assert_eq!(check_header_limits(&[10, 10, 1], &options), Err(RequestHeaderFieldsTooLarge));
+
+    let mut options = ServerOptions::new();
+    options.max_headers = None;
+    options.max_header_size = None;
+    options.max_header_section_size = None;
+    assert_eq!(check_header_limits(&[100_000, 100_000], &options), Ok(()));
 }
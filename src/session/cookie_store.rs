@@ -0,0 +1,145 @@
+use std::time::Duration;
+use serialize::json;
+use serialize::base64::{ToBase64, FromBase64, URL_SAFE};
+use crypto::hmac::Hmac;
+use crypto::mac::{Mac, MacResult};
+use crypto::sha2::Sha256;
+use time;
+
+use session::{SessionStore, SessionData};
+
+/// A SessionStore which keeps the session data on the client. The data is
+/// serialized into the session cookie itself and signed with HMAC-SHA256,
+/// so tampered cookies are rejected. The data is readable by the client, so
+/// don't put secrets into it and keep in mind that cookies are limited to
+/// about 4KB.
+///
+/// Keys can be rotated by passing several of them: new cookies get signed
+/// with the first key while cookies signed with any of the keys are accepted.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, SessionMiddleware, CookieSessionStore};
+///
+/// let store = CookieSessionStore::new(b"a long and random secret key");
+/// let mut server = Nickel::new();
+/// server.utilize(SessionMiddleware::new(store));
+/// ```
+pub struct CookieSessionStore {
+    keys: Vec<Vec<u8>>
+}
+
+impl CookieSessionStore {
+    /// Creates a store signing cookies with the given key.
+    pub fn new(key: &[u8]) -> CookieSessionStore {
+        CookieSessionStore::with_keys(vec![key.to_vec()])
+    }
+
+    /// Creates a store signing cookies with the first of the given keys and
+    /// accepting cookies signed with any of them.
+    pub fn with_keys(keys: Vec<Vec<u8>>) -> CookieSessionStore {
+        assert!(!keys.is_empty(), "CookieSessionStore needs at least one key");
+        CookieSessionStore { keys: keys }
+    }
+
+    fn is_signed_by_any_key(&self, payload: &str, signature: &[u8]) -> bool {
+        // MacResult compares in constant time
+        let expected = MacResult::new(signature);
+        self.keys.iter().any(|key| sign(key.as_slice(), payload) == expected)
+    }
+}
+
+fn sign(key: &[u8], payload: &str) -> MacResult {
+    let mut hmac = Hmac::new(Sha256::new(), key);
+    hmac.input(payload.as_bytes());
+    hmac.result()
+}
+
+impl SessionStore for CookieSessionStore {
+    // A key looks like `<base64 encoded JSON data>.<expiry timestamp>.<signature>`
+    fn load(&self, key: &str) -> Option<SessionData> {
+        let parts: Vec<&str> = key.split('.').collect();
+        if parts.len() != 3 {
+            return None
+        }
+
+        let payload = key.slice_to(parts[0].len() + 1 + parts[1].len());
+        let signature = match parts[2].from_base64() {
+            Ok(signature) => signature,
+            Err(_) => return None
+        };
+
+        if !self.is_signed_by_any_key(payload, signature.as_slice()) {
+            return None
+        }
+
+        match from_str::<i64>(parts[1]) {
+            Some(expires) if expires > time::get_time().sec => {},
+            _ => return None
+        }
+
+        parts[0].from_base64().ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .and_then(|data| json::decode::<SessionData>(data.as_slice()).ok())
+    }
+
+    fn save(&self, _key: &str, data: &SessionData, ttl: Duration) -> String {
+        let expires = time::get_time().sec + ttl.num_seconds();
+        let payload = format!("{}.{}", json::encode(data).as_bytes().to_base64(URL_SAFE), expires);
+        let signature = sign(self.keys[0].as_slice(), payload.as_slice());
+
+        format!("{}.{}", payload, signature.code().to_base64(URL_SAFE))
+    }
+
+    // The data only lives in the cookie, which gets deleted by the middleware
+    fn destroy(&self, _key: &str) {}
+}
+
+#[test]
+fn loads_saved_sessions() {
+    use std::collections::HashMap;
+    let store = CookieSessionStore::new(b"secret");
+    let mut data = HashMap::new();
+    data.insert("user".to_string(), "john".to_string());
+
+    let key = store.save("", &data, Duration::hours(1));
+    assert_eq!(store.load(key.as_slice()), Some(data));
+}
+
+#[test]
+fn rejects_tampered_sessions() {
+    use std::collections::HashMap;
+    let store = CookieSessionStore::new(b"secret");
+    let mut data = HashMap::new();
+    data.insert("user".to_string(), "john".to_string());
+    let key = store.save("", &data, Duration::hours(1));
+
+    let mut forged = HashMap::new();
+    forged.insert("user".to_string(), "admin".to_string());
+    let forged_payload = json::encode(&forged).as_bytes().to_base64(URL_SAFE);
+    let signature_start = key.as_slice().find('.').unwrap();
+    let tampered = format!("{}{}", forged_payload, key.slice_from(signature_start));
+
+    assert_eq!(store.load(tampered.as_slice()), None);
+    assert_eq!(store.load("garbage"), None);
+    assert_eq!(CookieSessionStore::new(b"other secret").load(key.as_slice()), None);
+}
+
+#[test]
+fn rejects_expired_sessions() {
+    use std::collections::HashMap;
+    let store = CookieSessionStore::new(b"secret");
+    let key = store.save("", &HashMap::new(), Duration::seconds(-1));
+
+    assert_eq!(store.load(key.as_slice()), None);
+}
+
+#[test]
+fn accepts_sessions_signed_with_rotated_keys() {
+    use std::collections::HashMap;
+    let old_store = CookieSessionStore::new(b"old secret");
+    let key = old_store.save("", &HashMap::new(), Duration::hours(1));
+
+    let store = CookieSessionStore::with_keys(vec![b"new secret".to_vec(), b"old secret".to_vec()]);
+    assert_eq!(store.load(key.as_slice()), Some(HashMap::new()));
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::io::timer;
+use std::sync::{Arc, RWLock};
+use std::time::Duration;
+use time;
+use time::Timespec;
+
+use session::{SessionStore, SessionData};
+
+/// A SessionStore which keeps the sessions in memory. Sessions are lost when
+/// the server shuts down and aren't shared between several server processes,
+/// which makes this store a good fit for small apps and development.
+///
+/// Expired sessions are never loaded, but they only get evicted by `sweep`.
+/// Use `MemorySessionStore::with_sweeper` to get a store which sweeps itself
+/// periodically in the background.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, SessionMiddleware, MemorySessionStore};
+/// use std::time::Duration;
+///
+/// let store = MemorySessionStore::with_sweeper(Duration::minutes(5));
+/// let mut server = Nickel::new();
+/// server.utilize(SessionMiddleware::new(store).ttl(Duration::hours(2)));
+/// ```
+pub struct MemorySessionStore {
+    sessions: RWLock<HashMap<String, (SessionData, Timespec)>>
+}
+
+impl MemorySessionStore {
+    pub fn new() -> MemorySessionStore {
+        MemorySessionStore {
+            sessions: RWLock::new(HashMap::new())
+        }
+    }
+
+    /// Creates a store along with a background task which evicts expired
+    /// sessions in the given interval. The task ends once the store is dropped.
+    pub fn with_sweeper(interval: Duration) -> Arc<MemorySessionStore> {
+        let store = Arc::new(MemorySessionStore::new());
+        let weak_store = store.downgrade();
+
+        spawn(proc() {
+            loop {
+                timer::sleep(interval);
+                match weak_store.upgrade() {
+                    Some(store) => store.sweep(),
+                    None => break
+                }
+            }
+        });
+
+        store
+    }
+
+    /// Evicts all expired sessions.
+    pub fn sweep(&self) {
+        let now = time::get_time();
+        let mut sessions = self.sessions.write();
+        let expired: Vec<String> = sessions.iter()
+                                           .filter(|&(_, &(_, expires))| expires <= now)
+                                           .map(|(key, _)| key.clone())
+                                           .collect();
+
+        for key in expired.iter() {
+            sessions.remove(key);
+        }
+    }
+
+    /// Returns the number of stored sessions, including expired ones which
+    /// weren't swept yet.
+    pub fn len(&self) -> uint {
+        self.sessions.read().len()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load(&self, key: &str) -> Option<SessionData> {
+        match self.sessions.read().get(key) {
+            Some(&(ref data, expires)) if expires > time::get_time() => Some(data.clone()),
+            _ => None
+        }
+    }
+
+    fn save(&self, key: &str, data: &SessionData, ttl: Duration) -> String {
+        let expires = time::get_time() + ttl;
+        self.sessions.write().insert(key.to_string(), (data.clone(), expires));
+        key.to_string()
+    }
+
+    fn destroy(&self, key: &str) {
+        self.sessions.write().remove(key);
+    }
+}
+
+#[test]
+fn loads_saved_sessions() {
+    let store = MemorySessionStore::new();
+    let mut data = HashMap::new();
+    data.insert("user".to_string(), "john".to_string());
+
+    let key = store.save("abc", &data, Duration::hours(1));
+    assert_eq!(key.as_slice(), "abc");
+    assert_eq!(store.load("abc"), Some(data));
+
+    store.destroy("abc");
+    assert_eq!(store.load("abc"), None);
+}
+
+#[test]
+fn sweeps_expired_sessions() {
+    let store = MemorySessionStore::new();
+    store.save("expired", &HashMap::new(), Duration::seconds(-1));
+    store.save("alive", &HashMap::new(), Duration::hours(1));
+
+    assert_eq!(store.load("expired"), None);
+    assert_eq!(store.len(), 2);
+
+    store.sweep();
+    assert_eq!(store.len(), 1);
+    assert!(store.load("alive").is_some());
+}
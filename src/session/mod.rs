@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::rand::{OsRng, Rng};
+use std::sync::Arc;
+use std::time::Duration;
+
+use http::server::ResponseWriter;
+use request::Request;
+use response::{Response, ResponseHook};
+use middleware::{Continue, Middleware, MiddlewareResult};
+use cookies;
+use cookies::CookieOptions;
+use header_utils;
+
+pub use self::cookie_store::CookieSessionStore;
+pub use self::memory_store::MemorySessionStore;
+
+mod cookie_store;
+mod memory_store;
+
+/// The data of a session, mapping keys to values.
+pub type SessionData = HashMap<String, String>;
+
+/// A SessionStore persists the data of sessions between requests.
+///
+/// The `key` of a session is the value of the session cookie. Stores keeping the
+/// data on the server use the session id as key while other stores may choose to
+/// put the data itself into the key.
+pub trait SessionStore: Send + Sync {
+    /// Loads the data of the session with the given key. Unknown or expired
+    /// sessions yield `None`.
+    fn load(&self, key: &str) -> Option<SessionData>;
+
+    /// Persists the data of the session with the given key for the given time to
+    /// live and returns the key to be sent to the client as the session cookie.
+    fn save(&self, key: &str, data: &SessionData, ttl: Duration) -> String;
+
+    /// Removes the session with the given key.
+    fn destroy(&self, key: &str);
+}
+
+impl<S: SessionStore> SessionStore for Arc<S> {
+    fn load(&self, key: &str) -> Option<SessionData> {
+        (**self).load(key)
+    }
+
+    fn save(&self, key: &str, data: &SessionData, ttl: Duration) -> String {
+        (**self).save(key, data, ttl)
+    }
+
+    fn destroy(&self, key: &str) {
+        (**self).destroy(key)
+    }
+}
+
+// Flash messages are stored in the session data with this key prefix
+static FLASH_PREFIX: &'static str = "_flash.";
+
+struct SessionState {
+    key: String,
+    data: SessionData,
+    // flash messages set during the previous request
+    flashes: HashMap<String, String>,
+    is_new: bool,
+    modified: bool,
+    destroyed: bool
+}
+
+/// The session of the current request. Changes are persisted right before the
+/// headers of the response get written, so they need to happen before anything
+/// is sent to the client.
+#[deriving(Clone)]
+pub struct Session {
+    state: Rc<RefCell<SessionState>>
+}
+
+impl Session {
+    fn new(key: String, data: SessionData, is_new: bool) -> Session {
+        // Flash messages only live until the next request, so they are moved
+        // out of the persisted data as soon as the session gets loaded.
+        let entries: Vec<(String, String)> = data.into_iter().collect();
+        let (flashes, data) = entries.partition(|&(ref key, _)| {
+            key.as_slice().starts_with(FLASH_PREFIX)
+        });
+        let flashes: HashMap<String, String> = flashes.into_iter().map(|(key, value)| {
+            (key.as_slice().slice_from(FLASH_PREFIX.len()).to_string(), value)
+        }).collect();
+        let modified = !flashes.is_empty();
+
+        Session {
+            state: Rc::new(RefCell::new(SessionState {
+                key: key,
+                data: data.into_iter().collect(),
+                flashes: flashes,
+                is_new: is_new,
+                modified: modified,
+                destroyed: false
+            }))
+        }
+    }
+
+    /// Returns the value stored under the given key.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.state.borrow().data.get(key).cloned()
+    }
+
+    /// Stores the value under the given key.
+    pub fn set(&self, key: &str, value: &str) {
+        let mut state = self.state.borrow_mut();
+        state.data.insert(key.to_string(), value.to_string());
+        state.modified = true;
+    }
+
+    /// Removes the value stored under the given key and returns it.
+    pub fn remove(&self, key: &str) -> Option<String> {
+        let mut state = self.state.borrow_mut();
+        let removed = state.data.remove(key);
+        if removed.is_some() {
+            state.modified = true;
+        }
+        removed
+    }
+
+    /// Removes all values from the session.
+    pub fn clear(&self) {
+        let mut state = self.state.borrow_mut();
+        state.data.clear();
+        state.modified = true;
+    }
+
+    /// Removes the session from the store and deletes the session cookie.
+    pub fn destroy(&self) {
+        self.state.borrow_mut().destroyed = true;
+    }
+
+    /// Stores a flash message which is available during the next request only,
+    /// e.g. to show an error after redirecting.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Request, Response, SessionAccess};
+    /// fn login(request: &Request, response: &mut Response) {
+    ///     request.session().flash("error", "Wrong password");
+    ///     response.send("Please try again");
+    /// }
+    ///
+    /// fn login_form(request: &Request, response: &mut Response) -> String {
+    ///     match request.session().get_flash("error") {
+    ///         Some(error) => format!("<p class=\"error\">{}</p>", error),
+    ///         None => "".to_string()
+    ///     }
+    /// }
+    /// ```
+    pub fn flash(&self, key: &str, message: &str) {
+        self.set(format!("{}{}", FLASH_PREFIX, key).as_slice(), message);
+    }
+
+    /// Returns the flash message stored under the given key during the
+    /// previous request.
+    pub fn get_flash(&self, key: &str) -> Option<String> {
+        self.state.borrow().flashes.get(key).cloned()
+    }
+
+    /// Returns all flash messages stored during the previous request,
+    /// e.g. to pass them to a template.
+    pub fn flashes(&self) -> HashMap<String, String> {
+        self.state.borrow().flashes.clone()
+    }
+
+    /// Returns whether the session was created during this request.
+    pub fn is_new(&self) -> bool {
+        self.state.borrow().is_new
+    }
+}
+
+/// Gives handlers access to the session attached by the `SessionMiddleware`.
+pub trait SessionAccess {
+    fn session(&self) -> Session;
+}
+
+impl<'a, 'b> SessionAccess for Request<'a, 'b> {
+    fn session(&self) -> Session {
+        self.map.get::<Session>()
+                .cloned()
+                .expect("Session not available. Ensure the session middleware \
+                         is added before the route that depends on it.")
+    }
+}
+
+/// A middleware which attaches a session to every request and persists it in
+/// the given `SessionStore`.
+///
+/// # Example
+/// ```{rust}
+/// use nickel::{Nickel, Request, Response, HttpRouter, SessionMiddleware, SessionAccess};
+/// use nickel::MemorySessionStore;
+/// use std::time::Duration;
+///
+/// fn counter(request: &Request, response: &mut Response) -> String {
+///     let session = request.session();
+///     let visits = session.get("visits")
+///                         .and_then(|v| from_str::<uint>(v.as_slice()))
+///                         .unwrap_or(0) + 1;
+///     session.set("visits", visits.to_string().as_slice());
+///     format!("You visited this page {} times", visits)
+/// }
+///
+/// let mut server = Nickel::new();
+/// server.utilize(SessionMiddleware::new(MemorySessionStore::new())
+///                    .cookie_name("sid")
+///                    .ttl(Duration::hours(1)));
+/// server.get("/", counter);
+/// ```
+pub struct SessionMiddleware<S> {
+    store: Arc<S>,
+    cookie_name: String,
+    ttl: Duration,
+    cookie_options: CookieOptions
+}
+
+impl<S: SessionStore> SessionMiddleware<S> {
+    /// Creates a session middleware persisting sessions in the given store.
+    /// By default the session cookie is called `nickel.sid` and sessions
+    /// live for a day.
+    pub fn new(store: S) -> SessionMiddleware<S> {
+        let mut cookie_options = CookieOptions::new();
+        cookie_options.path = Some("/".to_string());
+        cookie_options.http_only = true;
+
+        SessionMiddleware {
+            store: Arc::new(store),
+            cookie_name: "nickel.sid".to_string(),
+            ttl: Duration::days(1),
+            cookie_options: cookie_options
+        }
+    }
+
+    /// Sets the name of the session cookie.
+    pub fn cookie_name(mut self, name: &str) -> SessionMiddleware<S> {
+        self.cookie_name = name.to_string();
+        self
+    }
+
+    /// Sets the time to live of sessions.
+    pub fn ttl(mut self, ttl: Duration) -> SessionMiddleware<S> {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the attributes of the session cookie such as the path or domain.
+    /// The Max-Age attribute is always derived from the time to live.
+    pub fn cookie_options(mut self, options: CookieOptions) -> SessionMiddleware<S> {
+        self.cookie_options = options;
+        self
+    }
+}
+
+impl<S: SessionStore> Middleware for SessionMiddleware<S> {
+    fn invoke(&self, req: &mut Request, res: &mut Response) -> MiddlewareResult {
+        let existing = req.cookie(self.cookie_name.as_slice()).and_then(|key| {
+            self.store.load(key.as_slice()).map(|data| (key, data))
+        });
+
+        let session = match existing {
+            Some((key, data)) => Session::new(key, data, false),
+            None => Session::new(new_session_id(), HashMap::new(), true)
+        };
+
+        req.map.insert(session.clone());
+        res.on_send(PersistSession {
+            session: session,
+            store: self.store.clone(),
+            cookie_name: self.cookie_name.clone(),
+            ttl: self.ttl,
+            cookie_options: self.cookie_options.clone()
+        });
+
+        Ok(Continue)
+    }
+}
+
+struct PersistSession<S> {
+    session: Session,
+    store: Arc<S>,
+    cookie_name: String,
+    ttl: Duration,
+    cookie_options: CookieOptions
+}
+
+impl<S: SessionStore> ResponseHook for PersistSession<S> {
+    fn before_send(&mut self, res: &mut ResponseWriter) {
+        let state = self.session.state.borrow();
+
+        if state.destroyed {
+            if !state.is_new {
+                self.store.destroy(state.key.as_slice());
+            }
+            let mut options = self.cookie_options.clone();
+            options.max_age = Some(0);
+            let cookie = cookies::serialize(self.cookie_name.as_slice(), "", &options);
+            header_utils::append_set_cookie(&mut res.headers, cookie);
+            return
+        }
+
+        // Don't hand out cookies for sessions which never got used
+        if !state.modified || (state.is_new && state.data.is_empty()) {
+            return
+        }
+
+        let key = self.store.save(state.key.as_slice(), &state.data, self.ttl);
+        let mut options = self.cookie_options.clone();
+        options.max_age = Some(self.ttl.num_seconds());
+        let cookie = cookies::serialize(self.cookie_name.as_slice(), key.as_slice(), &options);
+        header_utils::append_set_cookie(&mut res.headers, cookie);
+    }
+}
+
+// Generates a random session id of 32 alphanumeric characters
+pub fn new_session_id() -> String {
+    let mut rng = OsRng::new().ok().expect("Failed to access the OS random number generator");
+    rng.gen_ascii_chars().take(32).collect()
+}
+
+#[test]
+fn tracks_modifications_of_the_session() {
+    let session = Session::new(new_session_id(), HashMap::new(), true);
+    assert!(!session.state.borrow().modified);
+    assert_eq!(session.remove("missing"), None);
+    assert!(!session.state.borrow().modified);
+
+    session.set("user", "john");
+    assert_eq!(session.get("user"), Some("john".to_string()));
+    assert!(session.state.borrow().modified);
+
+    session.clear();
+    assert_eq!(session.get("user"), None);
+}
+
+#[test]
+fn flash_messages_survive_one_request() {
+    let session = Session::new(new_session_id(), HashMap::new(), true);
+    session.flash("error", "bad password");
+    assert_eq!(session.get_flash("error"), None);
+
+    // the next request loads what got persisted
+    let persisted = session.state.borrow().data.clone();
+    let session = Session::new(new_session_id(), persisted, false);
+    assert_eq!(session.get_flash("error"), Some("bad password".to_string()));
+    assert!(session.state.borrow().modified);
+
+    // and the one after that doesn't see the message anymore
+    let persisted = session.state.borrow().data.clone();
+    let session = Session::new(new_session_id(), persisted, false);
+    assert_eq!(session.get_flash("error"), None);
+}
+
+#[test]
+fn generates_distinct_session_ids() {
+    let first = new_session_id();
+    let second = new_session_id();
+
+    assert_eq!(first.len(), 32);
+    assert!(first != second);
+}
@@ -1,20 +1,28 @@
-use std::path::BytesContainer;
-use std::io::{IoError, IoResult, FileNotFound};
+use std::io::{IoError, IoResult, FileNotFound, PermissionDenied, OtherIoError, TypeSymlink};
+use std::io::fs;
+use std::io::fs::PathExtensions;
 
-use http::server::request::AbsolutePath;
 use http::method::{Get, Head};
-use http::status::{ InternalServerError };
+use http::status::{ InternalServerError, Forbidden, BadRequest, MovedPermanently };
+use url::percent_encoding::{utf8_percent_encode, percent_decode, DEFAULT_ENCODE_SET};
 
 use request;
 use response;
+use response::escape_html;
 use middleware::{Halt, Continue, Middleware, MiddlewareResult};
 use nickel_error::{ NickelError, ErrorWithStatusCode };
 
 // this should be much simpler after unboxed closures land in Rust.
 
+// the same limit as Linux applies
+const MAX_SYMLINKS: uint = 40;
+
 #[deriving(Clone)]
 pub struct StaticFilesHandler {
-    root_path: Path
+    root_path: Path,
+    index_file: String,
+    listing: bool,
+    symlinks_outside_root: bool
 }
 
 impl Middleware for StaticFilesHandler {
@@ -22,15 +30,19 @@ impl Middleware for StaticFilesHandler {
                -> MiddlewareResult {
         match req.origin.method {
             Get | Head => {
-                match self.with_file(self.extract_path(req), res) {
-                    Ok(()) => Ok(Halt),
-                    Err(err) => match err.kind {
-                        // We shouldn't assume the StaticFileHandler to be the last middleware in the stack.
-                        // Therefore it's important to continue in case of FileNotFound errors.
-                        FileNotFound => Ok(Continue),
-                        _ => Err(NickelError::new(format!("Unknown Error ({})", err),
-                                                  ErrorWithStatusCode(InternalServerError)))
-                    }
+                let (url_path, query) = match self.extract_path(req) {
+                    Some(parts) => parts,
+                    None => return Ok(Continue)
+                };
+                let path = match self.resolve(url_path) {
+                    Ok(path) => path,
+                    Err(result) => return result
+                };
+
+                if path.is_dir() {
+                    self.with_directory(url_path, query, &path, res)
+                } else {
+                    self.with_file(&path, res)
                 }
             },
             _ => Ok(Continue)
@@ -41,7 +53,17 @@ impl Middleware for StaticFilesHandler {
 impl StaticFilesHandler {
     /// Create a new middleware to serve files from within a given root directory.
     /// The file to serve will be determined by combining the requested Url with
-    /// the provided root directory.
+    /// the provided root directory. The Content-Type of the response is derived
+    /// from the file extension, see `mimes::register_extension` for adding types.
+    ///
+    /// Requests for directories are answered with the `index.html` within
+    /// them, if there is one. Requests for files which don't exist are passed
+    /// on to the next middleware, which eventually results in a 404 if nothing
+    /// else handles them. Files which can't be read due to missing permissions
+    /// result in a 403.
+    ///
+    /// Requests for paths outside of the root directory, be it through `..`
+    /// segments or symlinks pointing outside of it, are rejected with a 403.
     ///
     ///
     /// # Example
@@ -53,29 +75,257 @@ impl StaticFilesHandler {
     /// ```
     pub fn new (root_path: &str) -> StaticFilesHandler {
         StaticFilesHandler {
-            root_path: Path::new(root_path)
+            root_path: Path::new(root_path),
+            index_file: "index.html".to_string(),
+            listing: false,
+            symlinks_outside_root: false
+        }
+    }
+
+    /// Sets the name of the file which is served for requests to a
+    /// directory, `index.html` by default.
+    pub fn index_file(mut self, name: &str) -> StaticFilesHandler {
+        self.index_file = name.to_string();
+        self
+    }
+
+    /// Enables HTML listings of the files in directories without an index
+    /// file. Hidden files, i.e. the ones starting with a dot, aren't listed.
+    ///
+    /// # Example
+    /// ```{rust}
+    /// use nickel::{Nickel, StaticFilesHandler};
+    /// let mut server = Nickel::new();
+    ///
+    /// server.utilize(StaticFilesHandler::new("/srv/downloads/").directory_listing(true));
+    /// ```
+    pub fn directory_listing(mut self, enabled: bool) -> StaticFilesHandler {
+        self.listing = enabled;
+        self
+    }
+
+    /// Allows serving files through symlinks which point outside of the
+    /// root directory. Such requests get a 403 by default.
+    pub fn allow_symlinks_outside_root(mut self, allowed: bool) -> StaticFilesHandler {
+        self.symlinks_outside_root = allowed;
+        self
+    }
+
+    // Maps the url path to a path within the root directory.
+    fn resolve(&self, url_path: &str) -> Result<Path, MiddlewareResult> {
+        let decoded = match String::from_utf8(percent_decode(url_path.as_bytes())) {
+            Ok(decoded) => decoded,
+            Err(_) => return Err(Err(NickelError::new("Invalid encoding of the path",
+                                                      ErrorWithStatusCode(BadRequest))))
+        };
+        if decoded.as_slice().split('/').any(|segment| segment == "..") ||
+           decoded.as_slice().contains_char('\0') {
+            return forbidden()
         }
+
+        // Path normalizes the joined path, so make sure it's still within the root
+        let path = self.root_path.join(decoded.as_slice().trim_left_chars('/'));
+        if !self.root_path.is_ancestor_of(&path) {
+            return forbidden()
+        }
+
+        if !self.symlinks_outside_root {
+            match (resolve_symlinks(&self.root_path), resolve_symlinks(&path)) {
+                (Ok(root), Ok(resolved)) => if !root.is_ancestor_of(&resolved) {
+                    return forbidden()
+                },
+                (Err(err), _) | (_, Err(err)) => {
+                    return Err(StaticFilesHandler::error_result(err))
+                }
+            }
+        }
+
+        Ok(path)
     }
 
-    fn extract_path<'a>(&self, req: &'a mut request::Request) -> Option<&'a str> {
-        match req.origin.request_uri {
-            AbsolutePath(ref path) => {
+    // Splits the path of the request from its query string, which isn't part
+    // of the file path. The query keeps its leading `?`, if there is one.
+    fn extract_path<'a>(&self, req: &'a mut request::Request) -> Option<(&'a str, &'a str)> {
+        match req.path() {
+            Some(path) => {
                 debug!("{} {}{}", req.origin.method, self.root_path.display(), path);
 
-                match path.as_slice() {
-                    "/" => Some("index.html"),
-                    path => Some(path.slice_from(1)),
+                match path.find('?') {
+                    Some(pos) => Some((path.slice_to(pos), path.slice_from(pos))),
+                    None => Some((path, ""))
                 }
             }
-            _ => None
+            None => None
+        }
+    }
+
+    fn with_directory(&self, url_path: &str, query: &str, dir: &Path,
+                      res: &mut response::Response) -> MiddlewareResult {
+        let index = dir.join(self.index_file.as_slice());
+        if !index.is_file() && !self.listing {
+            return Ok(Continue)
+        }
+
+        // relative links within the page need to resolve inside the directory
+        if !url_path.ends_with("/") {
+            res.redirect_with(MovedPermanently, format!("{}/{}", url_path, query).as_slice());
+            return Ok(Halt)
+        }
+
+        if index.is_file() {
+            return self.with_file(&index, res)
+        }
+
+        let mut entries = Vec::new();
+        match fs::readdir(dir) {
+            Ok(paths) => for path in paths.iter() {
+                match path.filename_str() {
+                    Some(name) if !name.starts_with(".") => {
+                        entries.push((name.to_string(), path.is_dir()))
+                    },
+                    _ => {}
+                }
+            },
+            Err(err) => return StaticFilesHandler::error_result(err)
+        }
+        entries.sort();
+
+        res.send(render_listing(url_path, entries.as_slice()));
+        Ok(Halt)
+    }
+
+    fn with_file(&self, path: &Path, res: &mut response::Response) -> MiddlewareResult {
+        // anything but a regular file is left to the next middleware
+        let result = if !path.is_file() {
+            Err(IoError {
+                kind: FileNotFound,
+                desc: "No file at the requested path",
+                detail: None
+            })
+        } else {
+            res.send_file(path)
+        };
+
+        match result {
+            Ok(()) => Ok(Halt),
+            Err(err) => StaticFilesHandler::error_result(err)
+        }
+    }
+
+    fn error_result(err: IoError) -> MiddlewareResult {
+        match err.kind {
+            // We shouldn't assume the StaticFileHandler to be the last middleware in the stack.
+            // Therefore it's important to continue in case of FileNotFound errors.
+            FileNotFound => Ok(Continue),
+            PermissionDenied => Err(NickelError::new("Forbidden",
+                                                     ErrorWithStatusCode(Forbidden))),
+            _ => Err(NickelError::new(format!("Unknown Error ({})", err),
+                                      ErrorWithStatusCode(InternalServerError)))
         }
     }
+}
+
+fn forbidden() -> Result<Path, MiddlewareResult> {
+    Err(Err(NickelError::new("Forbidden", ErrorWithStatusCode(Forbidden))))
+}
+
+// Resolves all symlinks within the path, like realpath(3) does. Relative
+// paths stay relative to the working directory.
+fn resolve_symlinks(path: &Path) -> IoResult<Path> {
+    let mut resolved = Path::new(if path.is_absolute() { "/" } else { "." });
+    let mut pending: Vec<Vec<u8>> = path.components().rev().map(|c| c.to_vec()).collect();
+    let mut links = 0u;
+
+    loop {
+        let component = match pending.pop() {
+            Some(component) => component,
+            None => return Ok(resolved)
+        };
+
+        let next = resolved.join(component);
+        if try!(next.lstat()).kind != TypeSymlink {
+            resolved = next;
+            continue
+        }
+
+        links += 1;
+        if links > MAX_SYMLINKS {
+            return Err(IoError {
+                kind: OtherIoError,
+                desc: "Too many levels of symbolic links",
+                detail: Some(format!("{}", path.display()))
+            })
+        }
 
-    fn with_file<T: BytesContainer>(&self, relative_path: Option<T>, res: &mut response::Response)
-                                    -> IoResult<()> {
-        match relative_path {
-            Some(path) => res.send_file(&self.root_path.join(path)),
-            None => Err(IoError::last_error())
+        let target = try!(fs::readlink(&next));
+        if target.is_absolute() {
+            resolved = Path::new("/");
         }
+        pending.extend(target.components().rev().map(|c| c.to_vec()));
+    }
+}
+
+// Renders the listing of a directory, with the entries given as pairs of
+// their name and whether they are a directory.
+fn render_listing(url_path: &str, entries: &[(String, bool)]) -> String {
+    let title = escape_html(url_path);
+    let mut html = format!("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+                            <title>Index of {}</title></head>\n\
+                            <body><h1>Index of {}</h1>\n<ul>\n", title, title);
+
+    if url_path != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
     }
+    for &(ref name, is_dir) in entries.iter() {
+        let suffix = if is_dir { "/" } else { "" };
+        html.push_str(format!("<li><a href=\"{}{}\">{}{}</a></li>\n",
+                              escape_html(utf8_percent_encode(name.as_slice(),
+                                                              DEFAULT_ENCODE_SET).as_slice()),
+                              suffix, escape_html(name.as_slice()), suffix).as_slice());
+    }
+
+    html.push_str("</ul></body></html>\n");
+    html
+}
+
+#[test]
+fn renders_directory_listings() {
+    let entries = vec![("docs".to_string(), true), ("a <b>.txt".to_string(), false)];
+    let html = render_listing("/files/", entries.as_slice());
+
+    assert!(html.as_slice().contains("<title>Index of /files/</title>"));
+    assert!(html.as_slice().contains("<li><a href=\"../\">../</a></li>"));
+    assert!(html.as_slice().contains("<li><a href=\"docs/\">docs/</a></li>"));
+    assert!(html.as_slice().contains("<li><a href=\"a%20%3Cb%3E.txt\">a &lt;b&gt;.txt</a></li>"));
+}
+
+#[test]
+fn rejects_paths_outside_of_the_root() {
+    let handler = StaticFilesHandler::new("examples/assets/");
+
+    assert!(handler.resolve("/../Cargo.toml").is_err());
+    assert!(handler.resolve("/%2e%2e/Cargo.toml").is_err());
+    assert!(handler.resolve("/nested/%2E%2E/%2E%2E/Cargo.toml").is_err());
+    assert!(handler.resolve("/foo%00.txt").is_err());
+    assert_eq!(handler.resolve("/template.tpl").ok(), Some(Path::new("examples/assets/template.tpl")));
+}
+
+#[test]
+fn rejects_symlinks_outside_of_the_root() {
+    use std::io::TempDir;
+
+    let dir = TempDir::new("nickel-static").unwrap();
+    let root = dir.path().join("public");
+    fs::mkdir(&root, ::std::io::USER_RWX).unwrap();
+    fs::File::create(&root.join("inside.txt")).unwrap();
+    fs::File::create(&dir.path().join("secret.txt")).unwrap();
+    fs::symlink(&Path::new("../secret.txt"), &root.join("escape.txt")).unwrap();
+    fs::symlink(&Path::new("inside.txt"), &root.join("alias.txt")).unwrap();
+
+    let handler = StaticFilesHandler::new(root.as_str().unwrap());
+    assert!(handler.resolve("/escape.txt").is_err());
+    assert!(handler.resolve("/alias.txt").is_ok());
+
+    let handler = handler.allow_symlinks_outside_root(true);
+    assert!(handler.resolve("/escape.txt").is_ok());
 }
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::io::{File, IoResult, IoError, OtherIoError};
+use std::io::fs::PathExtensions;
+use std::sync::{Arc, RWLock};
+use serialize::json::Json;
+use mustache;
+use mustache::Template;
+
+/// Compiled mustache templates along with the modification time of their
+/// file when they were compiled.
+pub type TemplateCache = RWLock<HashMap<&'static str, (Template, u64)>>;
+
+/// A TemplateEngine compiles the source of templates so they can be rendered
+/// with `Response::render_template`. Engines get registered per file extension
+/// with `Nickel::register_template_engine`.
+///
+/// # Example
+/// ```{rust}
+/// # extern crate nickel;
+/// # extern crate serialize;
+/// use nickel::{Nickel, TemplateEngine, CompiledTemplate};
+/// use serialize::json::Json;
+/// use std::io::IoResult;
+///
+/// // An engine which ignores the data and sends the template as is
+/// struct Verbatim;
+/// struct VerbatimTemplate(String);
+///
+/// impl TemplateEngine for Verbatim {
+///     fn compile(&self, source: &str)
+///                -> Result<Box<CompiledTemplate + Send + Sync>, String> {
+///         Ok(box VerbatimTemplate(source.to_string()) as Box<CompiledTemplate + Send + Sync>)
+///     }
+/// }
+///
+/// impl CompiledTemplate for VerbatimTemplate {
+///     fn render(&self, _data: &Json, writer: &mut Writer) -> IoResult<()> {
+///         let VerbatimTemplate(ref source) = *self;
+///         writer.write_str(source.as_slice())
+///     }
+/// }
+///
+/// # fn main() {
+/// let mut server = Nickel::new();
+/// server.register_template_engine("txt", Verbatim);
+/// # }
+/// ```
+pub trait TemplateEngine: Send + Sync {
+    fn compile(&self, source: &str) -> Result<Box<CompiledTemplate + Send + Sync>, String>;
+}
+
+/// A compiled template which can be rendered with a data map.
+pub trait CompiledTemplate {
+    fn render(&self, data: &Json, writer: &mut Writer) -> IoResult<()>;
+}
+
+/// The built-in engine, which is used for all templates without a
+/// registered engine.
+pub struct MustacheEngine;
+
+struct MustacheTemplate {
+    template: Template
+}
+
+impl TemplateEngine for MustacheEngine {
+    fn compile(&self, source: &str) -> Result<Box<CompiledTemplate + Send + Sync>, String> {
+        let template = MustacheTemplate { template: mustache::compile_str(source) };
+        Ok(box template as Box<CompiledTemplate + Send + Sync>)
+    }
+}
+
+impl CompiledTemplate for MustacheTemplate {
+    fn render(&self, data: &Json, writer: &mut Writer) -> IoResult<()> {
+        self.template.render(writer, data).map_err(|err| {
+            IoError {
+                kind: OtherIoError,
+                desc: "Failed to render template",
+                detail: Some(format!("{}", err))
+            }
+        })
+    }
+}
+
+/// Templates holds the registered template engines along with the caches for
+/// compiled templates.
+///
+/// Compiled templates are kept for the lifetime of the server. In development
+/// mode the modification time of a template file is checked on every use and
+/// the template gets recompiled when the file changed, so edits show up
+/// without a restart.
+pub struct Templates {
+    // compiled templates of `Response::render`
+    pub cache: TemplateCache,
+    engines: HashMap<String, Box<TemplateEngine + Send + Sync>>,
+    default_engine: MustacheEngine,
+    compiled: RWLock<HashMap<String, (Arc<Box<CompiledTemplate + Send + Sync>>, u64)>>,
+    reload: bool
+}
+
+impl Templates {
+    pub fn new() -> Templates {
+        Templates {
+            cache: RWLock::new(HashMap::new()),
+            engines: HashMap::new(),
+            default_engine: MustacheEngine,
+            compiled: RWLock::new(HashMap::new()),
+            reload: false
+        }
+    }
+
+    /// Enables or disables the check for changed template files.
+    pub fn set_reload(&mut self, enabled: bool) {
+        self.reload = enabled;
+    }
+
+    /// Returns true if the template at `path` has to be recompiled because
+    /// its file changed since it was compiled at `modified`. This is only
+    /// ever the case in development mode.
+    pub fn is_stale(&self, path: &str, modified: u64) -> bool {
+        self.reload && Templates::modified(path) != modified
+    }
+
+    /// The modification time of the file at `path`, or 0 if it can't be
+    /// determined.
+    pub fn modified(path: &str) -> u64 {
+        Path::new(path).stat().map(|stat| stat.modified).unwrap_or(0)
+    }
+
+    pub fn register<E: TemplateEngine>(&mut self, extension: &str, engine: E) {
+        let extension = extension.trim_left_chars('.').to_string();
+        self.engines.insert(extension, box engine as Box<TemplateEngine + Send + Sync>);
+    }
+
+    /// Returns the compiled template at the given path, compiling it with the
+    /// engine registered for its extension on first use.
+    pub fn get(&self, path: &str) -> Result<Arc<Box<CompiledTemplate + Send + Sync>>, String> {
+        // Fast path doesn't need writer lock
+        match self.compiled.read().get(path) {
+            Some(&(ref template, modified)) if !self.is_stale(path, modified) => {
+                return Ok(template.clone())
+            },
+            _ => {}
+        }
+
+        let modified = Templates::modified(path);
+        let file_path = Path::new(path);
+        let source = match File::open(&file_path).read_to_string() {
+            Ok(source) => source,
+            Err(err) => return Err(format!("Couldn't open the template file {}: {}", path, err))
+        };
+
+        let engine = match file_path.extension_str().and_then(|ext| self.engines.get(ext)) {
+            Some(engine) => &**engine,
+            None => &self.default_engine as &TemplateEngine
+        };
+        let template = Arc::new(try!(engine.compile(source.as_slice())));
+
+        // A concurrent request might have compiled the template meanwhile,
+        // but both results are equally good.
+        self.compiled.write().insert(path.to_string(), (template.clone(), modified));
+        Ok(template)
+    }
+}
+
+#[test]
+fn recompiles_changed_templates_in_reload_mode() {
+    use std::io::TempDir;
+    use std::io::fs;
+    use std::collections::TreeMap;
+    use serialize::json::Object;
+
+    fn render(templates: &Templates, path: &str) -> String {
+        let mut out = Vec::new();
+        templates.get(path).unwrap().render(&Object(TreeMap::new()), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    let dir = TempDir::new("nickel-templates").unwrap();
+    let file = dir.path().join("template.tpl");
+    let path = file.as_str().unwrap();
+
+    File::create(&file).write_str("first").unwrap();
+    let mut templates = Templates::new();
+    assert_eq!(render(&templates, path).as_slice(), "first");
+
+    // the file changes but the cached template is kept
+    File::create(&file).write_str("second").unwrap();
+    fs::change_file_times(&file, 0, 1000).unwrap();
+    assert_eq!(render(&templates, path).as_slice(), "first");
+
+    templates.set_reload(true);
+    assert_eq!(render(&templates, path).as_slice(), "second");
+}
@@ -23,3 +23,13 @@ fn parses_urlencoded_characters() {
     assert_eq!(map["message".to_string()],
                 vec!["hello world".to_string()]);
 }
+
+#[test]
+fn parses_plus_as_space() {
+    let map = parse("message=hello+world&name=John%2BDoe");
+
+    assert_eq!(map["message".to_string()],
+                vec!["hello world".to_string()]);
+    assert_eq!(map["name".to_string()],
+                vec!["John+Doe".to_string()]);
+}
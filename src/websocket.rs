@@ -0,0 +1,403 @@
+//! WebSocket support as described in RFC 6455.
+//!
+//! `WebSocket` implements the framing protocol on top of any stream, the
+//! handshake helpers check upgrade requests and compute the
+//! `Sec-WebSocket-Accept` value to answer them with.
+//!
+//! Route handlers accept an upgrade with `Response::websocket`. Once the
+//! middleware is done, the server sends the `101 Switching Protocols`
+//! response and hands the connection to a `WebSocketHandler`.
+//!
+//! # Example
+//! ```{rust}
+//! use nickel::{Nickel, Request, Response, HttpRouter, MiddlewareResult};
+//! use nickel::websocket::{WebSocket, WebSocketStream, Text};
+//!
+//! fn echo(mut socket: WebSocket<WebSocketStream>) {
+//!     loop {
+//!         match socket.read_message() {
+//!             Ok(Text(text)) => { let _ = socket.send_text(text.as_slice()); },
+//!             Ok(_) => {},
+//!             Err(_) => break
+//!         }
+//!     }
+//! }
+//!
+//! fn handler(request: &Request, response: &mut Response) -> MiddlewareResult {
+//!     response.websocket(request, echo)
+//! }
+//!
+//! let mut server = Nickel::new();
+//! server.get("/echo", handler);
+//! ```
+
+pub use self::Message::{Text, Binary, Ping, Pong, Close};
+
+use std::ascii::AsciiExt;
+use std::io::{IoResult, IoError, InvalidInput, BufferedStream};
+use std::io::net::tcp::TcpStream;
+use serialize::base64::{ToBase64, STANDARD};
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+
+use request::Request;
+use header_utils;
+
+const GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Messages are limited to 16MB, larger ones are rejected with an error.
+pub const MAX_MESSAGE_SIZE: uint = 16 * 1024 * 1024;
+
+/// A complete message received from or sent to the other side.
+#[deriving(Show, PartialEq, Clone)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// A close frame with the status code given by the other side, if any.
+    Close(Option<u16>)
+}
+
+/// Returns true if the request asks for an upgrade to the WebSocket protocol.
+pub fn is_upgrade_request(req: &Request) -> bool {
+    let headers = &req.origin.headers;
+    let has = |name: &str, token: &str| {
+        header_utils::find(headers, name).map_or(false, |value| {
+            value.as_slice().split(',').any(|part| part.trim().eq_ignore_ascii_case(token))
+        })
+    };
+
+    has("Upgrade", "websocket") &&
+        has("Connection", "upgrade") &&
+        has("Sec-WebSocket-Version", "13") &&
+        header_key(req).is_some()
+}
+
+/// The `Sec-WebSocket-Key` header of the request.
+pub fn header_key(req: &Request) -> Option<String> {
+    header_utils::find(&req.origin.headers, "Sec-WebSocket-Key")
+}
+
+/// Computes the `Sec-WebSocket-Accept` header value for the
+/// `Sec-WebSocket-Key` of an upgrade request.
+pub fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.input_str(key.trim());
+    sha1.input_str(GUID);
+
+    let mut digest = [0u8, ..20];
+    sha1.result(&mut digest);
+    digest.to_base64(STANDARD)
+}
+
+/// The connection of a request which was upgraded to the WebSocket protocol.
+pub type WebSocketStream = BufferedStream<TcpStream>;
+
+/// Takes over a connection upgraded with `Response::websocket`. It runs in
+/// the task of the connection, which stays busy until it returns, and the
+/// connection is closed afterwards.
+pub trait WebSocketHandler: Send {
+    fn handle(&self, socket: WebSocket<WebSocketStream>);
+}
+
+impl WebSocketHandler for fn(WebSocket<WebSocketStream>) {
+    fn handle(&self, socket: WebSocket<WebSocketStream>) {
+        (*self)(socket)
+    }
+}
+
+/// The server side of a WebSocket connection.
+pub struct WebSocket<S> {
+    stream: S,
+    closed: bool,
+    // the opcode and payload of a fragmented message read so far, which
+    // control frames in between the fragments must not lose
+    fragment: Option<(u8, Vec<u8>)>
+}
+
+impl<S: Reader + Writer> WebSocket<S> {
+    /// Wraps a stream on which the handshake was completed already.
+    pub fn new(stream: S) -> WebSocket<S> {
+        WebSocket { stream: stream, closed: false, fragment: None }
+    }
+
+    /// Reads the next message, joining fragmented messages. Pings are
+    /// answered automatically but still returned, so applications can
+    /// ignore them. Once a close frame was received, it gets answered and
+    /// `Close` is returned. Control frames may arrive between the fragments
+    /// of a message, which is returned once its last fragment arrived.
+    pub fn read_message(&mut self) -> IoResult<Message> {
+        loop {
+            let frame = try!(read_frame(&mut self.stream));
+
+            // RFC 6455 requires failing the connection for these
+            if !frame.masked {
+                return Err(protocol_error("Unmasked frame from the client"))
+            }
+            if frame.opcode & 0x8 != 0 && (!frame.fin || frame.payload.len() > 125) {
+                return Err(protocol_error("Fragmented or oversized control frame"))
+            }
+
+            match frame.opcode {
+                OP_PING => {
+                    try!(write_frame(&mut self.stream, OP_PONG, frame.payload.as_slice()));
+                    return Ok(Ping(frame.payload))
+                },
+                OP_PONG => return Ok(Pong(frame.payload)),
+                OP_CLOSE => {
+                    let code = if frame.payload.len() >= 2 {
+                        Some((frame.payload[0] as u16 << 8) | frame.payload[1] as u16)
+                    } else {
+                        None
+                    };
+                    if !self.closed {
+                        self.closed = true;
+                        try!(write_frame(&mut self.stream, OP_CLOSE, frame.payload.slice_to(
+                            if code.is_some() { 2 } else { 0 })));
+                    }
+                    return Ok(Close(code))
+                },
+                OP_CONTINUATION => match self.fragment {
+                    Some((_, ref mut payload)) => {
+                        if payload.len() + frame.payload.len() > MAX_MESSAGE_SIZE {
+                            return Err(protocol_error("Message too large"))
+                        }
+                        payload.push_all(frame.payload.as_slice())
+                    },
+                    None => return Err(protocol_error("Unexpected continuation frame"))
+                },
+                OP_TEXT | OP_BINARY => {
+                    if self.fragment.is_some() {
+                        return Err(protocol_error("Expected a continuation frame"))
+                    }
+                    self.fragment = Some((frame.opcode, frame.payload));
+                },
+                _ => return Err(protocol_error("Unknown opcode"))
+            }
+
+            if frame.fin {
+                let (opcode, payload) = self.fragment.take().unwrap();
+                return if opcode == OP_TEXT {
+                    String::from_utf8(payload).map(Text)
+                                              .map_err(|_| protocol_error("Invalid UTF-8 in text message"))
+                } else {
+                    Ok(Binary(payload))
+                }
+            }
+        }
+    }
+
+    /// Sends a text message.
+    pub fn send_text(&mut self, text: &str) -> IoResult<()> {
+        self.send(OP_TEXT, text.as_bytes())
+    }
+
+    /// Sends a binary message.
+    pub fn send_binary(&mut self, data: &[u8]) -> IoResult<()> {
+        self.send(OP_BINARY, data)
+    }
+
+    /// Sends a ping, which the other side answers with a pong.
+    pub fn ping(&mut self, data: &[u8]) -> IoResult<()> {
+        self.send(OP_PING, data)
+    }
+
+    /// Sends a pong, e.g. as an unsolicited heartbeat.
+    pub fn pong(&mut self, data: &[u8]) -> IoResult<()> {
+        self.send(OP_PONG, data)
+    }
+
+    /// Starts closing the connection with the given status code. The other
+    /// side confirms with a close frame, which `read_message` returns.
+    pub fn close(&mut self, code: u16) -> IoResult<()> {
+        self.closed = true;
+        self.send(OP_CLOSE, &[(code >> 8) as u8, code as u8])
+    }
+
+    /// Returns the wrapped stream.
+    pub fn unwrap(self) -> S {
+        self.stream
+    }
+
+    fn send(&mut self, opcode: u8, payload: &[u8]) -> IoResult<()> {
+        try!(write_frame(&mut self.stream, opcode, payload));
+        self.stream.flush()
+    }
+}
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    masked: bool,
+    payload: Vec<u8>
+}
+
+fn protocol_error(desc: &'static str) -> IoError {
+    IoError {
+        kind: InvalidInput,
+        desc: desc,
+        detail: None
+    }
+}
+
+fn read_frame<R: Reader>(reader: &mut R) -> IoResult<Frame> {
+    let first = try!(reader.read_u8());
+    let second = try!(reader.read_u8());
+
+    let len = match second & 0x7F {
+        126 => try!(reader.read_be_u16()) as u64,
+        127 => try!(reader.read_be_u64()),
+        len => len as u64
+    };
+    if len > MAX_MESSAGE_SIZE as u64 {
+        return Err(protocol_error("Message too large"))
+    }
+
+    // clients have to mask their frames, which `read_message` checks
+    let mask = if second & 0x80 != 0 {
+        Some(try!(reader.read_exact(4)))
+    } else {
+        None
+    };
+
+    let mut payload = try!(reader.read_exact(len as uint));
+    match mask {
+        Some(ref mask) => {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        },
+        None => {}
+    }
+
+    Ok(Frame {
+        fin: first & 0x80 != 0,
+        opcode: first & 0x0F,
+        masked: mask.is_some(),
+        payload: payload
+    })
+}
+
+// Writes a single unmasked frame, as servers must not mask their frames.
+fn write_frame<W: Writer>(writer: &mut W, opcode: u8, payload: &[u8]) -> IoResult<()> {
+    try!(writer.write_u8(0x80 | opcode));
+
+    let len = payload.len();
+    if len < 126 {
+        try!(writer.write_u8(len as u8));
+    } else if len <= 0xFFFF {
+        try!(writer.write_u8(126));
+        try!(writer.write_be_u16(len as u16));
+    } else {
+        try!(writer.write_u8(127));
+        try!(writer.write_be_u64(len as u64));
+    }
+
+    writer.write(payload)
+}
+
+#[test]
+fn computes_accept_key() {
+    // the example of RFC 6455, section 1.3
+    assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ==").as_slice(),
+               "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+}
+
+#[test]
+fn reads_masked_frames() {
+    use std::io::MemReader;
+
+    // a masked "Hello" from RFC 6455, section 5.7
+    let bytes = vec![0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+    let frame = read_frame(&mut MemReader::new(bytes)).unwrap();
+    assert!(frame.fin);
+    assert_eq!(frame.opcode, OP_TEXT);
+    assert_eq!(frame.payload.as_slice(), b"Hello");
+}
+
+#[test]
+fn writes_frames_with_extended_lengths() {
+    use std::io::{MemReader, MemWriter};
+
+    for &len in [5u, 200, 70000].iter() {
+        let payload = Vec::from_elem(len, 42u8);
+        let mut writer = MemWriter::new();
+        write_frame(&mut writer, OP_BINARY, payload.as_slice()).unwrap();
+
+        let frame = read_frame(&mut MemReader::new(writer.unwrap())).unwrap();
+        assert_eq!(frame.opcode, OP_BINARY);
+        assert_eq!(frame.payload, payload);
+    }
+}
+
+#[cfg(test)]
+struct TestStream {
+    input: ::std::io::MemReader,
+    output: ::std::io::MemWriter
+}
+
+#[cfg(test)]
+impl Reader for TestStream {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<uint> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl Writer for TestStream {
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.output.write(buf)
+    }
+}
+
+// A frame like clients send them, with a mask of zeros
+#[cfg(test)]
+fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![if fin { 0x80 | opcode } else { opcode }, 0x80 | payload.len() as u8, 0, 0, 0, 0];
+    frame.push_all(payload);
+    frame
+}
+
+#[test]
+fn reads_pings_between_fragments() {
+    use std::io::{MemReader, MemWriter};
+
+    let mut bytes = client_frame(false, OP_TEXT, b"Hel");
+    bytes.push_all(client_frame(true, OP_PING, b"hi").as_slice());
+    bytes.push_all(client_frame(true, OP_CONTINUATION, b"lo").as_slice());
+
+    let mut socket = WebSocket::new(TestStream { input: MemReader::new(bytes), output: MemWriter::new() });
+    assert_eq!(socket.read_message().unwrap(), Ping(b"hi".to_vec()));
+    assert_eq!(socket.read_message().unwrap(), Text("Hello".to_string()));
+
+    let pong = socket.unwrap().output.unwrap();
+    assert_eq!(pong.as_slice(), [0x80 | OP_PONG, 2, b'h', b'i'].as_slice());
+}
+
+#[test]
+fn rejects_invalid_frames() {
+    use std::io::{MemReader, MemWriter};
+
+    let read = |bytes: Vec<u8>| {
+        let stream = TestStream { input: MemReader::new(bytes), output: MemWriter::new() };
+        WebSocket::new(stream).read_message().map_err(|err| err.desc)
+    };
+
+    // unmasked
+    assert_eq!(read(vec![0x81, 0x02, b'h', b'i']), Err("Unmasked frame from the client"));
+    // a fragmented ping
+    assert_eq!(read(client_frame(false, OP_PING, b"hi")),
+               Err("Fragmented or oversized control frame"));
+    // a ping with a payload of 126 bytes
+    let mut bytes = vec![0x80 | OP_PING, 0x80 | 126, 0, 126, 0, 0, 0, 0];
+    bytes.push_all(Vec::from_elem(126, 0u8).as_slice());
+    assert_eq!(read(bytes), Err("Fragmented or oversized control frame"));
+}